@@ -0,0 +1,216 @@
+use crate::{board::*, events::*, keyboard::*, notation::*, pieces::*, position::*};
+use bevy::prelude::*;
+use std::{
+    io::Write,
+    process::{Child, Command, Stdio},
+};
+
+/// Where announcements go besides the on-screen text
+#[derive(Default)]
+pub struct AccessibilitySettings {
+    /// Print every announcement on its own line
+    pub stdout: bool,
+    /// A command that reads lines to speak from its stdin, like `spd-say -e`
+    pub speech_command: Option<String>,
+}
+
+/// A sentence describing the game for players who can't see the board
+pub struct Announcement {
+    pub text: String,
+}
+
+/// The speech command, kept running for the whole session
+#[derive(Default)]
+struct SpeechPipe {
+    child: Option<Child>,
+}
+impl SpeechPipe {
+    fn open(command: &str) -> Self {
+        let mut words = command.split_whitespace();
+        let child = words.next().and_then(|program| {
+            Command::new(program)
+                .args(words)
+                .stdin(Stdio::piped())
+                .spawn()
+                .map_err(|err| warn!("Could not start speech command {}: {}", command, err))
+                .ok()
+        });
+        Self { child }
+    }
+
+    fn say(&mut self, text: &str) {
+        let stdin = match self.child.as_mut().and_then(|child| child.stdin.as_mut()) {
+            Some(stdin) => stdin,
+            None => return,
+        };
+        if let Err(err) = writeln!(stdin, "{}", text).and_then(|_| stdin.flush()) {
+            warn!("Speech command stopped: {}", err);
+            self.child = None;
+        }
+    }
+}
+
+pub struct AccessibilityPlugin;
+impl Plugin for AccessibilityPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<AccessibilitySettings>();
+        let speech_pipe = app
+            .world()
+            .get_resource::<AccessibilitySettings>()
+            .and_then(|settings| settings.speech_command.as_deref())
+            .map_or_else(SpeechPipe::default, SpeechPipe::open);
+
+        app.insert_resource(speech_pipe)
+            .add_event::<Announcement>()
+            .add_system(announce_moves.system())
+            .add_system(answer_queries.system())
+            .add_system(output_announcements.system());
+    }
+}
+
+/// "White knight from g1 to f3, takes bishop"
+pub fn describe_move(event: &MoveMade) -> String {
+    let color = color_name(event.piece.color);
+    if event.castling {
+        let side = if event.san.starts_with("O-O-O") {
+            "queen"
+        } else {
+            "king"
+        };
+        return format!("{} castles {} side", color, side);
+    }
+    if event.dropped {
+        return format!(
+            "{} drops a {} on {}",
+            color,
+            piece_name(event.piece.piece_type),
+            square_name(event.from)
+        );
+    }
+
+    let moved = event
+        .promotion
+        .map_or(event.piece.piece_type, |_| PieceType::Pawn);
+    let mut text = format!(
+        "{} {} from {} to {}",
+        color,
+        piece_name(moved),
+        square_name(event.from),
+        square_name((event.piece.x, event.piece.y))
+    );
+    if let Some(captured) = event.captured {
+        text.push_str(&format!(", takes {}", piece_name(captured)));
+    }
+    if let Some(promotion) = event.promotion {
+        text.push_str(&format!(", becomes a {}", piece_name(promotion)));
+    }
+    text
+}
+
+/// "e4: white pawn" or "e4: empty"
+pub fn describe_square(position: &Position, square: (u8, u8)) -> String {
+    match position.piece_at(square) {
+        Some(piece) => format!(
+            "{}: {} {}",
+            square_name(square),
+            color_name(piece.color).to_lowercase(),
+            piece_name(piece.piece_type)
+        ),
+        None => format!("{}: empty", square_name(square)),
+    }
+}
+
+/// Every piece on the board, side by side, e.g. "White: king e1, rooks a1 h1. Black: ..."
+pub fn describe_position(position: &Position) -> String {
+    let sides: Vec<String> = [PieceColor::White, PieceColor::Black]
+        .iter()
+        .map(|&color| {
+            let groups: Vec<String> = PieceType::ALL
+                .iter()
+                .filter_map(|&piece_type| {
+                    let squares: Vec<String> = position
+                        .pieces()
+                        .into_iter()
+                        .filter(|piece| piece.color == color && piece.piece_type == piece_type)
+                        .map(|piece| square_name((piece.x, piece.y)))
+                        .collect();
+                    match squares.len() {
+                        0 => None,
+                        1 => Some(format!("{} {}", piece_name(piece_type), squares[0])),
+                        _ => Some(format!("{}s {}", piece_name(piece_type), squares.join(" "))),
+                    }
+                })
+                .collect();
+            format!("{}: {}", color_name(color), groups.join(", "))
+        })
+        .collect();
+
+    format!(
+        "{}. {} to move",
+        sides.join(". "),
+        color_name(position.turn)
+    )
+}
+
+fn announce_moves(
+    mut move_made_events: EventReader<MoveMade>,
+    mut check_given_events: EventReader<CheckGiven>,
+    mut game_ended_events: EventReader<GameEnded>,
+    mut announcements: EventWriter<Announcement>,
+) {
+    let check = check_given_events.iter().next().is_some();
+    let game_ended = game_ended_events.iter().last();
+
+    for event in move_made_events.iter() {
+        let mut text = describe_move(event);
+        match game_ended {
+            Some(game_ended) if game_ended.status == GameStatus::Checkmate => {
+                text.push_str(", checkmate")
+            }
+            _ if check => text.push_str(", check"),
+            _ => (),
+        }
+        announcements.send(Announcement { text });
+    }
+    if let Some(game_ended) = game_ended {
+        announcements.send(Announcement {
+            text: game_result_text(game_ended),
+        });
+    }
+}
+
+/// F1 describes the square under the keyboard cursor, or else the mouse, and F2 lists
+/// the whole position
+fn answer_queries(
+    keyboard_inputs: Res<Input<KeyCode>>,
+    position: Res<Position>,
+    keyboard_cursor: Res<KeyboardCursor>,
+    board_cursor: Res<BoardCursor>,
+    mut announcements: EventWriter<Announcement>,
+) {
+    if keyboard_inputs.just_pressed(KeyCode::F1) {
+        let text = match keyboard_cursor.square.or(board_cursor.square) {
+            Some(square) => describe_square(&position, square),
+            None => "No square under the cursor, use the arrow keys to pick one".to_string(),
+        };
+        announcements.send(Announcement { text });
+    }
+    if keyboard_inputs.just_pressed(KeyCode::F2) {
+        announcements.send(Announcement {
+            text: describe_position(&position),
+        });
+    }
+}
+
+fn output_announcements(
+    settings: Res<AccessibilitySettings>,
+    mut speech_pipe: ResMut<SpeechPipe>,
+    mut announcements: EventReader<Announcement>,
+) {
+    for announcement in announcements.iter() {
+        if settings.stdout {
+            println!("{}", announcement.text);
+        }
+        speech_pipe.say(&announcement.text);
+    }
+}
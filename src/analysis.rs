@@ -0,0 +1,277 @@
+use crate::{
+    board::*, engine::*, events::*, notation::*, opening_book::*, pieces::*, position::*,
+    tablebase::*,
+};
+use bevy::prelude::*;
+
+/// Instead of playing a game, the engine evaluates the position while moves are tried
+/// for either side
+#[derive(Default)]
+pub struct AnalysisMode {
+    pub active: bool,
+    /// Where the game stood when analysis started, to come back to
+    pub main_line: Option<Position>,
+}
+impl AnalysisMode {
+    /// Whether `color` may move now: on its turn, or at any time during analysis
+    pub fn may_move(&self, color: PieceColor, turn: &PlayerTurn) -> bool {
+        self.active || color == turn.color
+    }
+}
+
+/// The search running on the current position, with the position it was started for, and
+/// the move the opening book would play there
+#[derive(Default)]
+struct AnalysisEngine {
+    search: Option<(Position, BackgroundSearch)>,
+    book_move: Option<Move>,
+}
+
+struct EvaluationBar;
+
+struct EvaluationBarFill;
+
+struct AnalysisText;
+
+pub struct AnalysisPlugin;
+impl Plugin for AnalysisPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<AnalysisMode>()
+            .init_resource::<AnalysisEngine>()
+            .add_startup_system(init_evaluation_bar.system())
+            .add_startup_system(init_analysis_text.system())
+            .add_system(toggle_analysis.system())
+            .add_system(run_engine.system())
+            .add_system(evaluation_bar_update.system())
+            .add_system(analysis_text_update.system());
+    }
+}
+
+/// F3 starts and stops analysis, and Home goes back to the main line. Stopping also
+/// returns to where the game stood
+fn toggle_analysis(
+    keyboard_inputs: Res<Input<KeyCode>>,
+    position: Res<Position>,
+    mut analysis: ResMut<AnalysisMode>,
+    mut load_position_events: EventWriter<LoadPosition>,
+) {
+    if keyboard_inputs.just_pressed(KeyCode::F3) {
+        if analysis.active {
+            analysis.active = false;
+            if let Some(main_line) = analysis.main_line.take() {
+                load_position_events.send(LoadPosition {
+                    position: main_line,
+                });
+            }
+        } else {
+            analysis.active = true;
+            analysis.main_line = Some(position.clone());
+        }
+    }
+
+    if keyboard_inputs.just_pressed(KeyCode::Home) && analysis.active {
+        if let Some(main_line) = analysis.main_line.clone() {
+            if main_line != *position {
+                load_position_events.send(LoadPosition {
+                    position: main_line,
+                });
+            }
+        }
+    }
+}
+
+/// Restarts the search whenever the position changes, and stops it when analysis ends.
+/// The book is asked first, so a book move is shown as soon as there is one
+fn run_engine(
+    analysis: Res<AnalysisMode>,
+    position: Res<Position>,
+    book: Option<Res<OpeningBook>>,
+    tablebase: Option<Res<Tablebase>>,
+    mut engine: ResMut<AnalysisEngine>,
+) {
+    if !analysis.active {
+        if engine.search.is_some() {
+            engine.search = None;
+            engine.book_move = None;
+        }
+        return;
+    }
+
+    let searching = matches!(&engine.search, Some((searched, _)) if *searched == *position);
+    if !searching {
+        engine.book_move = book.and_then(|book| book.pick_move(&position));
+        let search = BackgroundSearch::start(&position, tablebase.as_deref().cloned());
+        engine.search = Some((position.clone(), search));
+    }
+}
+
+fn init_evaluation_bar(mut commands: Commands, mut color_materials: ResMut<Assets<ColorMaterial>>) {
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                display: Display::None,
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Px(10.),
+                    top: Val::Px(170.),
+                    ..Default::default()
+                },
+                size: Size::new(Val::Px(30.), Val::Px(600.)),
+                // Children stack from the bottom, so white's share grows upwards
+                flex_direction: FlexDirection::Column,
+                ..Default::default()
+            },
+            material: color_materials.add(Color::rgb(0.15, 0.15, 0.15).into()),
+            ..Default::default()
+        })
+        .insert(EvaluationBar)
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        size: Size::new(Val::Percent(100.), Val::Percent(50.)),
+                        ..Default::default()
+                    },
+                    material: color_materials.add(Color::rgb(0.9, 0.9, 0.9).into()),
+                    ..Default::default()
+                })
+                .insert(EvaluationBarFill);
+        });
+}
+
+/// White's share of the bar: a half for an even position, nearly all of it a few pawns up
+fn white_share(score: i32, turn: PieceColor) -> f32 {
+    let score = match turn {
+        PieceColor::White => score,
+        PieceColor::Black => -score,
+    };
+    if mate_in(score).is_some() || is_tablebase_result(score) {
+        return if score > 0 { 1. } else { 0. };
+    }
+    1. / (1. + 10f32.powf(-score as f32 / 400.))
+}
+
+fn evaluation_bar_update(
+    analysis: Res<AnalysisMode>,
+    engine: Res<AnalysisEngine>,
+    mut bar_query: Query<&mut Style, (With<EvaluationBar>, Without<EvaluationBarFill>)>,
+    mut fill_query: Query<&mut Style, With<EvaluationBarFill>>,
+) {
+    if analysis.is_changed() {
+        for mut style in bar_query.iter_mut() {
+            style.display = if analysis.active {
+                Display::Flex
+            } else {
+                Display::None
+            };
+        }
+    }
+
+    let share = match &engine.search {
+        Some((searched, search)) => match search.latest() {
+            Some(info) => white_share(info.score, searched.turn),
+            None => return,
+        },
+        None => return,
+    };
+    for mut style in fill_query.iter_mut() {
+        let height = Val::Percent(share * 100.);
+        if style.size.height != height {
+            style.size.height = height;
+        }
+    }
+}
+
+fn init_analysis_text(
+    mut commands: Commands,
+    asset_server: ResMut<AssetServer>,
+    mut color_materials: ResMut<Assets<ColorMaterial>>,
+) {
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    right: Val::Px(10.),
+                    bottom: Val::Px(10.),
+                    ..Default::default()
+                },
+                max_size: Size::new(Val::Px(700.), Val::Undefined),
+                ..Default::default()
+            },
+            material: color_materials.add(Color::NONE.into()),
+            ..Default::default()
+        })
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(TextBundle {
+                    text: Text::with_section(
+                        "Analysis: off (F3)".to_string(),
+                        TextStyle {
+                            font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                            font_size: 30.0,
+                            color: Color::rgb(0.8, 0.8, 0.8),
+                        },
+                        TextAlignment::default(),
+                    ),
+                    ..Default::default()
+                })
+                .insert(AnalysisText);
+        });
+}
+
+/// Shows the book move if there is one, then the depth, score and best line, e.g.
+/// "Depth 6  +0.35  1. e4 e5 2. Nf3"
+fn analysis_text_update(
+    analysis: Res<AnalysisMode>,
+    engine: Res<AnalysisEngine>,
+    mut shown: Local<Option<AnalysisInfo>>,
+    mut query: Query<&mut Text, With<AnalysisText>>,
+) {
+    let value = if !analysis.active {
+        if !analysis.is_changed() {
+            return;
+        }
+        *shown = None;
+        "Analysis: off (F3)".to_string()
+    } else {
+        let (searched, info) = match &engine.search {
+            Some((searched, search)) => (searched, search.latest()),
+            None => return,
+        };
+        if info == *shown && !analysis.is_changed() && !engine.is_changed() {
+            return;
+        }
+        *shown = info.clone();
+
+        let mut header = "Analysis (F3 to stop, Home for the main line)".to_string();
+        if let Some(mv) = engine.book_move {
+            header = format!("{}\nBook: {}", header, move_to_san(searched, mv));
+        }
+        match info {
+            Some(info) => format!(
+                "{}\nDepth {}  {}  {}",
+                header,
+                info.depth,
+                score_text(info.score, searched.turn),
+                line_to_san(searched, &info.pv)
+            ),
+            None if searched.status() != GameStatus::Ongoing => {
+                format!("{}\n{}", header, game_over_text(searched))
+            }
+            None => format!("{}\nThinking...", header),
+        }
+    };
+
+    for mut text in query.iter_mut() {
+        text.sections[0].value = value.clone();
+    }
+}
+
+fn game_over_text(position: &Position) -> String {
+    let status = position.status();
+    game_result_text(&GameEnded {
+        winner: status.winner(position.turn),
+        status,
+    })
+}
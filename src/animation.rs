@@ -0,0 +1,191 @@
+use crate::{events::*, pieces::*};
+use bevy::prelude::*;
+
+/// How fast and how showy piece movement is
+pub struct AnimationSettings {
+    /// Travel speed in squares per second
+    pub speed: f32,
+    /// Peak height of the hop made by knights and capturing pieces
+    pub arc_height: f32,
+    /// Seconds a captured piece takes to sink through the board
+    pub capture_duration: f32,
+    pub easing: Easing,
+}
+impl Default for AnimationSettings {
+    fn default() -> Self {
+        Self {
+            speed: 4.,
+            arc_height: 0.6,
+            capture_duration: 0.4,
+            easing: Easing::CubicInOut,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum Easing {
+    QuadIn,
+    CubicInOut,
+}
+impl Easing {
+    pub fn apply(self, t: f32) -> f32 {
+        match self {
+            Easing::QuadIn => t * t,
+            Easing::CubicInOut => {
+                if t < 0.5 {
+                    4. * t * t * t
+                } else {
+                    1. - (-2. * t + 2.).powi(3) / 2.
+                }
+            }
+        }
+    }
+}
+
+/// Sent when a piece lands on its square or a captured piece has disappeared. A move's
+/// sound waits for it, so it is heard as the piece lands, and so does a puzzle's reply
+pub struct AnimationFinished {
+    pub entity: Entity,
+}
+
+/// Replaces `Piece` on a piece that was taken, so it can sink out before being despawned
+#[derive(Default)]
+pub struct Captured {
+    elapsed: f32,
+}
+
+pub struct PieceTween {
+    start: Vec3,
+    end: Vec3,
+    arc_height: f32,
+    elapsed: f32,
+    duration: f32,
+    easing: Easing,
+}
+
+pub struct AnimationPlugin;
+impl Plugin for AnimationPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<AnimationSettings>()
+            .add_event::<AnimationFinished>()
+            // Runs after the game systems so moves made this frame start animating right away
+            .add_system_to_stage(CoreStage::PostUpdate, start_piece_tweens.system())
+            .add_system(mark_captured_pieces.system())
+            .add_system(tween_pieces.system())
+            .add_system(sink_captured_pieces.system());
+    }
+}
+
+fn square_translation(piece: &Piece) -> Vec3 {
+    Vec3::new(piece.x as f32, 0., piece.y as f32)
+}
+
+fn mark_captured_pieces(
+    mut commands: Commands,
+    mut piece_captured_events: EventReader<PieceCaptured>,
+) {
+    for event in piece_captured_events.iter() {
+        commands
+            .entity(event.entity)
+            .insert(Captured::default())
+            .remove::<PieceTween>();
+    }
+}
+
+fn start_piece_tweens(
+    mut commands: Commands,
+    settings: Res<AnimationSettings>,
+    mut move_made_events: EventReader<MoveMade>,
+    query: Query<(Entity, &Piece, &Transform), (Without<PieceTween>, Without<Dragged>)>,
+) {
+    let capturing_pieces: Vec<Entity> = move_made_events
+        .iter()
+        .filter(|event| event.captured.is_some())
+        .map(|event| event.entity)
+        .collect();
+
+    for (entity, piece, transform) in query.iter() {
+        let end = square_translation(piece);
+        let start = transform.translation;
+        if start == end {
+            continue;
+        }
+
+        // Knights jump over whatever is in the way, and captures hop onto their victim
+        let arc_height =
+            if piece.piece_type == PieceType::Knight || capturing_pieces.contains(&entity) {
+                settings.arc_height
+            } else {
+                0.
+            };
+
+        commands.entity(entity).insert(PieceTween {
+            start,
+            end,
+            arc_height,
+            elapsed: 0.,
+            duration: (end - start).length() / settings.speed,
+            easing: settings.easing,
+        });
+    }
+}
+
+fn tween_pieces(
+    mut commands: Commands,
+    time: Res<Time>,
+    settings: Res<AnimationSettings>,
+    mut animation_finished_events: EventWriter<AnimationFinished>,
+    mut query: Query<(Entity, &Piece, &mut Transform, &mut PieceTween), Without<Dragged>>,
+) {
+    for (entity, piece, mut transform, mut tween) in query.iter_mut() {
+        // The piece got a new destination mid-flight, so head there from where it is now
+        let target = square_translation(piece);
+        if tween.end != target {
+            tween.start = transform.translation;
+            tween.end = target;
+            tween.elapsed = 0.;
+            tween.duration = (tween.end - tween.start).length() / settings.speed;
+        }
+
+        tween.elapsed += time.delta_seconds();
+        let t = if tween.duration > 0. {
+            (tween.elapsed / tween.duration).min(1.)
+        } else {
+            1.
+        };
+
+        if t >= 1. {
+            transform.translation = tween.end;
+            commands.entity(entity).remove::<PieceTween>();
+            animation_finished_events.send(AnimationFinished { entity });
+            continue;
+        }
+
+        let progress = tween.easing.apply(t);
+        transform.translation = tween.start.lerp(tween.end, progress)
+            + Vec3::new(0., tween.arc_height * 4. * progress * (1. - progress), 0.);
+    }
+}
+
+fn sink_captured_pieces(
+    mut commands: Commands,
+    time: Res<Time>,
+    settings: Res<AnimationSettings>,
+    mut animation_finished_events: EventWriter<AnimationFinished>,
+    mut query: Query<(Entity, &mut Transform, &mut Captured)>,
+) {
+    for (entity, mut transform, mut captured) in query.iter_mut() {
+        captured.elapsed += time.delta_seconds();
+        let t = (captured.elapsed / settings.capture_duration).min(1.);
+
+        if t >= 1. {
+            commands.entity(entity).despawn_recursive();
+            animation_finished_events.send(AnimationFinished { entity });
+            continue;
+        }
+
+        let progress = Easing::QuadIn.apply(t);
+        transform.translation.y = -progress;
+        transform.scale = Vec3::splat(1. - progress);
+    }
+}
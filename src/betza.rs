@@ -0,0 +1,247 @@
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+/// A single direction a piece can go in, with how far and what for. Offsets are
+/// (ranks, files) from white's side, so a positive rank is forwards
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Step {
+    pub offset: (i8, i8),
+    /// How many times the offset may be repeated, `u8::MAX` for as far as the board goes
+    pub range: u8,
+    pub moves: bool,
+    pub captures: bool,
+}
+
+const UNLIMITED: u8 = u8::MAX;
+
+/// The jump of an atom, given in one of its eight orientations
+fn atom_jump(atom: char) -> Option<(i8, i8)> {
+    match atom {
+        'W' => Some((1, 0)),
+        'F' => Some((1, 1)),
+        'D' => Some((2, 0)),
+        'N' => Some((2, 1)),
+        'A' => Some((2, 2)),
+        'H' => Some((3, 0)),
+        'C' => Some((3, 1)),
+        'Z' => Some((3, 2)),
+        'G' => Some((3, 3)),
+        _ => None,
+    }
+}
+
+/// Shorthands standing for other atoms, with the range they imply
+fn compound(atom: char) -> Option<(&'static [char], u8)> {
+    match atom {
+        'K' => Some((&['W', 'F'], 1)),
+        'Q' => Some((&['W', 'F'], UNLIMITED)),
+        'R' => Some((&['W'], UNLIMITED)),
+        'B' => Some((&['F'], UNLIMITED)),
+        _ => None,
+    }
+}
+
+/// All distinct orientations of a jump
+fn orientations((a, b): (i8, i8)) -> Vec<(i8, i8)> {
+    let mut offsets = Vec::new();
+    for &(x, y) in [(a, b), (b, a)].iter() {
+        for &(sx, sy) in [(1, 1), (1, -1), (-1, 1), (-1, -1)].iter() {
+            let offset = (x * sx, y * sy);
+            if !offsets.contains(&offset) {
+                offsets.push(offset);
+            }
+        }
+    }
+    offsets
+}
+
+fn in_direction((rank, file): (i8, i8), direction: char) -> bool {
+    match direction {
+        'f' => rank > 0,
+        'b' => rank < 0,
+        'l' => file < 0,
+        'r' => file > 0,
+        'v' => rank.abs() > file.abs(),
+        's' => file.abs() > rank.abs(),
+        _ => false,
+    }
+}
+
+/// Reads piece movement written in Betza's funny notation, e.g. `RN` for rook plus
+/// knight, into the steps it allows. Supported is the common subset: the atoms
+/// `W F D N A H C Z G`, the shorthands `K Q R B`, a range after the atom (`W3`, or `0`
+/// or a doubled atom for unlimited), the modes `m` (move only) and `c` (capture only)
+/// and the directions `f b l r v s`. Directions of one pair add up, so `fbW` steps
+/// forwards and backwards, and `v` and `s` pick the more vertical or more sideways jumps
+/// of an oblique atom like `N`. Directions that narrow each other down, as `flF` does to
+/// a single diagonal or `ffN` to the two most forward jumps, aren't supported and are
+/// rejected rather than read as something else
+pub fn parse(betza: &str) -> Result<Vec<Step>, String> {
+    let mut steps = Vec::new();
+    let mut chars = betza.chars().peekable();
+    let mut modes = String::new();
+    let mut directions = String::new();
+
+    while let Some(c) = chars.next() {
+        if "mc".contains(c) {
+            modes.push(c);
+            continue;
+        }
+        if "fblrvs".contains(c) {
+            directions.push(c);
+            continue;
+        }
+
+        let (atoms, mut range): (Vec<char>, u8) = match (atom_jump(c), compound(c)) {
+            (Some(_), _) => (vec![c], 1),
+            (None, Some((atoms, range))) => (atoms.to_vec(), range),
+            (None, None) => return Err(format!("Unknown atom {:?} in {:?}", c, betza)),
+        };
+        if chars.peek() == Some(&c) {
+            chars.next();
+            range = UNLIMITED;
+        }
+        let mut digits = String::new();
+        while let Some(&digit) = chars.peek().filter(|digit| digit.is_ascii_digit()) {
+            digits.push(digit);
+            chars.next();
+        }
+        if !digits.is_empty() {
+            range = match digits.parse::<u8>() {
+                Ok(0) => UNLIMITED,
+                Ok(range) => range,
+                Err(_) => return Err(format!("Bad range {:?} in {:?}", digits, betza)),
+            };
+        }
+
+        let pairs = ["fb", "lr", "vs"]
+            .iter()
+            .filter(|pair| directions.chars().any(|d| pair.contains(d)))
+            .count();
+        let doubled = directions
+            .char_indices()
+            .any(|(i, d)| directions[..i].contains(d));
+        if pairs > 1 || doubled {
+            return Err(format!(
+                "Combined directions {:?} in {:?} aren't supported",
+                directions, betza
+            ));
+        }
+
+        let moves = modes.is_empty() || modes.contains('m');
+        let captures = modes.is_empty() || modes.contains('c');
+        for atom in atoms {
+            let jump = atom_jump(atom).expect("compounds are made of atoms");
+            for offset in orientations(jump) {
+                if directions.is_empty() || directions.chars().any(|d| in_direction(offset, d)) {
+                    steps.push(Step {
+                        offset,
+                        range,
+                        moves,
+                        captures,
+                    });
+                }
+            }
+        }
+        modes.clear();
+        directions.clear();
+    }
+
+    if !modes.is_empty() || !directions.is_empty() {
+        return Err(format!("Modifiers without an atom in {:?}", betza));
+    }
+    Ok(steps)
+}
+
+thread_local! {
+    static PARSED: RefCell<HashMap<&'static str, Rc<[Step]>>> = RefCell::new(HashMap::new());
+}
+
+/// The steps of one of the built-in descriptions, parsed once per thread
+pub fn steps(betza: &'static str) -> Rc<[Step]> {
+    PARSED.with(|parsed| {
+        parsed
+            .borrow_mut()
+            .entry(betza)
+            .or_insert_with(|| {
+                parse(betza)
+                    .unwrap_or_else(|err| panic!("built-in piece has a bad description: {}", err))
+                    .into()
+            })
+            .clone()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn offsets(betza: &str) -> Vec<(i8, i8)> {
+        let mut offsets: Vec<_> = parse(betza).unwrap().iter().map(|s| s.offset).collect();
+        offsets.sort_unstable();
+        offsets
+    }
+
+    #[test]
+    fn atoms_and_ranges() {
+        let knight = parse("N").unwrap();
+        assert_eq!(knight.len(), 8);
+        assert!(knight
+            .iter()
+            .all(|step| step.range == 1 && step.moves && step.captures));
+
+        let rook = parse("R").unwrap();
+        assert_eq!(rook.len(), 4);
+        assert!(rook.iter().all(|step| step.range == UNLIMITED));
+        assert_eq!(parse("K").unwrap().len(), 8);
+        assert_eq!(parse("RN").unwrap().len(), 12);
+
+        assert_eq!(parse("W3").unwrap()[0].range, 3);
+        assert_eq!(parse("W0").unwrap()[0].range, UNLIMITED);
+        assert_eq!(parse("WW").unwrap(), parse("R").unwrap());
+    }
+
+    #[test]
+    fn modes_and_directions() {
+        let pawn = parse("fmWfcF").unwrap();
+        assert_eq!(
+            pawn,
+            [
+                Step {
+                    offset: (1, 0),
+                    range: 1,
+                    moves: true,
+                    captures: false,
+                },
+                Step {
+                    offset: (1, 1),
+                    range: 1,
+                    moves: false,
+                    captures: true,
+                },
+                Step {
+                    offset: (1, -1),
+                    range: 1,
+                    moves: false,
+                    captures: true,
+                },
+            ]
+        );
+
+        assert_eq!(offsets("fbW"), [(-1, 0), (1, 0)]);
+        assert_eq!(offsets("lrW"), [(0, -1), (0, 1)]);
+        assert_eq!(offsets("fN"), [(1, -2), (1, 2), (2, -1), (2, 1)]);
+        assert_eq!(offsets("vN"), [(-2, -1), (-2, 1), (2, -1), (2, 1)]);
+        assert_eq!(offsets("sN"), [(-1, -2), (-1, 2), (1, -2), (1, 2)]);
+    }
+
+    #[test]
+    fn rejected() {
+        assert!(parse("X").is_err());
+        assert!(parse("Nm").is_err());
+        assert!(parse("W300").is_err());
+        // Combined directions would mean fewer jumps than the union we could give
+        assert!(parse("flF").is_err());
+        assert!(parse("fsN").is_err());
+        assert!(parse("ffN").is_err());
+    }
+}
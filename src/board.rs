@@ -1,4 +1,7 @@
-use crate::pieces::*;
+use crate::{
+    analysis::*, animation::*, editor::*, events::*, keyboard::*, notation::*, piece_set::*,
+    pieces::*, position::*, puzzle::*, review::*, theme::*, view2d::*,
+};
 use bevy::{app::AppExit, prelude::*};
 use bevy_mod_picking::*;
 
@@ -8,7 +11,7 @@ pub struct Square {
 }
 
 impl Square {
-    fn is_white(&self) -> bool {
+    pub fn is_white(&self) -> bool {
         (self.x + self.y + 1) % 2 == 0
     }
 }
@@ -16,181 +19,602 @@ impl Square {
 pub struct BoardPlugin;
 impl Plugin for BoardPlugin {
     fn build(&self, app: &mut AppBuilder) {
-        app.init_resource::<SelectedSquare>()
+        app.init_resource::<Position>()
+            .init_resource::<SelectedSquare>()
             .init_resource::<SelectedPiece>()
+            .init_resource::<DraggedPiece>()
+            .init_resource::<SelectedDrop>()
             .init_resource::<PlayerTurn>()
-            .add_startup_system(create_board.system())
+            .init_resource::<BoardCursor>()
+            .add_system(create_board.system())
+            .add_system_to_stage(CoreStage::PreUpdate, pick_square.system())
             .add_system(color_squares.system())
-            .add_system(select_square.system());
+            .add_system(select_square.system())
+            .add_system(drag_piece.system())
+            .add_system(apply_moves.system())
+            .add_system(apply_drops.system())
+            .add_system(load_positions.system())
+            .add_system(exit_on_game_end.system());
     }
 }
 
 #[derive(Default)]
-struct SelectedSquare {
-    entity: Option<Entity>,
+pub struct SelectedSquare {
+    pub square: Option<(u8, u8)>,
+}
+
+/// Where the cursor points on the board, filled in by whichever view is active
+#[derive(Default)]
+pub struct BoardCursor {
+    pub square: Option<(u8, u8)>,
+    /// The cursor projected onto the board plane, in board coordinates
+    pub position: Option<Vec3>,
+}
+
+#[derive(Default)]
+pub struct SelectedPiece {
+    pub entity: Option<Entity>,
+}
+
+/// A piece picked from a pocket, to be dropped on the next square clicked
+#[derive(Default)]
+pub struct SelectedDrop {
+    pub piece: Option<(PieceColor, PieceType)>,
 }
 
 #[derive(Default)]
-struct SelectedPiece {
+struct DraggedPiece {
     entity: Option<Entity>,
 }
 
+/// How high a lifted piece floats above the board while being dragged
+const DRAG_HEIGHT: f32 = 0.3;
+
+/// How long the finished game stays on screen before the app closes
+const GAME_END_DELAY: f32 = 3.;
+
 pub struct PlayerTurn {
     pub color: PieceColor,
 }
-impl Default for PlayerTurn {
-    fn default() -> Self {
+/// Starts with whoever is to move in the `Position`, which need not be white
+impl FromWorld for PlayerTurn {
+    fn from_world(world: &mut World) -> Self {
         Self {
-            color: PieceColor::White,
+            color: world
+                .get_resource::<Position>()
+                .map_or(PieceColor::White, |position| position.turn),
         }
     }
 }
 
+/// Lays out the squares, and again whenever a position on a board of another size is
+/// loaded
 fn create_board(
     mut commands: Commands,
+    position: Res<Position>,
+    themes: Res<Themes>,
+    mut board_size: Local<(u8, u8)>,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
+    squares_query: Query<Entity, (With<Square>, With<PickableMesh>)>,
 ) {
+    if *board_size == (position.ranks(), position.files()) {
+        return;
+    }
+    *board_size = (position.ranks(), position.files());
+    for entity in squares_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+
     let mesh = meshes.add(Mesh::from(shape::Plane { size: 1. }));
-    // let white_material = materials.add(Color::rgb(1., 0.9, 0.9).into());
-    // let black_material = materials.add(Color::rgb(0., 0.1, 0.1).into());
-    for x in 0..8 {
-        for y in 0..8 {
+    for x in 0..position.ranks() {
+        for y in 0..position.files() {
+            let square = Square { x, y };
             commands
                 .spawn_bundle(PbrBundle {
                     mesh: mesh.clone(),
-                    // Change material according to position to get alternating pattern
-                    material: if (x + y + 1) % 2 == 0 {
-                        materials.add(Color::rgb(1., 0.9, 0.9).into())
-                    } else {
-                        materials.add(Color::rgb(0., 0.1, 0.1).into())
-                    },
+                    // Every square gets its own material so it can be highlighted on its own
+                    material: materials
+                        .add(themes.current().square_color(square.is_white()).into()),
                     transform: Transform::from_translation(Vec3::new(x as f32, 0., y as f32)),
                     ..Default::default()
                 })
                 .insert(PickableMesh::default())
-                .insert(Square { x, y });
+                .insert(square);
         }
     }
 }
 
+/// Fills in `BoardCursor` from the 3D picking camera
+fn pick_square(
+    view_mode: Res<ViewMode>,
+    mut board_cursor: ResMut<BoardCursor>,
+    picking_camera_query: Query<&PickingCamera>,
+    squares_query: Query<&Square>,
+) {
+    if *view_mode != ViewMode::ThreeD {
+        return;
+    }
+
+    let picking_camera = match picking_camera_query.iter().last() {
+        Some(picking_camera) => picking_camera,
+        None => {
+            *board_cursor = BoardCursor::default();
+            return;
+        }
+    };
+
+    board_cursor.square = picking_camera
+        .intersect_top()
+        .and_then(|(entity, _intersection)| squares_query.get(entity).ok())
+        .map(|square| (square.x, square.y));
+
+    // Project the cursor ray onto the board plane (y = 0)
+    board_cursor.position = picking_camera.ray().and_then(|ray| {
+        let (origin, direction) = (ray.origin(), ray.direction());
+        if direction.y.abs() < f32::EPSILON {
+            return None;
+        }
+        let distance = -origin.y / direction.y;
+        if distance < 0. {
+            return None;
+        }
+        Some(origin + direction * distance)
+    });
+}
+
 fn color_squares(
     selected_square: Res<SelectedSquare>,
+    board_cursor: Res<BoardCursor>,
+    keyboard_cursor: Res<KeyboardCursor>,
+    themes: Res<Themes>,
     mut materials: ResMut<Assets<StandardMaterial>>,
-    query: Query<(Entity, &Square, &Handle<StandardMaterial>)>,
-    picking_camera_query: Query<&PickingCamera>,
+    query: Query<(&Square, &Handle<StandardMaterial>)>,
 ) {
-    let top_entity = match picking_camera_query.iter().last() {
-        Some(picking_camera) => picking_camera
-            .intersect_top()
-            .map(|(entity, _intersection)| entity),
-        None => None,
-    };
+    let theme = themes.current();
 
-    for (entity, square, material_handle) in query.iter() {
-        let material = materials.get_mut(material_handle).unwrap();
-
-        material.base_color = if Some(entity) == top_entity {
-            Color::rgb(0.8, 0.3, 0.3)
-        } else if Some(entity) == selected_square.entity {
-            Color::rgb(0.9, 0.1, 0.1)
-        } else if square.is_white() {
-            Color::rgb(1., 0.9, 0.9)
-        } else {
-            Color::rgb(0., 0.1, 0.1)
-        };
+    for (square, material_handle) in query.iter() {
+        let color =
+            theme.square_highlight(square, &board_cursor, &keyboard_cursor, &selected_square);
+
+        // Only touch materials whose color actually changes, as every write re-uploads it
+        if materials
+            .get(material_handle)
+            .map(|material| material.base_color)
+            != Some(color)
+        {
+            if let Some(material) = materials.get_mut(material_handle) {
+                material.base_color = color;
+            }
+        }
     }
 }
 
 fn select_square(
     mut commands: Commands,
-    picking_camera_query: Query<&PickingCamera>,
+    board_cursor: Res<BoardCursor>,
     mouse_button_inputs: Res<Input<MouseButton>>,
     mut selected_square: ResMut<SelectedSquare>,
     mut selected_piece: ResMut<SelectedPiece>,
-    mut turn: ResMut<PlayerTurn>,
-    mut app_exit_events: EventWriter<AppExit>,
-    squares_query: Query<&Square>,
-    mut pieces_query: Query<(Entity, &mut Piece, &Children)>,
+    mut dragged_piece: ResMut<DraggedPiece>,
+    mut selected_drop: ResMut<SelectedDrop>,
+    turn: Res<PlayerTurn>,
+    analysis: Res<AnalysisMode>,
+    puzzles: Res<PuzzleMode>,
+    editor: Res<EditorMode>,
+    position: Res<Position>,
+    mut move_requested_events: EventWriter<MoveRequested>,
+    mut drop_requested_events: EventWriter<DropRequested>,
+    pieces_query: Query<(Entity, &Piece)>,
 ) {
-    if !mouse_button_inputs.just_pressed(MouseButton::Left) {
+    let pressed = mouse_button_inputs.just_pressed(MouseButton::Left);
+    let released = mouse_button_inputs.just_released(MouseButton::Left);
+    // While setting up a position, clicks place pieces instead
+    if (!pressed && !released) || editor.active {
         return;
     }
 
-    if let Some(picking_camera) = picking_camera_query.iter().last() {
-        if let Some((square_entity, _intersection)) = picking_camera.intersect_top() {
-            if let Ok(square) = squares_query.get(square_entity) {
-                selected_square.entity = Some(square_entity);
-
-                if let Some(selected_piece_entity) = selected_piece.entity {
-                    let pieces_entity_vec: Vec<(Entity, Piece, Vec<Entity>)> = pieces_query
-                        .iter_mut()
-                        .map(|(entity, piece, children)| {
-                            (entity, *piece, children.iter().copied().collect())
-                        })
-                        .collect();
-
-                    let pieces_vec = pieces_query
-                        .iter_mut()
-                        .map(|(_, piece, _)| *piece)
-                        .collect();
-
-                    if let Ok((_piece_entity, mut piece, _)) =
-                        pieces_query.get_mut(selected_piece_entity)
-                    {
-                        if piece.is_move_valid((square.x, square.y), pieces_vec) {
-                            for (other_entity, other_piece, other_children) in
-                                pieces_entity_vec.iter()
-                            {
-                                if other_piece.x == square.x
-                                    && other_piece.y == square.y
-                                    && other_piece.color != piece.color
-                                {
-                                    if other_piece.piece_type == PieceType::King {
-                                        println!(
-                                            "{} won.",
-                                            match turn.color {
-                                                PieceColor::White => "Black",
-                                                PieceColor::Black => "White",
-                                            }
-                                        );
-                                        app_exit_events.send(AppExit);
-                                    }
-                                    commands.entity(*other_entity).despawn();
-                                    for child in other_children.iter() {
-                                        commands.entity(*child).despawn();
-                                    }
-                                }
-                            }
-                            piece.x = square.x;
-                            piece.y = square.y;
-
-                            turn.color = match turn.color {
-                                PieceColor::White => PieceColor::Black,
-                                PieceColor::Black => PieceColor::White,
-                            }
-                        }
-                    }
+    let square = board_cursor.square;
+
+    if released {
+        // Dropping a lifted piece: releasing over its own square turns the drag into
+        // a click, so the piece stays selected and can still be moved click-click
+        if let Some(dragged_entity) = dragged_piece.entity.take() {
+            commands.entity(dragged_entity).remove::<Dragged>();
+
+            let origin = pieces_query
+                .get(dragged_entity)
+                .map(|(_, piece)| (piece.x, piece.y))
+                .ok();
+            match square {
+                Some(square) if origin != Some(square) => {
+                    move_requested_events.send(MoveRequested {
+                        entity: dragged_entity,
+                        to: square,
+                        promotion: None,
+                    });
                     selected_piece.entity = None;
-                } else {
-                    for (piece_entity, piece, _) in pieces_query.iter_mut() {
-                        if piece.x == square.x && piece.y == square.y && piece.color == turn.color {
-                            selected_piece.entity = Some(piece_entity);
-                            break;
-                        }
-                    }
                 }
+                Some(_) => (),
+                None => selected_piece.entity = None,
             }
-        } else {
-            selected_square.entity = None;
+        }
+        return;
+    }
+
+    selected_square.square = square;
+
+    let square = match square {
+        Some(square) => square,
+        None => {
             selected_piece.entity = None;
+            return;
+        }
+    };
+
+    // A piece picked from the pocket goes where the board is clicked next. Clicks off the
+    // board keep it, as picking it is one of them
+    if let Some((color, piece_type)) = selected_drop.piece.take() {
+        selected_piece.entity = None;
+        drop_requested_events.send(DropRequested {
+            color,
+            piece_type,
+            to: square,
+        });
+        return;
+    }
+
+    // Pressing on one of our own pieces lifts it, whether or not another one was selected
+    let own_piece_entity = pieces_query
+        .iter()
+        .find(|(_, piece)| {
+            (piece.x, piece.y) == square
+                && analysis.may_move(piece.color, &turn)
+                && puzzles.may_move(piece.color)
+        })
+        .map(|(entity, _)| entity);
+
+    // except that clicking the rook a selected king may castle with asks to castle
+    let castling_king = selected_piece.entity.filter(|&entity| {
+        pieces_query.get(entity).map_or(false, |(_, king)| {
+            king.piece_type == PieceType::King
+                && king.x == square.0
+                && position.castling[color_index(king.color)].contains(&Some(square.1))
+        })
+    });
+
+    if let (Some(king_entity), Some(_)) = (castling_king, own_piece_entity) {
+        selected_piece.entity = None;
+        move_requested_events.send(MoveRequested {
+            entity: king_entity,
+            to: square,
+            promotion: None,
+        });
+    } else if let Some(piece_entity) = own_piece_entity {
+        selected_piece.entity = Some(piece_entity);
+        dragged_piece.entity = Some(piece_entity);
+        commands
+            .entity(piece_entity)
+            .insert(Dragged)
+            .remove::<PieceTween>();
+    } else if let Some(selected_piece_entity) = selected_piece.entity.take() {
+        move_requested_events.send(MoveRequested {
+            entity: selected_piece_entity,
+            to: square,
+            promotion: None,
+        });
+    }
+}
+
+/// Plays requested moves that are legal in the current `Position`, mirroring them on
+/// the piece entities, and announces the outcome. Illegal moves leave the piece where it was
+fn apply_moves(
+    mut commands: Commands,
+    mut position: ResMut<Position>,
+    mut turn: ResMut<PlayerTurn>,
+    analysis: Res<AnalysisMode>,
+    mut move_requested_events: EventReader<MoveRequested>,
+    mut illegal_move_events: EventWriter<IllegalMoveAttempted>,
+    mut move_made_events: EventWriter<MoveMade>,
+    mut piece_captured_events: EventWriter<PieceCaptured>,
+    mut check_given_events: EventWriter<CheckGiven>,
+    mut turn_changed_events: EventWriter<TurnChanged>,
+    mut game_ended_events: EventWriter<GameEnded>,
+    mut pieces_query: Query<(Entity, &mut Piece)>,
+) {
+    for request in move_requested_events.iter() {
+        let piece = match pieces_query.get_mut(request.entity) {
+            Ok((_, piece)) => *piece,
+            Err(_) => continue,
+        };
+        let from = (piece.x, piece.y);
+
+        // Once the game is over nothing more is played, even though a variant's win or a
+        // draw by the fifty-move rule still leaves moves that would be legal
+        if position.status() != GameStatus::Ongoing {
+            illegal_move_events.send(IllegalMoveAttempted {
+                entity: request.entity,
+                piece,
+                to: request.to,
+            });
+            continue;
+        }
+
+        // During analysis either side may move, as long as that leaves no king in check
+        let mut next = position.clone();
+        if analysis.active && piece.color != next.turn && !next.is_in_check(next.turn) {
+            next.pass();
+        }
+
+        // A king may also castle by moving onto its own rook, which is the only way to ask
+        // for it in Chess960 when the king's target square is where it already stands.
+        // Moving onto the target square is read as a plain king move when both are legal
+        let mut candidates: Vec<Move> = next
+            .legal_moves()
+            .into_iter()
+            .filter(|mv| {
+                mv.from == from
+                    && (mv.to == request.to
+                        || next.castling_rook(*mv).map(|(rook_from, _)| rook_from)
+                            == Some(request.to))
+                    && mv.promotion
+                        == mv
+                            .promotion
+                            .map(|_| request.promotion.unwrap_or(PieceType::Queen))
+            })
+            .collect();
+        candidates.sort_by_key(|mv| mv.castling && mv.to != request.to);
+        let mv = candidates.first().copied();
+        let mv = match mv {
+            Some(mv) if piece.color == next.turn => mv,
+            _ => {
+                illegal_move_events.send(IllegalMoveAttempted {
+                    entity: request.entity,
+                    piece,
+                    to: request.to,
+                });
+                continue;
+            }
+        };
+
+        let san = move_to_san(&next, mv);
+        let captured_square = next.captured_square(mv);
+        let castling_rook = next.castling_rook(mv);
+        next.play(mv);
+        *position = next;
+
+        let mut victim = None;
+        let mut moved_piece = piece;
+        for (entity, mut other_piece) in pieces_query.iter_mut() {
+            let square = (other_piece.x, other_piece.y);
+            if entity == request.entity {
+                other_piece.x = mv.to.0;
+                other_piece.y = mv.to.1;
+                other_piece.piece_type = mv.promotion.unwrap_or(other_piece.piece_type);
+                moved_piece = *other_piece;
+            } else if Some(square) == captured_square && other_piece.color != piece.color {
+                victim = Some((entity, *other_piece));
+            } else if let Some((rook_from, rook_to)) = castling_rook {
+                if square == rook_from && other_piece.color == piece.color {
+                    other_piece.x = rook_to.0;
+                    other_piece.y = rook_to.1;
+                }
+            }
+        }
+
+        if let Some((victim_entity, victim_piece)) = victim {
+            commands.entity(victim_entity).remove::<Piece>();
+            piece_captured_events.send(PieceCaptured {
+                entity: victim_entity,
+                piece: victim_piece,
+                by: piece.color,
+            });
+        }
+        move_made_events.send(MoveMade {
+            entity: request.entity,
+            piece: moved_piece,
+            from,
+            captured: victim.map(|(_, victim_piece)| victim_piece.piece_type),
+            castling: mv.castling,
+            promotion: mv.promotion,
+            dropped: false,
+            san,
+        });
+
+        end_turn(
+            &position,
+            &mut turn,
+            &mut check_given_events,
+            &mut game_ended_events,
+            &mut turn_changed_events,
+        );
+    }
+}
+
+/// Plays requested drops that are legal in the current `Position`, spawning the dropped
+/// piece
+fn apply_drops(
+    mut commands: Commands,
+    mut position: ResMut<Position>,
+    mut turn: ResMut<PlayerTurn>,
+    analysis: Res<AnalysisMode>,
+    piece_set: Option<Res<PieceSet>>,
+    materials: Option<Res<PieceMaterials>>,
+    mut drop_requested_events: EventReader<DropRequested>,
+    mut move_made_events: EventWriter<MoveMade>,
+    mut check_given_events: EventWriter<CheckGiven>,
+    mut turn_changed_events: EventWriter<TurnChanged>,
+    mut game_ended_events: EventWriter<GameEnded>,
+) {
+    let (piece_set, materials) = match (piece_set, materials) {
+        (Some(piece_set), Some(materials)) => (piece_set, materials),
+        _ => return,
+    };
+
+    for request in drop_requested_events.iter() {
+        if position.status() != GameStatus::Ongoing {
+            info!("The game is over, no more pieces can be dropped");
+            continue;
+        }
+        let mut next = position.clone();
+        if analysis.active && request.color != next.turn && !next.is_in_check(next.turn) {
+            next.pass();
+        }
+
+        let mv = Move {
+            from: request.to,
+            to: request.to,
+            promotion: None,
+            castling: false,
+            drop: Some(request.piece_type),
+        };
+        if request.color != next.turn || !next.legal_moves().contains(&mv) {
+            info!(
+                "{} can't drop a {} on {}",
+                color_name(request.color),
+                piece_name(request.piece_type),
+                square_name(request.to)
+            );
+            continue;
+        }
+
+        let san = move_to_san(&next, mv);
+        next.play(mv);
+        *position = next;
+
+        let piece = Piece {
+            color: request.color,
+            piece_type: request.piece_type,
+            x: request.to.0,
+            y: request.to.1,
+        };
+        let entity = spawn_piece(&mut commands, &piece_set, materials.get(piece.color), piece);
+        move_made_events.send(MoveMade {
+            entity,
+            piece,
+            from: request.to,
+            captured: None,
+            castling: false,
+            promotion: None,
+            dropped: true,
+            san,
+        });
+
+        end_turn(
+            &position,
+            &mut turn,
+            &mut check_given_events,
+            &mut game_ended_events,
+            &mut turn_changed_events,
+        );
+    }
+}
+
+/// Hands the turn over after a move was played on `position`, announcing a check or the
+/// end of the game first
+fn end_turn(
+    position: &Position,
+    turn: &mut PlayerTurn,
+    check_given_events: &mut EventWriter<CheckGiven>,
+    game_ended_events: &mut EventWriter<GameEnded>,
+    turn_changed_events: &mut EventWriter<TurnChanged>,
+) {
+    let opponent = position.turn;
+    if position.is_in_check(opponent) {
+        check_given_events.send(CheckGiven { color: opponent });
+    }
+    match position.status() {
+        GameStatus::Ongoing => (),
+        status => game_ended_events.send(GameEnded {
+            winner: status.winner(position.turn),
+            status,
+        }),
+    }
+
+    turn.color = opponent;
+    turn_changed_events.send(TurnChanged { color: opponent });
+}
+
+/// Puts the board in the position of a `LoadPosition` event, respawning every piece
+fn load_positions(
+    mut commands: Commands,
+    mut position: ResMut<Position>,
+    mut turn: ResMut<PlayerTurn>,
+    mut selected_square: ResMut<SelectedSquare>,
+    mut selected_piece: ResMut<SelectedPiece>,
+    mut dragged_piece: ResMut<DraggedPiece>,
+    mut selected_drop: ResMut<SelectedDrop>,
+    piece_set: Option<Res<PieceSet>>,
+    materials: Option<Res<PieceMaterials>>,
+    mut load_position_events: EventReader<LoadPosition>,
+    mut turn_changed_events: EventWriter<TurnChanged>,
+    pieces_query: Query<Entity, With<Piece>>,
+) {
+    let event = match load_position_events.iter().last() {
+        Some(event) => event,
+        None => return,
+    };
+    let (piece_set, materials) = match (piece_set, materials) {
+        (Some(piece_set), Some(materials)) => (piece_set, materials),
+        _ => return,
+    };
+
+    for entity in pieces_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    for piece in event.position.pieces() {
+        spawn_piece(&mut commands, &piece_set, materials.get(piece.color), piece);
+    }
+
+    *position = event.position.clone();
+    selected_square.square = None;
+    selected_piece.entity = None;
+    selected_drop.piece = None;
+    dragged_piece.entity = None;
+    turn.color = position.turn;
+    turn_changed_events.send(TurnChanged {
+        color: position.turn,
+    });
+}
+
+/// Closes the app a while after the game ends, unless the end was reached while analysing
+/// or solving puzzles, or the game is reviewed
+fn exit_on_game_end(
+    time: Res<Time>,
+    analysis: Res<AnalysisMode>,
+    puzzles: Res<PuzzleMode>,
+    review_settings: Res<ReviewSettings>,
+    mut timer: Local<Option<Timer>>,
+    mut game_ended_events: EventReader<GameEnded>,
+    mut app_exit_events: EventWriter<AppExit>,
+) {
+    if game_ended_events.iter().next().is_some()
+        && timer.is_none()
+        && !analysis.active
+        && !puzzles.active
+        && !review_settings.enabled
+    {
+        *timer = Some(Timer::from_seconds(GAME_END_DELAY, false));
+    }
+
+    if let Some(timer) = timer.as_mut() {
+        if timer.tick(time.delta()).just_finished() {
+            app_exit_events.send(AppExit);
         }
     }
+}
 
-    selected_square.entity = match picking_camera_query.iter().last() {
-        Some(picking_camera) => picking_camera
-            .intersect_top()
-            .map(|(entity, _intersection)| entity),
-        None => None,
+/// Keeps the lifted piece under the cursor
+fn drag_piece(
+    board_cursor: Res<BoardCursor>,
+    mut dragged_query: Query<&mut Transform, With<Dragged>>,
+) {
+    let cursor = match board_cursor.position {
+        Some(cursor) => cursor,
+        None => return,
     };
+
+    for mut transform in dragged_query.iter_mut() {
+        transform.translation = Vec3::new(cursor.x, DRAG_HEIGHT, cursor.z);
+    }
 }
@@ -0,0 +1,138 @@
+use crate::{events::*, piece_set::*, pieces::*, position::*};
+use bevy::prelude::*;
+
+/// Pieces taken so far, grouped by the side that took them
+#[derive(Default)]
+pub struct CapturedPieces {
+    pub by_white: Vec<PieceType>,
+    pub by_black: Vec<PieceType>,
+}
+impl CapturedPieces {
+    pub fn record(&mut self, capturer: PieceColor, piece_type: PieceType) {
+        let pieces = match capturer {
+            PieceColor::White => &mut self.by_white,
+            PieceColor::Black => &mut self.by_black,
+        };
+        pieces.push(piece_type);
+        // Most valuable first, so the tray reads the same way as a scoresheet
+        pieces.sort_by_key(|piece_type| std::cmp::Reverse(piece_type.value()));
+    }
+
+    /// What is missing from a full set on either side, for positions that weren't played
+    /// out move by move. Promoted pieces can hide a missing pawn
+    pub fn missing_from(position: &Position) -> Self {
+        let full_set = Position::variant_start(position.variant).pieces();
+        let present = position.pieces();
+        let mut captured = Self::default();
+        for &color in [PieceColor::White, PieceColor::Black].iter() {
+            for &piece_type in PieceType::ALL.iter() {
+                let count = |pieces: &[Piece]| {
+                    pieces
+                        .iter()
+                        .filter(|piece| piece.color == color && piece.piece_type == piece_type)
+                        .count()
+                };
+                for _ in count(&present)..count(&full_set) {
+                    captured.record(color.opposite(), piece_type);
+                }
+            }
+        }
+        captured
+    }
+
+    /// Value of the pieces the side took, in centipawns
+    pub fn material(&self, capturer: PieceColor) -> i32 {
+        match capturer {
+            PieceColor::White => &self.by_white,
+            PieceColor::Black => &self.by_black,
+        }
+        .iter()
+        .map(PieceType::value)
+        .sum()
+    }
+
+    /// Material advantage of white over black, in whole pawns
+    pub fn balance(&self) -> i32 {
+        (self.material(PieceColor::White) - self.material(PieceColor::Black)) / 100
+    }
+}
+
+/// Marks the small piece models shown beside the board
+struct TrayPiece;
+
+const TRAY_SCALE: f32 = 0.5;
+const TRAY_SPACING: f32 = 0.45;
+
+pub struct CapturedPlugin;
+impl Plugin for CapturedPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<CapturedPieces>()
+            .add_system(record_captures.system())
+            .add_system(reset_captures.system())
+            .add_system(update_captured_tray.system());
+    }
+}
+
+fn record_captures(
+    mut captured: ResMut<CapturedPieces>,
+    mut piece_captured_events: EventReader<PieceCaptured>,
+) {
+    for event in piece_captured_events.iter() {
+        captured.record(event.by, event.piece.piece_type);
+    }
+}
+
+fn reset_captures(
+    mut captured: ResMut<CapturedPieces>,
+    mut load_position_events: EventReader<LoadPosition>,
+) {
+    if let Some(event) = load_position_events.iter().last() {
+        *captured = CapturedPieces::missing_from(&event.position);
+    }
+}
+
+fn update_captured_tray(
+    mut commands: Commands,
+    captured: Res<CapturedPieces>,
+    position: Res<Position>,
+    piece_set: Option<Res<PieceSet>>,
+    materials: Option<Res<PieceMaterials>>,
+    tray_query: Query<Entity, With<TrayPiece>>,
+) {
+    if !captured.is_changed() {
+        return;
+    }
+    let (piece_set, materials) = match (piece_set, materials) {
+        (Some(piece_set), Some(materials)) => (piece_set, materials),
+        _ => return,
+    };
+
+    for entity in tray_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+
+    // White's catch lines up along the left edge of the board, black's along the right
+    let right_edge = position.files() as f32;
+    for &(capturer, side) in [(PieceColor::White, -1.), (PieceColor::Black, right_edge)].iter() {
+        let (pieces, color) = match capturer {
+            PieceColor::White => (&captured.by_white, PieceColor::Black),
+            PieceColor::Black => (&captured.by_black, PieceColor::White),
+        };
+
+        for (i, &piece_type) in pieces.iter().enumerate() {
+            commands
+                .spawn_bundle(PbrBundle {
+                    transform: Transform {
+                        translation: Vec3::new(i as f32 * TRAY_SPACING - 0.25, 0., side),
+                        scale: Vec3::splat(TRAY_SCALE),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+                .insert(TrayPiece)
+                .with_children(|parent| {
+                    spawn_piece_model(parent, &piece_set, materials.get(color), piece_type);
+                });
+        }
+    }
+}
@@ -0,0 +1,236 @@
+use crate::{
+    eco::*, engine::*, events::*, game_tree::*, notation::*, opening_book::*, pieces::*,
+    position::*, tablebase::*, variant::*,
+};
+use std::{
+    io::{self, BufRead, Write},
+    sync::atomic::AtomicBool,
+};
+
+/// How deep `go` searches unless given a depth
+const GO_DEPTH: u32 = 6;
+
+const HELP: &str = "\
+Type a move in SAN (Nf3, O-O, e8=Q) or UCI (g1f3, e7e8q), or a drop (N@f3), or one of:
+  board        show the board
+  fen [FEN]    print the position as FEN, or set up the one given
+  pgn          print the game as PGN
+  undo         take back the last move
+  go [DEPTH]   let the engine play a move, from the book while there is one, else
+               searching and printing each finished depth
+  perft DEPTH  count the move sequences DEPTH plies deep, split by the first move
+  new          start the game again
+  help         show this
+  quit         leave";
+
+/// Plays in the terminal instead of a window, reading moves and commands from stdin so it
+/// can be scripted and run without a display. `ascii` draws the pieces as letters
+pub fn run(start: Position, book: Option<OpeningBook>, tablebase: Option<Tablebase>, ascii: bool) {
+    let eco = EcoTable::load().ok();
+    let mut tree = GameTree::new(start.clone());
+    println!("{}\n", HELP);
+    print_board(tree.position(tree.current()), ascii);
+
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        print!("> ");
+        io::stdout().flush().ok();
+        let line = match lines.next() {
+            Some(Ok(line)) => line,
+            _ => break,
+        };
+        let mut words = line.split_whitespace();
+        let command = match words.next() {
+            Some(command) => command,
+            None => continue,
+        };
+        let rest: Vec<&str> = words.collect();
+        let position = tree.position(tree.current()).clone();
+
+        match command {
+            "quit" | "exit" => break,
+            "help" => println!("{}", HELP),
+            "board" => print_board(&position, ascii),
+            "fen" if rest.is_empty() => println!("{}", position_to_fen(&position)),
+            "fen" => match parse_fen(&rest.join(" ")) {
+                Ok(mut new_start) => {
+                    new_start.variant = start.variant;
+                    match new_start.check_setup() {
+                        Ok(()) => {
+                            tree = GameTree::new(new_start);
+                            print_board(tree.position(tree.current()), ascii);
+                        }
+                        Err(err) => println!("Can't play from there: {}", err),
+                    }
+                }
+                Err(err) => println!("Not a FEN: {}", err),
+            },
+            "pgn" => {
+                let tags = eco.as_ref().map_or(Vec::new(), |eco| eco.pgn_tags(&tree));
+                println!("{}", tree.to_pgn_with_tags(&tags));
+            }
+            // The move is removed rather than kept as a variation, so the next one played
+            // continues the game's main line
+            "undo" => match tree.take_back() {
+                Some(parent) => print_board(tree.position(parent), ascii),
+                None => println!("Nothing to take back"),
+            },
+            "new" => {
+                tree = GameTree::new(tree.position(ROOT).clone());
+                print_board(tree.position(ROOT), ascii);
+            }
+            "go" => {
+                if position.status() != GameStatus::Ongoing {
+                    println!("The game is over");
+                    continue;
+                }
+                let depth = match rest.first().map(|depth| depth.parse::<u32>()) {
+                    Some(Ok(depth)) if depth > 0 => depth,
+                    None => GO_DEPTH,
+                    _ => {
+                        println!("The depth is a number of plies, like go 5");
+                        continue;
+                    }
+                };
+                if let Some(mv) = book.as_ref().and_then(|book| book.pick_move(&position)) {
+                    println!("bestmove {} (book)", move_to_uci(&position, mv));
+                    play(&mut tree, mv, &eco, ascii);
+                    continue;
+                }
+                let best = search(
+                    &position,
+                    depth.min(MAX_DEPTH),
+                    tablebase.as_ref(),
+                    &AtomicBool::new(false),
+                    |info| {
+                        println!(
+                            "depth {} score {} nodes {} pv {}",
+                            info.depth,
+                            score_text(info.score, position.turn),
+                            info.nodes,
+                            line_to_san(&position, &info.pv)
+                        )
+                    },
+                );
+                match best.and_then(|info| info.pv.first().copied()) {
+                    Some(mv) => {
+                        println!("bestmove {}", move_to_uci(&position, mv));
+                        play(&mut tree, mv, &eco, ascii);
+                    }
+                    None => println!("The game is over"),
+                }
+            }
+            "perft" => match rest.first().map(|depth| depth.parse::<u32>()) {
+                Some(Ok(depth)) if depth > 0 => {
+                    let mut total = 0;
+                    for mv in position.legal_moves() {
+                        let mut next = position.clone();
+                        next.play(mv);
+                        let count = next.perft(depth - 1);
+                        println!("{}: {}", move_to_uci(&position, mv), count);
+                        total += count;
+                    }
+                    println!("\nNodes searched: {}", total);
+                }
+                _ => println!("Give the depth in plies, like perft 4"),
+            },
+            _ => match parse_move(&position, line.trim()) {
+                // Variant wins and draws by the fifty-move rule leave moves that are legal
+                Ok(_) if position.status() != GameStatus::Ongoing => {
+                    println!("The game is over (new starts again, undo takes back a move)")
+                }
+                Ok(mv) => play(&mut tree, mv, &eco, ascii),
+                Err(err) => println!("{} (help lists the commands)", err),
+            },
+        }
+    }
+}
+
+fn play(tree: &mut GameTree, mv: Move, eco: &Option<EcoTable>, ascii: bool) {
+    let mut position = tree.position(tree.current()).clone();
+    let san = move_to_san(&position, mv);
+    position.play(mv);
+    let node = tree.add_move(mv, san, position);
+
+    println!("{}", tree.san(node));
+    if let Some(opening) = eco.as_ref().and_then(|eco| eco.classify(tree, node)) {
+        println!("{} {}", opening.eco, opening.name);
+    }
+    print_board(tree.position(node), ascii);
+}
+
+/// The board with white at the bottom, then the pockets if there are drops, and whose move
+/// it is or how the game ended
+fn print_board(position: &Position, ascii: bool) {
+    for rank in (0..position.ranks()).rev() {
+        let row: Vec<String> = (0..position.files())
+            .map(|file| match position.piece_at((rank, file)) {
+                Some(piece) => piece_symbol(piece.color, piece.piece_type, ascii).to_string(),
+                None if ascii => ".".to_string(),
+                None => "·".to_string(),
+            })
+            .collect();
+        println!("{:>2} {}", rank + 1, row.join(" "));
+    }
+    let files: Vec<String> = (0..position.files())
+        .map(|file| ((b'a' + file) as char).to_string())
+        .collect();
+    println!("   {}\n", files.join(" "));
+
+    if position.rules().has_drops() {
+        for &color in [PieceColor::White, PieceColor::Black].iter() {
+            let pocket = position.pockets[color_index(color)];
+            let pieces: String = Pocket::PIECE_TYPES
+                .iter()
+                .flat_map(|&piece_type| {
+                    let symbol = piece_symbol(color, piece_type, ascii);
+                    std::iter::repeat(symbol).take(pocket.count(piece_type) as usize)
+                })
+                .collect();
+            println!("{} pocket: {}", color_name(color), pieces);
+        }
+    }
+
+    match position.status() {
+        GameStatus::Ongoing if position.is_in_check(position.turn) => {
+            println!("{} to move (check)", color_name(position.turn))
+        }
+        GameStatus::Ongoing => println!("{} to move", color_name(position.turn)),
+        status => println!(
+            "{}",
+            game_result_text(&GameEnded {
+                winner: status.winner(position.turn),
+                status,
+            })
+        ),
+    }
+}
+
+/// Chess figurines, or the FEN letters when `ascii` is set. Pieces without a figurine
+/// always use their letter
+fn piece_symbol(color: PieceColor, piece_type: PieceType, ascii: bool) -> char {
+    let figurine = match (color, piece_type) {
+        _ if ascii => None,
+        (PieceColor::White, PieceType::King) => Some('♔'),
+        (PieceColor::White, PieceType::Queen) => Some('♕'),
+        (PieceColor::White, PieceType::Rook) => Some('♖'),
+        (PieceColor::White, PieceType::Bishop) => Some('♗'),
+        (PieceColor::White, PieceType::Knight) => Some('♘'),
+        (PieceColor::White, PieceType::Pawn) => Some('♙'),
+        (PieceColor::Black, PieceType::King) => Some('♚'),
+        (PieceColor::Black, PieceType::Queen) => Some('♛'),
+        (PieceColor::Black, PieceType::Rook) => Some('♜'),
+        (PieceColor::Black, PieceType::Bishop) => Some('♝'),
+        (PieceColor::Black, PieceType::Knight) => Some('♞'),
+        (PieceColor::Black, PieceType::Pawn) => Some('♟'),
+        _ => None,
+    };
+    figurine.unwrap_or_else(|| {
+        let letter = piece_letter(piece_type);
+        match color {
+            PieceColor::White => letter,
+            PieceColor::Black => letter.to_ascii_lowercase(),
+        }
+    })
+}
@@ -0,0 +1,646 @@
+use crate::{
+    analysis::*, eco::*, editor::*, events::*, game_tree::*, notation::*, pieces::*, position::*,
+    puzzle::*, review::*, view2d::*,
+};
+use bevy::prelude::*;
+use std::{
+    cmp::Reverse,
+    io::Write,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// How many matching games the browser lists at once
+const SHOWN_GAMES: usize = 15;
+
+/// Material signatures list the king first and then the other pieces from the most
+/// valuable down, e.g. `KRPvKR`
+fn material_order(piece_type: PieceType) -> (bool, Reverse<i32>) {
+    (piece_type != PieceType::King, Reverse(piece_type.value()))
+}
+
+pub struct DatabaseSettings {
+    /// The database itself, a PGN file that finished games are appended to
+    pub path: PathBuf,
+    /// A PGN file whose games are added to the database at startup
+    pub import: Option<PathBuf>,
+}
+impl Default for DatabaseSettings {
+    fn default() -> Self {
+        Self {
+            path: "games.pgn".into(),
+            import: None,
+        }
+    }
+}
+
+/// A stored game, with what it is searched by worked out once when it is read
+pub struct DatabaseGame {
+    pub tags: Vec<(String, String)>,
+    pgn: String,
+    /// Every position of the main line, as the board and side to move fields of its FEN
+    positions: Vec<String>,
+    /// The material of every position of the main line, e.g. `KQvKR`
+    materials: Vec<String>,
+}
+impl DatabaseGame {
+    fn new(pgn: String) -> Result<Self, String> {
+        let game = parse_pgn(&pgn)?;
+        let mut nodes = vec![ROOT];
+        nodes.extend(game.tree.line(ROOT));
+        let positions = nodes.iter().map(|&node| game.tree.position(node));
+        Ok(Self {
+            positions: positions.clone().map(fen_key).collect(),
+            materials: positions.map(material_signature).collect(),
+            tags: game.tags,
+            pgn,
+        })
+    }
+
+    pub fn tag(&self, name: &str) -> &str {
+        self.tags
+            .iter()
+            .find(|(tag, _)| tag == name)
+            .map_or("?", |(_, value)| value.as_str())
+    }
+
+    /// e.g. "Carlsen - Nakamura, 1-0, 2024.05.12, B90"
+    fn summary(&self) -> String {
+        let mut summary = format!(
+            "{} - {}, {}, {}",
+            self.tag("White"),
+            self.tag("Black"),
+            self.tag("Result"),
+            self.tag("Date")
+        );
+        for &name in ["ECO", "Opening"].iter() {
+            if self.tag(name) != "?" {
+                summary.push_str(&format!(", {}", self.tag(name)));
+            }
+        }
+        summary
+    }
+}
+
+/// The board and side to move of a FEN, which is what makes two positions the same here
+fn fen_key(position: &Position) -> String {
+    let fen = position_to_fen(position);
+    fen.split_whitespace().take(2).collect::<Vec<_>>().join(" ")
+}
+
+/// White's pieces, then black's, strongest first: `KRPvKR`
+fn material_signature(position: &Position) -> String {
+    let side = |color: PieceColor| -> String {
+        let pieces = position.pieces();
+        let mut piece_types: Vec<_> = pieces
+            .iter()
+            .filter(|piece| piece.color == color)
+            .map(|piece| piece.piece_type)
+            .collect();
+        piece_types.sort_by_key(|&piece_type| material_order(piece_type));
+        piece_types.into_iter().map(piece_letter).collect()
+    };
+    format!("{}v{}", side(PieceColor::White), side(PieceColor::Black))
+}
+
+/// A material signature typed in any piece order, put in the order signatures are kept in
+fn normalize_material(signature: &str) -> Option<String> {
+    let (white, black) = signature.split_at(signature.find(|c| c == 'v' || c == 'V')?);
+    let side = |letters: &str| -> Option<String> {
+        let mut pieces = letters
+            .chars()
+            .map(piece_from_letter)
+            .collect::<Option<Vec<_>>>()?;
+        pieces.sort_by_key(|&piece_type| material_order(piece_type));
+        Some(pieces.into_iter().map(piece_letter).collect())
+    };
+    Some(format!("{}v{}", side(white)?, side(&black[1..])?))
+}
+
+/// What to look for. Words are `key:value` pairs: `white:`, `black:`, `player:`, `date:`,
+/// `result:`, `eco:`, `material:` and `fen:`, which takes the rest of the query. Other
+/// words are looked for in either player's name
+#[derive(Default)]
+struct SearchQuery {
+    players: Vec<String>,
+    white: Option<String>,
+    black: Option<String>,
+    date: Option<String>,
+    result: Option<String>,
+    eco: Option<String>,
+    material: Option<String>,
+    fen: Option<String>,
+}
+impl SearchQuery {
+    fn parse(text: &str) -> Result<Self, String> {
+        let mut query = SearchQuery::default();
+        let (text, fen) = match text.find("fen:") {
+            Some(start) => (&text[..start], Some(text[start + 4..].trim())),
+            None => (text, None),
+        };
+        if let Some(fen) = fen.filter(|fen| !fen.is_empty()) {
+            // A board alone matches with either side to move
+            let fields: Vec<&str> = fen.split_whitespace().take(2).collect();
+            let position = parse_fen(&format!(
+                "{} {} - -",
+                fields[0],
+                fields.get(1).unwrap_or(&"w")
+            ))?;
+            let key = fen_key(&position);
+            query.fen = Some(if fields.len() < 2 {
+                key.split_whitespace().next().unwrap_or("").to_string()
+            } else {
+                key
+            });
+        }
+
+        for word in text.split_whitespace() {
+            let lower = word.to_lowercase();
+            let (key, value) = match lower.find(':') {
+                Some(colon) => (&lower[..colon], lower[colon + 1..].to_string()),
+                None => {
+                    query.players.push(lower.clone());
+                    continue;
+                }
+            };
+            match key {
+                "white" => query.white = Some(value),
+                "black" => query.black = Some(value),
+                "player" => query.players.push(value),
+                "date" => query.date = Some(value.replace(|c| c == '-' || c == '/', ".")),
+                "result" => {
+                    query.result = Some(match value.as_str() {
+                        "white" => "1-0".to_string(),
+                        "black" => "0-1".to_string(),
+                        "draw" | "½-½" => "1/2-1/2".to_string(),
+                        _ => value,
+                    })
+                }
+                "eco" => query.eco = Some(value),
+                "material" => {
+                    query.material = Some(
+                        normalize_material(&value)
+                            .ok_or_else(|| format!("Not a material signature: {}", value))?,
+                    )
+                }
+                _ => return Err(format!("Unknown search field: {}", key)),
+            }
+        }
+        Ok(query)
+    }
+
+    fn matches(&self, game: &DatabaseGame) -> bool {
+        let tag = |name: &str| game.tag(name).to_lowercase();
+        let (white, black) = (tag("White"), tag("Black"));
+        let allows = |filter: &Option<String>, test: &dyn Fn(&str) -> bool| {
+            filter.as_deref().map_or(true, test)
+        };
+        self.players
+            .iter()
+            .all(|player| white.contains(player) || black.contains(player))
+            && allows(&self.white, &|name| white.contains(name))
+            && allows(&self.black, &|name| black.contains(name))
+            && allows(&self.date, &|date| tag("Date").starts_with(date))
+            && allows(&self.result, &|result| tag("Result") == result)
+            && allows(&self.eco, &|eco| tag("ECO").starts_with(eco))
+            && allows(&self.material, &|material| {
+                game.materials.iter().any(|signature| signature == material)
+            })
+            && allows(&self.fen, &|fen| {
+                game.positions
+                    .iter()
+                    .any(|key| key == fen || key.split_whitespace().next() == Some(fen))
+            })
+    }
+}
+
+/// Every game in the database file, kept in memory for searching
+pub struct GameDatabase {
+    pub games: Vec<DatabaseGame>,
+}
+impl GameDatabase {
+    fn load(settings: &DatabaseSettings) -> Self {
+        let mut database = Self { games: Vec::new() };
+        match std::fs::read_to_string(&settings.path) {
+            Ok(text) => {
+                let skipped = database.read_games(&text);
+                if skipped > 0 {
+                    warn!(
+                        "Skipped {} broken games in {}",
+                        skipped,
+                        settings.path.display()
+                    );
+                }
+            }
+            Err(err) if settings.path.exists() => {
+                warn!("Could not read {}: {}", settings.path.display(), err)
+            }
+            Err(_) => (),
+        }
+        database
+    }
+
+    /// Loads the database and adds the games of the file to import, if any
+    fn open(settings: &DatabaseSettings) -> Self {
+        let mut database = Self::load(settings);
+        if let Some(import) = &settings.import {
+            let added = std::fs::read_to_string(import)
+                .map_err(|err| err.to_string())
+                .and_then(|text| database.add(settings, &text));
+            match added {
+                Ok((added, skipped)) => info!(
+                    "Imported {} games from {}, skipped {} broken ones",
+                    added,
+                    import.display(),
+                    skipped
+                ),
+                Err(err) => warn!("Could not import {}: {}", import.display(), err),
+            }
+        }
+        database
+    }
+
+    /// Adds the games of a PGN text that are readable, returning how many weren't
+    fn read_games(&mut self, text: &str) -> usize {
+        let mut skipped = 0;
+        for pgn in split_pgn(text) {
+            match DatabaseGame::new(pgn) {
+                Ok(game) => self.games.push(game),
+                Err(_) => skipped += 1,
+            }
+        }
+        skipped
+    }
+
+    /// Adds games to the database and appends them to its file, returning how many were
+    /// added and how many were skipped as unreadable. Games already in the database are
+    /// left out
+    fn add(&mut self, settings: &DatabaseSettings, text: &str) -> Result<(usize, usize), String> {
+        let mut new_games = Vec::new();
+        let mut skipped = 0;
+        for pgn in split_pgn(text) {
+            if self.games.iter().any(|game| game.pgn.trim() == pgn.trim()) {
+                continue;
+            }
+            match DatabaseGame::new(pgn) {
+                Ok(game) => new_games.push(game),
+                Err(_) => skipped += 1,
+            }
+        }
+
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&settings.path)
+            .map_err(|err| err.to_string())?;
+        for game in new_games.iter() {
+            writeln!(file, "{}", game.pgn.trim_end()).map_err(|err| err.to_string())?;
+            writeln!(file).map_err(|err| err.to_string())?;
+        }
+        let added = new_games.len();
+        self.games.extend(new_games);
+        Ok((added, skipped))
+    }
+}
+
+/// Today's date as PGN writes it, e.g. `2024.05.12`
+fn today() -> String {
+    let days = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs() / 86400) as i64;
+    // Days since 1970-01-01 to a civil date, counting in 400-year eras that start in March
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}.{:02}.{:02}", year, month, day)
+}
+
+/// The game browser and the search typed into it
+#[derive(Default)]
+pub struct DatabaseBrowser {
+    pub open: bool,
+    query: String,
+    /// Indexes of the games matching the query, newest first
+    results: Vec<usize>,
+    error: Option<String>,
+}
+impl DatabaseBrowser {
+    fn search(&mut self, database: &GameDatabase) {
+        match SearchQuery::parse(&self.query) {
+            Ok(query) => {
+                self.results = (0..database.games.len())
+                    .rev()
+                    .filter(|&index| query.matches(&database.games[index]))
+                    .collect();
+                self.error = None;
+            }
+            Err(err) => self.error = Some(err),
+        }
+    }
+}
+
+struct DatabaseButton {
+    game: usize,
+}
+
+struct DatabasePanel;
+
+pub struct DatabasePlugin;
+impl Plugin for DatabasePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<DatabaseSettings>();
+        let database = app
+            .world()
+            .get_resource::<DatabaseSettings>()
+            .map_or(GameDatabase { games: Vec::new() }, GameDatabase::open);
+        info!("{} games in the database", database.games.len());
+
+        app.insert_resource(database)
+            .init_resource::<DatabaseBrowser>()
+            .add_startup_system(init_database_panel.system())
+            .add_system(store_finished_games.system())
+            .add_system(toggle_browser.system())
+            .add_system(type_search.system())
+            .add_system(database_buttons.system())
+            .add_system(database_panel_update.system());
+    }
+}
+
+/// Adds every game finished on the board to the database, once the tree holds its last move
+fn store_finished_games(
+    settings: Res<DatabaseSettings>,
+    eco: Option<Res<EcoTable>>,
+    analysis: Res<AnalysisMode>,
+    puzzles: Res<PuzzleMode>,
+    tree: Res<GameTree>,
+    mut database: ResMut<GameDatabase>,
+    mut pending: Local<bool>,
+    mut game_ended_events: EventReader<GameEnded>,
+) {
+    if game_ended_events.iter().next().is_some() && !analysis.active && !puzzles.active {
+        *pending = true;
+    }
+    if !*pending || tree.position(tree.current()).status() == GameStatus::Ongoing {
+        return;
+    }
+    *pending = false;
+
+    let mut tags = eco.map_or(Vec::new(), |eco| eco.pgn_tags(&tree));
+    tags.push(("Date", today()));
+    let pgn = tree.to_pgn_with_tags(&tags);
+    match database.add(&settings, &pgn) {
+        Ok((_, 0)) => info!("Stored the game in {}", settings.path.display()),
+        Ok(_) => warn!("Could not store the game, as its PGN doesn't read back"),
+        Err(err) => warn!("Could not store the game: {}", err),
+    }
+}
+
+/// F7 opens and closes the game browser
+fn toggle_browser(
+    keyboard_inputs: Res<Input<KeyCode>>,
+    puzzles: Res<PuzzleMode>,
+    editor: Res<EditorMode>,
+    database: Res<GameDatabase>,
+    mut browser: ResMut<DatabaseBrowser>,
+) {
+    if !keyboard_inputs.just_pressed(KeyCode::F7) || puzzles.active || editor.active {
+        return;
+    }
+    browser.open = !browser.open;
+    if browser.open {
+        browser.search(&database);
+    }
+}
+
+/// While the browser is open, typing goes into the search instead of the move input
+fn type_search(
+    keyboard_inputs: Res<Input<KeyCode>>,
+    database: Res<GameDatabase>,
+    mut browser: ResMut<DatabaseBrowser>,
+    mut received_characters: EventReader<ReceivedCharacter>,
+) {
+    // Drained while closed too, so a move being typed doesn't end up in the search
+    let typed: String = received_characters
+        .iter()
+        .map(|event| event.char)
+        .filter(|c| !c.is_control())
+        .collect();
+    if !browser.open {
+        return;
+    }
+    let mut query = browser.query.clone();
+    query.push_str(&typed);
+    if keyboard_inputs.just_pressed(KeyCode::Back) {
+        query.pop();
+    }
+    if keyboard_inputs.just_pressed(KeyCode::Escape) {
+        query.clear();
+    }
+    if query != browser.query {
+        browser.query = query;
+        browser.search(&database);
+    }
+}
+
+/// Clicking a game puts it on the 3D board from its first move and reviews it
+fn database_buttons(
+    database: Res<GameDatabase>,
+    mut browser: ResMut<DatabaseBrowser>,
+    mut tree: ResMut<GameTree>,
+    mut review: ResMut<GameReview>,
+    mut view_mode: ResMut<ViewMode>,
+    mut load_position_events: EventWriter<LoadPosition>,
+    buttons_query: Query<(&Interaction, &DatabaseButton), Changed<Interaction>>,
+) {
+    for (interaction, button) in buttons_query.iter() {
+        if *interaction != Interaction::Clicked {
+            continue;
+        }
+        let game = match database
+            .games
+            .get(button.game)
+            .map(|game| parse_pgn(&game.pgn))
+        {
+            Some(Ok(game)) => game,
+            Some(Err(err)) => {
+                browser.error = Some(err);
+                continue;
+            }
+            None => continue,
+        };
+
+        *tree = game.tree;
+        load_position_events.send(LoadPosition {
+            position: tree.position(ROOT).clone(),
+        });
+        review.request();
+        *view_mode = ViewMode::ThreeD;
+        browser.open = false;
+    }
+}
+
+fn init_database_panel(mut commands: Commands, mut color_materials: ResMut<Assets<ColorMaterial>>) {
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                display: Display::None,
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    right: Val::Px(10.),
+                    top: Val::Px(250.),
+                    ..Default::default()
+                },
+                flex_direction: FlexDirection::ColumnReverse,
+                align_items: AlignItems::FlexEnd,
+                max_size: Size::new(Val::Px(800.), Val::Undefined),
+                ..Default::default()
+            },
+            material: color_materials.add(Color::rgb(0.1, 0.1, 0.1).into()),
+            ..Default::default()
+        })
+        .insert(DatabasePanel);
+}
+
+/// The search as typed, how many games match and a button for each of the newest ones
+fn database_panel_update(
+    mut commands: Commands,
+    browser: Res<DatabaseBrowser>,
+    database: Res<GameDatabase>,
+    asset_server: Res<AssetServer>,
+    mut color_materials: ResMut<Assets<ColorMaterial>>,
+    mut panel_query: Query<(Entity, &mut Style, Option<&Children>), With<DatabasePanel>>,
+) {
+    if !browser.is_changed() && !database.is_changed() {
+        return;
+    }
+    let (panel, mut style, children) = match panel_query.iter_mut().next() {
+        Some(panel) => panel,
+        None => return,
+    };
+    for &child in children.into_iter().flat_map(|children| children.iter()) {
+        commands.entity(child).despawn_recursive();
+    }
+    if !browser.open {
+        style.display = Display::None;
+        return;
+    }
+    style.display = Display::Flex;
+
+    let mut lines = vec![
+        "Games (F7 to close). Search by name or white: black: date: result: eco: material: fen:"
+            .to_string(),
+        format!("Search: {}_", browser.query),
+        match &browser.error {
+            Some(err) => err.clone(),
+            None => format!(
+                "{} of {} games match",
+                browser.results.len(),
+                database.games.len()
+            ),
+        },
+    ];
+    if browser.results.len() > SHOWN_GAMES {
+        lines.push(format!("Showing the newest {}", SHOWN_GAMES));
+    }
+
+    let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+    let button_material = color_materials.add(Color::rgb(0.25, 0.25, 0.25).into());
+    let text = |label: &str| TextBundle {
+        text: Text::with_section(
+            label,
+            TextStyle {
+                font: font.clone(),
+                font_size: 26.0,
+                color: Color::rgb(0.8, 0.8, 0.8),
+            },
+            TextAlignment::default(),
+        ),
+        ..Default::default()
+    };
+
+    commands.entity(panel).with_children(|parent| {
+        for line in lines.iter() {
+            parent.spawn_bundle(text(line));
+        }
+        for &game in browser.results.iter().take(SHOWN_GAMES) {
+            parent
+                .spawn_bundle(ButtonBundle {
+                    style: Style {
+                        margin: Rect::all(Val::Px(3.)),
+                        padding: Rect::all(Val::Px(4.)),
+                        ..Default::default()
+                    },
+                    material: button_material.clone(),
+                    ..Default::default()
+                })
+                .insert(DatabaseButton { game })
+                .with_children(|parent| {
+                    parent.spawn_bundle(text(&database.games[game].summary()));
+                });
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GAMES: &str = "[White \"Kasparov, Garry\"]\n[Black \"Topalov, Veselin\"]\n\
+        [Date \"1999.01.20\"]\n[Result \"1-0\"]\n\n1. e4 e5 2. Qh5 Nc6 3. Bc4 Nf6 4. Qxf7# 1-0\n\n\
+        [White \"Broken\"]\n\n1. e4 e5 2. Ke3 *\n\n\
+        [White \"Carlsen, Magnus\"]\n[Black \"Kasparov, Garry\"]\n[Result \"1/2-1/2\"]\n\n\
+        1. d4 d5 1/2-1/2\n";
+
+    #[test]
+    fn import_skips_broken_games() {
+        let path = std::env::temp_dir().join(format!("bevy_chess_test_{}.pgn", std::process::id()));
+        let settings = DatabaseSettings { path, import: None };
+        let mut database = GameDatabase { games: Vec::new() };
+
+        assert_eq!(database.add(&settings, GAMES), Ok((2, 1)));
+        // Games already there aren't added twice
+        assert_eq!(database.add(&settings, GAMES), Ok((0, 1)));
+
+        let mut reloaded = GameDatabase { games: Vec::new() };
+        let stored = std::fs::read_to_string(&settings.path).unwrap();
+        std::fs::remove_file(&settings.path).ok();
+        assert_eq!(reloaded.read_games(&stored), 0);
+        assert_eq!(reloaded.games.len(), 2);
+    }
+
+    #[test]
+    fn search() {
+        let mut database = GameDatabase { games: Vec::new() };
+        assert_eq!(database.read_games(GAMES), 1);
+        let found = |text: &str| {
+            let query = SearchQuery::parse(text).unwrap();
+            database
+                .games
+                .iter()
+                .filter(|game| query.matches(game))
+                .count()
+        };
+
+        assert_eq!(found("kasparov"), 2);
+        assert_eq!(found("white:kasparov"), 1);
+        assert_eq!(found("kasparov result:draw"), 1);
+        assert_eq!(found("date:1999-01"), 1);
+        assert_eq!(found("material:kqrrbbnnppppppppvkqrrbbnnppppppp"), 1);
+        assert_eq!(
+            found("fen:rnbqkbnr/pppppppp/8/8/3P4/8/PPP1PPPP/RNBQKBNR b"),
+            1
+        );
+        assert!(SearchQuery::parse("colour:white").is_err());
+    }
+}
@@ -0,0 +1,181 @@
+use crate::{game_tree::*, notation::*, position::*, variant::*};
+use bevy::{asset::FileAssetIo, prelude::*};
+use std::collections::HashMap;
+
+/// The ECO table: code, name and moves in PGN, tab separated under a header line. This is
+/// the layout of the lichess chess-openings files, so any of them can be used instead
+const ECO_FILE: &str = "assets/eco.tsv";
+
+#[derive(Clone, PartialEq)]
+pub struct Opening {
+    /// e.g. `B90`
+    pub eco: String,
+    /// e.g. `Sicilian Defense: Najdorf Variation`
+    pub name: String,
+}
+
+/// Openings by the position their moves lead to, so a game reaching one in another move
+/// order still gets its name
+pub struct EcoTable {
+    openings: HashMap<String, Opening>,
+}
+impl EcoTable {
+    pub fn load() -> Result<Self, String> {
+        let path = FileAssetIo::get_root_path().join(ECO_FILE);
+        let contents =
+            std::fs::read_to_string(&path).map_err(|err| format!("{}: {}", path.display(), err))?;
+        Ok(Self::parse(&contents))
+    }
+
+    fn parse(contents: &str) -> Self {
+        let mut openings = HashMap::new();
+        let mut skipped = 0;
+        for line in contents.lines().filter(|line| !line.trim().is_empty()) {
+            let fields: Vec<&str> = line.split('\t').collect();
+            let (eco, name, pgn) = match fields.as_slice() {
+                ["eco", ..] => continue,
+                [eco, name, pgn, ..] => (*eco, *name, *pgn),
+                _ => {
+                    skipped += 1;
+                    continue;
+                }
+            };
+            let tree = match parse_pgn(pgn) {
+                Ok(game) => game.tree,
+                Err(_) => {
+                    skipped += 1;
+                    continue;
+                }
+            };
+            let end = tree.line(ROOT).last().copied().unwrap_or(ROOT);
+            // Where several lines reach the same position, the first one names it
+            openings
+                .entry(opening_key(tree.position(end)))
+                .or_insert_with(|| Opening {
+                    eco: eco.to_string(),
+                    name: name.to_string(),
+                });
+        }
+        if skipped > 0 {
+            warn!("Skipped {} unreadable lines in {}", skipped, ECO_FILE);
+        }
+        Self { openings }
+    }
+
+    /// The opening of the last named position on the way to `node`. Only games of
+    /// standard chess from the usual start have one
+    pub fn classify(&self, tree: &GameTree, node: NodeId) -> Option<&Opening> {
+        let start = tree.position(ROOT);
+        if start.variant != VariantKind::Standard
+            || opening_key(start) != opening_key(&Position::start())
+        {
+            return None;
+        }
+        let mut node = Some(node);
+        while let Some(current) = node {
+            if let Some(opening) = self.openings.get(&opening_key(tree.position(current))) {
+                return Some(opening);
+            }
+            node = tree.parent(current);
+        }
+        None
+    }
+
+    /// The `ECO` and `Opening` tags for the main line of a game, if it has an opening
+    pub fn pgn_tags(&self, tree: &GameTree) -> Vec<(&'static str, String)> {
+        let end = tree.line(ROOT).last().copied().unwrap_or(ROOT);
+        match self.classify(tree, end) {
+            Some(opening) => vec![
+                ("ECO", opening.eco.clone()),
+                ("Opening", opening.name.clone()),
+            ],
+            None => Vec::new(),
+        }
+    }
+}
+
+/// The board, side to move and castling rights of a FEN. The en passant square is left out,
+/// since whether it is given differs between move orders
+fn opening_key(position: &Position) -> String {
+    let fen = position_to_fen(position);
+    fen.split_whitespace().take(3).collect::<Vec<_>>().join(" ")
+}
+
+/// The opening of the position on the board
+#[derive(Default)]
+pub struct CurrentOpening {
+    pub opening: Option<Opening>,
+}
+
+pub struct EcoPlugin;
+impl Plugin for EcoPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<CurrentOpening>();
+        match EcoTable::load() {
+            Ok(table) => {
+                info!("Loaded {} openings", table.openings.len());
+                app.insert_resource(table)
+                    .add_system(classify_opening.system());
+            }
+            Err(err) => info!("No opening names: {}", err),
+        }
+    }
+}
+
+fn classify_opening(
+    table: Res<EcoTable>,
+    tree: Res<GameTree>,
+    mut current: ResMut<CurrentOpening>,
+) {
+    if !tree.is_changed() {
+        return;
+    }
+    let opening = table.classify(&tree, tree.current()).cloned();
+    if current.opening != opening {
+        current.opening = opening;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TABLE: &str = "eco\tname\tpgn\n\
+        A40\tQueen's Pawn Game\t1. d4\n\
+        D02\tQueen's Pawn Game: Zukertort Variation\t1. d4 d5 2. Nf3\n";
+
+    /// The opening at the end of the game's main line
+    fn classify(table: &EcoTable, pgn: &str) -> Option<Opening> {
+        let tree = parse_pgn(pgn).unwrap().tree;
+        let end = tree.line(ROOT).last().copied().unwrap_or(ROOT);
+        table.classify(&tree, end).cloned()
+    }
+
+    #[test]
+    fn transposition() {
+        let table = EcoTable::parse(TABLE);
+        let opening = classify(&table, "1. Nf3 d5 2. d4 *").unwrap();
+        assert_eq!(opening.eco, "D02");
+        assert_eq!(opening.name, "Queen's Pawn Game: Zukertort Variation");
+
+        // Until the position is reached, the moves have no name
+        assert!(classify(&table, "1. Nf3 d5 *").is_none());
+        // Later moves keep the last name on the way
+        let later = classify(&table, "1. Nf3 d5 2. d4 Nf6 3. e3 *").unwrap();
+        assert_eq!(later.eco, "D02");
+    }
+
+    #[test]
+    fn only_standard_chess_from_the_start() {
+        let table = EcoTable::parse(TABLE);
+        assert!(classify(&table, "1. d4 d5 2. Nf3 *").is_some());
+
+        let chess960 = "[Variant \"Chess960\"]\n\
+            [FEN \"rnbkqbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBKQBNR w HAha - 0 1\"]\n\n\
+            1. d4 d5 2. Nf3 *";
+        assert!(classify(&table, chess960).is_none());
+        // King of the Hill starts from the same position as chess
+        let variant = "[Variant \"King of the Hill\"]\n\n1. d4 d5 2. Nf3 *";
+        assert!(classify(&table, variant).is_none());
+    }
+}
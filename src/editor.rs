@@ -0,0 +1,451 @@
+use crate::{board::*, events::*, game_tree::*, notation::*, pieces::*, position::*, puzzle::*};
+use bevy::prelude::*;
+use std::path::PathBuf;
+
+/// Where positions are imported from and exported to as FEN
+pub struct EditorSettings {
+    pub path: PathBuf,
+}
+impl Default for EditorSettings {
+    fn default() -> Self {
+        Self {
+            path: "position.fen".into(),
+        }
+    }
+}
+
+/// Setting up a position piece by piece, to play or analyse from once it is legal
+pub struct EditorMode {
+    pub active: bool,
+    position: Position,
+    /// What clicking the board puts there, `None` to clear squares
+    brush: Option<(PieceColor, PieceType)>,
+    /// The game put aside while editing, brought back unless play starts from the setup
+    saved_game: Option<GameTree>,
+    /// How the last import or export went
+    message: Option<String>,
+}
+impl Default for EditorMode {
+    fn default() -> Self {
+        Self {
+            active: false,
+            position: Position::empty(),
+            brush: Some((PieceColor::White, PieceType::Pawn)),
+            saved_game: None,
+            message: None,
+        }
+    }
+}
+
+enum EditorButton {
+    Brush(Option<(PieceColor, PieceType)>),
+    Turn,
+    Castling(PieceColor, usize),
+    EnPassant,
+    Clear,
+    Reset,
+    Import,
+    Export,
+    Play,
+}
+
+struct EditorPanel;
+
+pub struct EditorPlugin;
+impl Plugin for EditorPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<EditorSettings>()
+            .init_resource::<EditorMode>()
+            .add_startup_system(init_editor_panel.system())
+            .add_system(toggle_editor.system())
+            .add_system(edit_squares.system())
+            .add_system(editor_buttons.system())
+            .add_system(editor_panel_update.system());
+    }
+}
+
+/// F5 puts the game aside to set up a position, and brings it back
+fn toggle_editor(
+    keyboard_inputs: Res<Input<KeyCode>>,
+    position: Res<Position>,
+    puzzles: Res<PuzzleMode>,
+    mut editor: ResMut<EditorMode>,
+    mut tree: ResMut<GameTree>,
+    mut load_position_events: EventWriter<LoadPosition>,
+) {
+    if !keyboard_inputs.just_pressed(KeyCode::F5) || puzzles.active {
+        return;
+    }
+
+    if editor.active {
+        editor.active = false;
+        // As when leaving puzzles, the tree goes back first so the game carries on
+        if let Some(game) = editor.saved_game.take() {
+            let position = game.position(game.current()).clone();
+            *tree = game;
+            load_position_events.send(LoadPosition { position });
+        }
+    } else {
+        editor.active = true;
+        editor.saved_game = Some(tree.clone());
+        editor.position = position.clone();
+        editor.message = None;
+    }
+}
+
+/// A left click puts the brush's piece on the square, or takes it off again when it is
+/// already there. A right click always clears the square
+fn edit_squares(
+    mouse_button_inputs: Res<Input<MouseButton>>,
+    board_cursor: Res<BoardCursor>,
+    mut editor: ResMut<EditorMode>,
+    mut load_position_events: EventWriter<LoadPosition>,
+) {
+    if !editor.active {
+        return;
+    }
+    let square = match board_cursor.square {
+        Some(square) => square,
+        None => return,
+    };
+
+    let piece = if mouse_button_inputs.just_pressed(MouseButton::Left) {
+        let standing = editor
+            .position
+            .piece_at(square)
+            .map(|piece| (piece.color, piece.piece_type));
+        if standing == editor.brush {
+            None
+        } else {
+            editor.brush
+        }
+    } else if mouse_button_inputs.just_pressed(MouseButton::Right) {
+        None
+    } else {
+        return;
+    };
+
+    editor.position.set(square, piece);
+    editor.position.set_promoted(square, false);
+    show_edit(&mut editor, &mut load_position_events);
+}
+
+/// Puts the edited position on the board, dropping castling and en passant rights the
+/// pieces no longer allow
+fn show_edit(editor: &mut EditorMode, load_position_events: &mut EventWriter<LoadPosition>) {
+    let position = &mut editor.position;
+    for &color in [PieceColor::White, PieceColor::Black].iter() {
+        for &side in [KING_SIDE, QUEEN_SIDE].iter() {
+            if let Some(rook_file) = position.castling[color_index(color)][side] {
+                if !position.may_castle_with(color, side, rook_file) {
+                    position.castling[color_index(color)][side] = None;
+                }
+            }
+        }
+    }
+    if let Some(square) = position.en_passant {
+        if !position.en_passant_squares().contains(&square) {
+            position.en_passant = None;
+        }
+    }
+
+    load_position_events.send(LoadPosition {
+        position: editor.position.clone(),
+    });
+}
+
+/// The file of the outermost rook `color` could castle with on `side`
+fn castling_rook_file(position: &Position, color: PieceColor, side: usize) -> Option<u8> {
+    let files: Vec<u8> = if side == KING_SIDE {
+        (0..position.files()).rev().collect()
+    } else {
+        (0..position.files()).collect()
+    };
+    files
+        .into_iter()
+        .find(|&file| position.may_castle_with(color, side, file))
+}
+
+fn editor_buttons(
+    settings: Res<EditorSettings>,
+    mut editor: ResMut<EditorMode>,
+    mut load_position_events: EventWriter<LoadPosition>,
+    buttons_query: Query<(&Interaction, &EditorButton), Changed<Interaction>>,
+) {
+    for (interaction, button) in buttons_query.iter() {
+        if *interaction != Interaction::Clicked || !editor.active {
+            continue;
+        }
+        // Borrowed once per click, so the panel is only rebuilt when something was clicked
+        let editor = &mut *editor;
+        editor.message = None;
+        let position = &mut editor.position;
+
+        match button {
+            EditorButton::Brush(brush) => {
+                editor.brush = *brush;
+                continue;
+            }
+            EditorButton::Turn => position.turn = position.turn.opposite(),
+            EditorButton::Castling(color, side) => {
+                let right = &mut position.castling[color_index(*color)][*side];
+                if right.is_some() {
+                    *right = None;
+                } else {
+                    match castling_rook_file(position, *color, *side) {
+                        Some(rook_file) => {
+                            position.castling[color_index(*color)][*side] = Some(rook_file)
+                        }
+                        None => {
+                            editor.message = Some(format!(
+                                "{} has no king and rook to castle with there",
+                                color_name(*color)
+                            ));
+                            continue;
+                        }
+                    }
+                }
+            }
+            // Steps through the squares a pawn could just have skipped, and back to none
+            EditorButton::EnPassant => {
+                let squares = position.en_passant_squares();
+                position.en_passant = match position.en_passant {
+                    Some(square) => squares
+                        .iter()
+                        .skip_while(|&&other| other != square)
+                        .nth(1)
+                        .copied(),
+                    None => squares.first().copied(),
+                };
+            }
+            EditorButton::Clear => {
+                let mut empty = Position::empty_board(position.ranks(), position.files());
+                empty.variant = position.variant;
+                empty.turn = position.turn;
+                *position = empty;
+            }
+            EditorButton::Reset => *position = Position::variant_start(position.variant),
+            EditorButton::Import => {
+                let imported = std::fs::read_to_string(&settings.path)
+                    .map_err(|err| err.to_string())
+                    .and_then(|text| parse_fen(text.trim()));
+                match imported {
+                    Ok(mut imported) => {
+                        imported.variant = position.variant;
+                        *position = imported;
+                        editor.message = Some(format!("Imported {}", settings.path.display()));
+                    }
+                    Err(err) => {
+                        editor.message = Some(format!(
+                            "Could not import {}: {}",
+                            settings.path.display(),
+                            err
+                        ));
+                        continue;
+                    }
+                }
+            }
+            EditorButton::Export => {
+                let fen = position_to_fen(position);
+                editor.message = Some(match std::fs::write(&settings.path, fen + "\n") {
+                    Ok(()) => format!("Exported to {}", settings.path.display()),
+                    Err(err) => format!("Could not export {}: {}", settings.path.display(), err),
+                });
+                continue;
+            }
+            // The board already shows the setup, and the tree started a new game from it
+            EditorButton::Play => {
+                match position.check_setup() {
+                    Ok(()) => {
+                        editor.active = false;
+                        editor.saved_game = None;
+                    }
+                    Err(err) => editor.message = Some(err),
+                }
+                continue;
+            }
+        }
+        show_edit(editor, &mut load_position_events);
+    }
+}
+
+fn init_editor_panel(mut commands: Commands, mut color_materials: ResMut<Assets<ColorMaterial>>) {
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                display: Display::None,
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    right: Val::Px(10.),
+                    top: Val::Px(250.),
+                    ..Default::default()
+                },
+                flex_direction: FlexDirection::ColumnReverse,
+                align_items: AlignItems::FlexEnd,
+                max_size: Size::new(Val::Px(700.), Val::Undefined),
+                ..Default::default()
+            },
+            material: color_materials.add(Color::NONE.into()),
+            ..Default::default()
+        })
+        .insert(EditorPanel);
+}
+
+/// The piece palette, the rights that aren't visible on the board, the FEN and whether
+/// the position can be played
+fn editor_panel_update(
+    mut commands: Commands,
+    editor: Res<EditorMode>,
+    asset_server: Res<AssetServer>,
+    mut color_materials: ResMut<Assets<ColorMaterial>>,
+    mut panel_query: Query<(Entity, &mut Style, Option<&Children>), With<EditorPanel>>,
+) {
+    if !editor.is_changed() {
+        return;
+    }
+    let (panel, mut style, children) = match panel_query.iter_mut().next() {
+        Some(panel) => panel,
+        None => return,
+    };
+    for &child in children.into_iter().flat_map(|children| children.iter()) {
+        commands.entity(child).despawn_recursive();
+    }
+    if !editor.active {
+        style.display = Display::None;
+        return;
+    }
+    style.display = Display::Flex;
+
+    let position = &editor.position;
+    let mut rows: Vec<(Option<String>, Vec<(EditorButton, String, bool)>)> = Vec::new();
+    for &color in [PieceColor::White, PieceColor::Black].iter() {
+        let brushes = PieceType::ALL
+            .iter()
+            .map(|&piece_type| {
+                let brush = Some((color, piece_type));
+                (
+                    EditorButton::Brush(brush),
+                    piece_letter(piece_type).to_string(),
+                    editor.brush == brush,
+                )
+            })
+            .collect();
+        rows.push((Some(format!("{}:", color_name(color))), brushes));
+    }
+    rows.push((
+        None,
+        vec![
+            (
+                EditorButton::Brush(None),
+                "Erase".to_string(),
+                editor.brush.is_none(),
+            ),
+            (EditorButton::Clear, "Clear".to_string(), false),
+            (EditorButton::Reset, "Start position".to_string(), false),
+        ],
+    ));
+
+    let mut rights = vec![(
+        EditorButton::Turn,
+        format!("{} to move", color_name(position.turn)),
+        false,
+    )];
+    for &color in [PieceColor::White, PieceColor::Black].iter() {
+        for &(side, label) in [(KING_SIDE, "O-O"), (QUEEN_SIDE, "O-O-O")].iter() {
+            rights.push((
+                EditorButton::Castling(color, side),
+                format!("{} {}", color_name(color), label),
+                position.castling[color_index(color)][side].is_some(),
+            ));
+        }
+    }
+    rights.push((
+        EditorButton::EnPassant,
+        format!(
+            "En passant: {}",
+            position
+                .en_passant
+                .map_or_else(|| "-".to_string(), square_name)
+        ),
+        position.en_passant.is_some(),
+    ));
+    rows.push((None, rights));
+    rows.push((
+        None,
+        vec![
+            (EditorButton::Import, "Import FEN".to_string(), false),
+            (EditorButton::Export, "Export FEN".to_string(), false),
+            (EditorButton::Play, "Play".to_string(), false),
+        ],
+    ));
+
+    let mut lines = vec![
+        "Setup: click to place, right click to clear, F5 to cancel".to_string(),
+        position_to_fen(position),
+        match position.check_setup() {
+            Ok(()) => "Ready to play".to_string(),
+            Err(err) => err,
+        },
+    ];
+    lines.extend(editor.message.clone());
+
+    let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+    let button_material = color_materials.add(Color::rgb(0.25, 0.25, 0.25).into());
+    let selected_material = color_materials.add(Color::rgb(0.35, 0.55, 0.35).into());
+    let row_material = color_materials.add(Color::NONE.into());
+    let text = |label: &str| TextBundle {
+        text: Text::with_section(
+            label,
+            TextStyle {
+                font: font.clone(),
+                font_size: 26.0,
+                color: Color::rgb(0.8, 0.8, 0.8),
+            },
+            TextAlignment::default(),
+        ),
+        ..Default::default()
+    };
+
+    commands.entity(panel).with_children(|parent| {
+        parent.spawn_bundle(text(&lines[0]));
+        for (label, buttons) in rows {
+            parent
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        align_items: AlignItems::Center,
+                        ..Default::default()
+                    },
+                    material: row_material.clone(),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    if let Some(label) = label {
+                        parent.spawn_bundle(text(&label));
+                    }
+                    for (button, label, selected) in buttons {
+                        parent
+                            .spawn_bundle(ButtonBundle {
+                                style: Style {
+                                    margin: Rect::all(Val::Px(3.)),
+                                    padding: Rect::all(Val::Px(4.)),
+                                    ..Default::default()
+                                },
+                                material: if selected {
+                                    selected_material.clone()
+                                } else {
+                                    button_material.clone()
+                                },
+                                ..Default::default()
+                            })
+                            .insert(button)
+                            .with_children(|parent| {
+                                parent.spawn_bundle(text(&label));
+                            });
+                    }
+                });
+        }
+        for line in lines[1..].iter() {
+            parent.spawn_bundle(text(line));
+        }
+    });
+}
@@ -0,0 +1,455 @@
+use crate::{pieces::*, position::*, tablebase::*};
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+/// Score of being mated right now. Mates further away score closer to zero, one point
+/// per ply, so the engine goes for the quickest mate
+pub const MATE: i32 = 100_000;
+/// Score of a position the tablebase says is won, counted down per ply like mates
+const TABLEBASE_WIN: i32 = 50_000;
+const MAX_PLY: i32 = 256;
+
+/// How deep analysis goes before it stops on its own
+pub const MAX_DEPTH: u32 = 64;
+
+// Piece-square bonuses from white's side, rank 1 first, files a to h
+#[rustfmt::skip]
+const PAWN_SQUARES: [i32; 64] = [
+      0,   0,   0,   0,   0,   0,   0,   0,
+      5,  10,  10, -20, -20,  10,  10,   5,
+      5,  -5, -10,   0,   0, -10,  -5,   5,
+      0,   0,   0,  20,  20,   0,   0,   0,
+      5,   5,  10,  25,  25,  10,   5,   5,
+     10,  10,  20,  30,  30,  20,  10,  10,
+     50,  50,  50,  50,  50,  50,  50,  50,
+      0,   0,   0,   0,   0,   0,   0,   0,
+];
+#[rustfmt::skip]
+const KNIGHT_SQUARES: [i32; 64] = [
+    -50, -40, -30, -30, -30, -30, -40, -50,
+    -40, -20,   0,   5,   5,   0, -20, -40,
+    -30,   5,  10,  15,  15,  10,   5, -30,
+    -30,   0,  15,  20,  20,  15,   0, -30,
+    -30,   5,  15,  20,  20,  15,   5, -30,
+    -30,   0,  10,  15,  15,  10,   0, -30,
+    -40, -20,   0,   0,   0,   0, -20, -40,
+    -50, -40, -30, -30, -30, -30, -40, -50,
+];
+#[rustfmt::skip]
+const BISHOP_SQUARES: [i32; 64] = [
+    -20, -10, -10, -10, -10, -10, -10, -20,
+    -10,   5,   0,   0,   0,   0,   5, -10,
+    -10,  10,  10,  10,  10,  10,  10, -10,
+    -10,   0,  10,  10,  10,  10,   0, -10,
+    -10,   5,   5,  10,  10,   5,   5, -10,
+    -10,   0,   5,  10,  10,   5,   0, -10,
+    -10,   0,   0,   0,   0,   0,   0, -10,
+    -20, -10, -10, -10, -10, -10, -10, -20,
+];
+#[rustfmt::skip]
+const ROOK_SQUARES: [i32; 64] = [
+      0,   0,   0,   5,   5,   0,   0,   0,
+     -5,   0,   0,   0,   0,   0,   0,  -5,
+     -5,   0,   0,   0,   0,   0,   0,  -5,
+     -5,   0,   0,   0,   0,   0,   0,  -5,
+     -5,   0,   0,   0,   0,   0,   0,  -5,
+     -5,   0,   0,   0,   0,   0,   0,  -5,
+      5,  10,  10,  10,  10,  10,  10,   5,
+      0,   0,   0,   0,   0,   0,   0,   0,
+];
+#[rustfmt::skip]
+const QUEEN_SQUARES: [i32; 64] = [
+    -20, -10, -10,  -5,  -5, -10, -10, -20,
+    -10,   0,   5,   0,   0,   0,   0, -10,
+    -10,   5,   5,   5,   5,   5,   0, -10,
+      0,   0,   5,   5,   5,   5,   0,  -5,
+     -5,   0,   5,   5,   5,   5,   0,  -5,
+    -10,   0,   5,   5,   5,   5,   0, -10,
+    -10,   0,   0,   0,   0,   0,   0, -10,
+    -20, -10, -10,  -5,  -5, -10, -10, -20,
+];
+#[rustfmt::skip]
+const KING_SQUARES: [i32; 64] = [
+     20,  30,  10,   0,   0,  10,  30,  20,
+     20,  20,   0,   0,   0,   0,  20,  20,
+    -10, -20, -20, -20, -20, -20, -20, -10,
+    -20, -30, -30, -40, -40, -30, -30, -20,
+    -30, -40, -40, -50, -50, -40, -40, -30,
+    -30, -40, -40, -50, -50, -40, -40, -30,
+    -30, -40, -40, -50, -50, -40, -40, -30,
+    -30, -40, -40, -50, -50, -40, -40, -30,
+];
+/// Once the heavy pieces are gone the king should walk to the center
+#[rustfmt::skip]
+const KING_ENDGAME_SQUARES: [i32; 64] = [
+    -50, -30, -30, -30, -30, -30, -30, -50,
+    -30, -30,   0,   0,   0,   0, -30, -30,
+    -30, -10,  20,  30,  30,  20, -10, -30,
+    -30, -10,  30,  40,  40,  30, -10, -30,
+    -30, -10,  30,  40,  40,  30, -10, -30,
+    -30, -10,  20,  30,  30,  20, -10, -30,
+    -30, -20, -10,   0,   0, -10, -20, -30,
+    -50, -40, -30, -20, -20, -30, -40, -50,
+];
+
+/// Non-pawn material of both sides together, in centipawns, below which kings count as
+/// being in the endgame
+const ENDGAME_MATERIAL: i32 = 2600;
+
+/// Static evaluation in centipawns from the side to move's point of view. Material and
+/// piece placement are scored as in chess, and the variant weighs them for what it plays
+/// for (see `Variant::evaluate`)
+pub fn evaluate(position: &Position) -> i32 {
+    let pieces = position.pieces();
+    let endgame = pieces
+        .iter()
+        .filter(|piece| piece.piece_type != PieceType::Pawn)
+        .map(|piece| piece.piece_type.value())
+        .sum::<i32>()
+        <= ENDGAME_MATERIAL;
+
+    let mut material = 0;
+    let mut placement = 0;
+    for piece in pieces {
+        // Black's pieces read the tables upside down. Other board sizes are squeezed or
+        // stretched onto the 8x8 tables
+        let rank = match piece.color {
+            PieceColor::White => piece.x,
+            PieceColor::Black => position.ranks() - 1 - piece.x,
+        };
+        let rank = rank as usize * 8 / position.ranks() as usize;
+        let file = piece.y as usize * 8 / position.files() as usize;
+        let index = rank * 8 + file;
+        let table = match piece.piece_type {
+            PieceType::Pawn => &PAWN_SQUARES,
+            PieceType::Knight => &KNIGHT_SQUARES,
+            PieceType::Bishop => &BISHOP_SQUARES,
+            PieceType::Rook => &ROOK_SQUARES,
+            PieceType::King if endgame => &KING_ENDGAME_SQUARES,
+            PieceType::King => &KING_SQUARES,
+            // The queen's table suits any long-range piece
+            _ => &QUEEN_SQUARES,
+        };
+        let sign = if piece.color == position.turn { 1 } else { -1 };
+        material += sign * piece.piece_type.value();
+        placement += sign * table[index];
+    }
+
+    // A pocketed piece can be dropped anywhere, so it counts at its full value
+    for &color in [PieceColor::White, PieceColor::Black].iter() {
+        let pocket = position.pockets[color_index(color)];
+        let value: i32 = Pocket::PIECE_TYPES
+            .iter()
+            .map(|&piece_type| piece_type.value() * pocket.count(piece_type) as i32)
+            .sum();
+        if color == position.turn {
+            material += value;
+        } else {
+            material -= value;
+        }
+    }
+    position.rules().evaluate(position, material, placement)
+}
+
+/// What a finished search depth found
+#[derive(Clone, Debug, PartialEq)]
+pub struct AnalysisInfo {
+    pub depth: u32,
+    /// From the point of view of the side to move in the searched position
+    pub score: i32,
+    /// The best line, starting with the move to play
+    pub pv: Vec<Move>,
+    pub nodes: u64,
+}
+
+/// Moves until mate for mate scores, negative when the side to move gets mated
+pub fn mate_in(score: i32) -> Option<i32> {
+    if score.abs() < MATE - MAX_PLY {
+        return None;
+    }
+    let plies = MATE - score.abs();
+    let moves = (plies + 1) / 2;
+    Some(if score > 0 { moves } else { -moves })
+}
+
+/// Whether the score comes from a tablebase win or loss rather than the evaluation
+pub fn is_tablebase_result(score: i32) -> bool {
+    mate_in(score).is_none() && score.abs() >= TABLEBASE_WIN - MAX_PLY
+}
+
+/// A score as engines print it, e.g. `+0.35`, `#3` or `-#2`, from white's point of view
+/// when `turn` is the side to move the score belongs to
+pub fn score_text(score: i32, turn: PieceColor) -> String {
+    let score = match turn {
+        PieceColor::White => score,
+        PieceColor::Black => -score,
+    };
+    match mate_in(score) {
+        Some(moves) if moves > 0 => format!("#{}", moves),
+        Some(moves) => format!("-#{}", -moves),
+        None if is_tablebase_result(score) && score > 0 => "White wins (TB)".to_string(),
+        None if is_tablebase_result(score) => "Black wins (TB)".to_string(),
+        None => format!("{:+.2}", score as f32 / 100.),
+    }
+}
+
+/// Tablebase outcomes as scores. Cursed wins and blessed losses are draws under the
+/// fifty-move rule
+fn tablebase_score(wdl: Wdl, ply: i32) -> i32 {
+    match wdl {
+        Wdl::Win => TABLEBASE_WIN - ply,
+        Wdl::Loss => -(TABLEBASE_WIN - ply),
+        Wdl::CursedWin | Wdl::Draw | Wdl::BlessedLoss => 0,
+    }
+}
+
+/// Score of a finished game for the side to move, `ply` plies into the search, so that
+/// quicker wins and slower losses are preferred
+pub fn game_over_score(status: GameStatus, turn: PieceColor, ply: i32) -> i32 {
+    match status.winner(turn) {
+        Some(winner) if winner == turn => MATE - ply,
+        Some(_) => -(MATE - ply),
+        None => 0,
+    }
+}
+
+struct Search<'a> {
+    tablebase: Option<&'a Tablebase>,
+    stop: &'a AtomicBool,
+    nodes: u64,
+}
+
+impl<'a> Search<'a> {
+    /// Negamax with alpha-beta pruning. Returns the score with the best line found, or
+    /// nothing useful once stopped
+    fn negamax(
+        &mut self,
+        position: &Position,
+        depth: u32,
+        ply: i32,
+        mut alpha: i32,
+        beta: i32,
+        previous_pv: &[Move],
+    ) -> (i32, Vec<Move>) {
+        self.nodes += 1;
+        if self.stop.load(Ordering::Relaxed) {
+            return (0, Vec::new());
+        }
+
+        // Right after a capture or pawn move the tablebase knows the exact outcome
+        if ply > 0 && position.halfmove_clock == 0 {
+            if let Some(wdl) = self
+                .tablebase
+                .and_then(|tablebase| tablebase.probe_wdl(position))
+            {
+                return (tablebase_score(wdl, ply), Vec::new());
+            }
+        }
+
+        let moves = position.legal_moves();
+        if let Some(status) = position.variant_outcome(&moves) {
+            return (game_over_score(status, position.turn, ply), Vec::new());
+        }
+        if moves.is_empty() {
+            let score = if position.is_in_check(position.turn) {
+                -(MATE - ply)
+            } else {
+                0
+            };
+            return (score, Vec::new());
+        }
+        if ply > 0 && (position.halfmove_clock >= 100 || position.has_insufficient_material()) {
+            return (0, Vec::new());
+        }
+        if depth == 0 {
+            return (self.quiescence(position, alpha, beta), Vec::new());
+        }
+
+        let mut pv = Vec::new();
+        for mv in ordered_moves(position, moves, previous_pv.first().copied()) {
+            let mut after = position.clone();
+            after.play(mv);
+            // Only the line the previous depth liked is worth following further down
+            let child_previous_pv = match previous_pv.split_first() {
+                Some((&first, rest)) if first == mv => rest,
+                _ => &[][..],
+            };
+            let (score, child_pv) =
+                self.negamax(&after, depth - 1, ply + 1, -beta, -alpha, child_previous_pv);
+            let score = -score;
+            if self.stop.load(Ordering::Relaxed) {
+                return (0, Vec::new());
+            }
+
+            if score > alpha {
+                alpha = score;
+                pv.clear();
+                pv.push(mv);
+                pv.extend(child_pv);
+                if alpha >= beta {
+                    break;
+                }
+            }
+        }
+        (alpha, pv)
+    }
+
+    /// Plays out captures and promotions until the position is quiet, so the evaluation
+    /// isn't taken in the middle of an exchange
+    fn quiescence(&mut self, position: &Position, mut alpha: i32, beta: i32) -> i32 {
+        self.nodes += 1;
+        let stand_pat = evaluate(position);
+        if stand_pat >= beta {
+            return stand_pat;
+        }
+        alpha = alpha.max(stand_pat);
+
+        let noisy_moves = position
+            .legal_moves()
+            .into_iter()
+            .filter(|&mv| mv.promotion.is_some() || position.captured_square(mv).is_some())
+            .collect();
+        for mv in ordered_moves(position, noisy_moves, None) {
+            let mut after = position.clone();
+            after.play(mv);
+            let score = -self.quiescence(&after, -beta, -alpha);
+            if score >= beta {
+                return score;
+            }
+            alpha = alpha.max(score);
+        }
+        alpha
+    }
+}
+
+/// The move from the previous best line first, then captures of the most valuable
+/// pieces by the least valuable ones, then the rest
+fn ordered_moves(position: &Position, mut moves: Vec<Move>, first: Option<Move>) -> Vec<Move> {
+    moves.sort_by_cached_key(|&mv| {
+        if Some(mv) == first {
+            return i32::MIN;
+        }
+        let victim = position
+            .captured_square(mv)
+            .and_then(|square| position.piece_at(square))
+            .map_or(0, |piece| piece.piece_type.value());
+        let attacker = position
+            .piece_at(mv.from)
+            .map_or(0, |piece| piece.piece_type.value());
+        let promotion = mv.promotion.map_or(0, |piece_type| piece_type.value());
+        if victim > 0 || promotion > 0 {
+            -(victim * 10 + promotion - attacker / 10)
+        } else {
+            0
+        }
+    });
+    moves
+}
+
+/// Searches one ply deeper at a time until `max_depth` or until `stop` is set, calling
+/// `on_depth` after every depth. Returns the result of the deepest finished depth
+pub fn search(
+    position: &Position,
+    max_depth: u32,
+    tablebase: Option<&Tablebase>,
+    stop: &AtomicBool,
+    mut on_depth: impl FnMut(&AnalysisInfo),
+) -> Option<AnalysisInfo> {
+    let moves = position.legal_moves();
+    if moves.is_empty() || position.variant_outcome(&moves).is_some() {
+        return None;
+    }
+
+    // With the tablebase there is nothing left to search
+    if let Some(tablebase) = tablebase {
+        if let (Some(probe), Some(mv)) = (tablebase.probe(position), tablebase.best_move(position))
+        {
+            let info = AnalysisInfo {
+                depth: 1,
+                score: tablebase_score(probe.wdl, 0),
+                pv: vec![mv],
+                nodes: 1,
+            };
+            on_depth(&info);
+            return Some(info);
+        }
+    }
+
+    let mut search = Search {
+        tablebase,
+        stop,
+        nodes: 0,
+    };
+    let mut best = None;
+    for depth in 1..=max_depth {
+        let previous_pv = best
+            .as_ref()
+            .map_or_else(Vec::new, |info: &AnalysisInfo| info.pv.clone());
+        let (score, pv) = search.negamax(position, depth, 0, -MATE, MATE, &previous_pv);
+        if stop.load(Ordering::Relaxed) {
+            break;
+        }
+
+        let info = AnalysisInfo {
+            depth,
+            score,
+            pv,
+            nodes: search.nodes,
+        };
+        on_depth(&info);
+        best = Some(info);
+
+        // A forced mate can't get any shorter by looking deeper
+        if mate_in(score).is_some() {
+            break;
+        }
+    }
+    best
+}
+
+/// A search running on its own thread, for analysis that shouldn't hold up the frame.
+/// Dropping it stops the search
+pub struct BackgroundSearch {
+    stop: Arc<AtomicBool>,
+    latest: Arc<Mutex<Option<AnalysisInfo>>>,
+}
+impl BackgroundSearch {
+    pub fn start(position: &Position, tablebase: Option<Tablebase>) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let latest = Arc::new(Mutex::new(None));
+
+        let position = position.clone();
+        let thread_stop = stop.clone();
+        let thread_latest = latest.clone();
+        thread::spawn(move || {
+            search(
+                &position,
+                MAX_DEPTH,
+                tablebase.as_ref(),
+                &thread_stop,
+                |info| {
+                    if let Ok(mut latest) = thread_latest.lock() {
+                        *latest = Some(info.clone());
+                    }
+                },
+            );
+        });
+
+        Self { stop, latest }
+    }
+
+    /// The deepest result so far
+    pub fn latest(&self) -> Option<AnalysisInfo> {
+        self.latest.lock().ok().and_then(|latest| latest.clone())
+    }
+}
+impl Drop for BackgroundSearch {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
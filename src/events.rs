@@ -0,0 +1,156 @@
+use crate::{pieces::*, position::*};
+use bevy::prelude::*;
+
+/// Asks the game to move a piece. Sent by whatever produces moves: the mouse, the
+/// keyboard or a typed move
+pub struct MoveRequested {
+    pub entity: Entity,
+    pub to: (u8, u8),
+    /// What a pawn reaching the last rank becomes, a queen when not given
+    pub promotion: Option<PieceType>,
+}
+
+/// Asks the game to put a piece from `color`'s pocket on the empty square `to`, in
+/// variants with drops
+pub struct DropRequested {
+    pub color: PieceColor,
+    pub piece_type: PieceType,
+    pub to: (u8, u8),
+}
+
+/// Replaces the game with another position, e.g. to go back to the main line after
+/// exploring. The pieces on the board are respawned to match
+pub struct LoadPosition {
+    pub position: Position,
+}
+
+/// A move that broke the rules and was not played
+pub struct IllegalMoveAttempted {
+    pub entity: Entity,
+    pub piece: Piece,
+    pub to: (u8, u8),
+}
+
+pub struct MoveMade {
+    pub entity: Entity,
+    /// The piece as it stands after the move
+    pub piece: Piece,
+    pub from: (u8, u8),
+    pub captured: Option<PieceType>,
+    pub castling: bool,
+    pub promotion: Option<PieceType>,
+    /// The piece came from the pocket, so `from` is where it landed
+    pub dropped: bool,
+    /// The move in standard algebraic notation, e.g. `Nf3` or `O-O+`
+    pub san: String,
+}
+
+pub struct PieceCaptured {
+    pub entity: Entity,
+    pub piece: Piece,
+    pub by: PieceColor,
+}
+
+/// The given side's king is under attack
+pub struct CheckGiven {
+    pub color: PieceColor,
+}
+
+pub struct TurnChanged {
+    pub color: PieceColor,
+}
+
+pub struct GameEnded {
+    /// `None` for a draw
+    pub winner: Option<PieceColor>,
+    pub status: GameStatus,
+}
+
+/// Registers the game events and logs them as they happen
+pub struct GameEventsPlugin;
+impl Plugin for GameEventsPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_event::<MoveRequested>()
+            .add_event::<DropRequested>()
+            .add_event::<LoadPosition>()
+            .add_event::<IllegalMoveAttempted>()
+            .add_event::<MoveMade>()
+            .add_event::<PieceCaptured>()
+            .add_event::<CheckGiven>()
+            .add_event::<TurnChanged>()
+            .add_event::<GameEnded>()
+            .add_system(log_game_events.system());
+    }
+}
+
+pub fn color_name(color: PieceColor) -> &'static str {
+    match color {
+        PieceColor::White => "White",
+        PieceColor::Black => "Black",
+    }
+}
+
+pub fn piece_name(piece_type: PieceType) -> &'static str {
+    piece_type.name()
+}
+
+/// How a finished game is announced, e.g. "White won by checkmate."
+pub fn game_result_text(event: &GameEnded) -> String {
+    match event.winner {
+        Some(winner) => format!(
+            "{} won by {}.",
+            color_name(winner),
+            match event.status {
+                GameStatus::VariantWin { reason, .. } => reason,
+                _ => "checkmate",
+            }
+        ),
+        None => format!(
+            "Draw by {}.",
+            match event.status {
+                GameStatus::Stalemate => "stalemate",
+                GameStatus::FiftyMoveRule => "the fifty-move rule",
+                GameStatus::InsufficientMaterial => "insufficient material",
+                GameStatus::Ongoing | GameStatus::Checkmate | GameStatus::VariantWin { .. } => {
+                    "agreement"
+                }
+            }
+        ),
+    }
+}
+
+fn log_game_events(
+    mut illegal_move_events: EventReader<IllegalMoveAttempted>,
+    mut move_made_events: EventReader<MoveMade>,
+    mut check_given_events: EventReader<CheckGiven>,
+    mut game_ended_events: EventReader<GameEnded>,
+) {
+    for event in illegal_move_events.iter() {
+        debug!(
+            "Illegal move of {} {} to {:?}",
+            color_name(event.piece.color),
+            piece_name(event.piece.piece_type),
+            event.to
+        );
+    }
+    for event in move_made_events.iter() {
+        info!(
+            "{} {} {:?} -> {:?} ({}){}",
+            color_name(event.piece.color),
+            piece_name(event.piece.piece_type),
+            event.from,
+            (event.piece.x, event.piece.y),
+            event.san,
+            match event.captured {
+                Some(piece_type) => format!(", takes {}", piece_name(piece_type)),
+                None => String::new(),
+            }
+        );
+    }
+    for event in check_given_events.iter() {
+        info!("{} is in check", color_name(event.color));
+    }
+    for event in game_ended_events.iter() {
+        info!("{}", game_result_text(event));
+    }
+}
@@ -0,0 +1,989 @@
+use crate::{eco::*, events::*, notation::*, pieces::*, position::*, variant::*};
+use bevy::prelude::*;
+use std::path::PathBuf;
+
+/// Index of a node in `GameTree`. Ids are never reused: deleting a variation only unlinks
+/// it, leaving its nodes orphaned in the tree, so an id held elsewhere never names
+/// another move
+pub type NodeId = usize;
+
+pub const ROOT: NodeId = 0;
+
+#[derive(Clone)]
+struct Node {
+    /// The move leading here and its SAN, `None` for the root
+    mv: Option<(Move, String)>,
+    /// The position after the move
+    position: Position,
+    parent: Option<NodeId>,
+    /// The first child continues the line, the others are variations
+    children: Vec<NodeId>,
+    /// Numeric annotation glyph for the move, e.g. 2 for `?`. The root's NAG and comment
+    /// are about the game before its first move
+    nag: Option<u8>,
+    comment: Option<String>,
+}
+
+/// Every move tried in the game, as a tree. Playing a different move from an earlier
+/// position starts a variation instead of overwriting what followed
+#[derive(Clone)]
+pub struct GameTree {
+    nodes: Vec<Node>,
+    current: NodeId,
+}
+/// The game starts wherever the board does, which may be a Chess960 setup or a FEN
+impl FromWorld for GameTree {
+    fn from_world(world: &mut World) -> Self {
+        Self::new(
+            world
+                .get_resource::<Position>()
+                .cloned()
+                .unwrap_or_else(Position::start),
+        )
+    }
+}
+
+impl GameTree {
+    pub fn new(start: Position) -> Self {
+        Self {
+            nodes: vec![Node {
+                mv: None,
+                position: start,
+                parent: None,
+                children: Vec::new(),
+                nag: None,
+                comment: None,
+            }],
+            current: ROOT,
+        }
+    }
+
+    pub fn current(&self) -> NodeId {
+        self.current
+    }
+
+    pub fn position(&self, node: NodeId) -> &Position {
+        &self.nodes[node].position
+    }
+
+    pub fn parent(&self, node: NodeId) -> Option<NodeId> {
+        self.nodes[node].parent
+    }
+
+    pub fn children(&self, node: NodeId) -> &[NodeId] {
+        &self.nodes[node].children
+    }
+
+    pub fn san(&self, node: NodeId) -> &str {
+        self.nodes[node]
+            .mv
+            .as_ref()
+            .map_or("", |(_, san)| san.as_str())
+    }
+
+    pub fn mv(&self, node: NodeId) -> Option<Move> {
+        self.nodes[node].mv.as_ref().map(|(mv, _)| *mv)
+    }
+
+    pub fn nag(&self, node: NodeId) -> Option<u8> {
+        self.nodes[node].nag
+    }
+
+    pub fn comment(&self, node: NodeId) -> Option<&str> {
+        self.nodes[node].comment.as_deref()
+    }
+
+    /// The SAN with the annotation symbol, e.g. `Qxb7??`
+    pub fn annotated_san(&self, node: NodeId) -> String {
+        let symbol = self.nodes[node].nag.and_then(nag_symbol).unwrap_or("");
+        format!("{}{}", self.san(node), symbol)
+    }
+
+    /// Marks the move leading to `node`, or the start of the game for the root, replacing
+    /// earlier annotations
+    pub fn annotate(&mut self, node: NodeId, nag: Option<u8>, comment: Option<String>) {
+        self.nodes[node].nag = nag;
+        self.nodes[node].comment = comment;
+    }
+
+    /// Goes to `node` without touching the board, which is left to the caller
+    pub fn set_current(&mut self, node: NodeId) {
+        self.current = node;
+    }
+
+    /// Plays `mv` from the current node, following the existing child when the move was
+    /// tried before
+    pub fn add_move(&mut self, mv: Move, san: String, position: Position) -> NodeId {
+        let current = self.current;
+        let existing = self.nodes[current].children.iter().copied().find(|&child| {
+            self.nodes[child].mv.as_ref().map(|(child_mv, _)| *child_mv) == Some(mv)
+        });
+
+        self.current = match existing {
+            Some(existing) => existing,
+            None => {
+                self.nodes.push(Node {
+                    mv: Some((mv, san)),
+                    position,
+                    parent: Some(current),
+                    children: Vec::new(),
+                    nag: None,
+                    comment: None,
+                });
+                let node = self.nodes.len() - 1;
+                self.nodes[current].children.push(node);
+                node
+            }
+        };
+        self.current
+    }
+
+    /// The start of the innermost variation `node` is in: the first node on the way back to
+    /// the root that is a variation rather than the continuation of its parent's line
+    fn variation_start(&self, node: NodeId) -> Option<NodeId> {
+        let mut node = node;
+        while let Some(parent) = self.nodes[node].parent {
+            if self.nodes[parent].children[0] != node {
+                return Some(node);
+            }
+            node = parent;
+        }
+        None
+    }
+
+    /// Makes the line through `node` the main line
+    pub fn promote(&mut self, node: NodeId) {
+        let mut node = node;
+        while let Some(parent) = self.nodes[node].parent {
+            let children = &mut self.nodes[parent].children;
+            if let Some(index) = children.iter().position(|&child| child == node) {
+                let child = children.remove(index);
+                children.insert(0, child);
+            }
+            node = parent;
+        }
+    }
+
+    /// Removes the variation `node` belongs to, with everything after it, and goes to where
+    /// it branched off. Does nothing on the main line
+    pub fn delete_variation(&mut self, node: NodeId) -> bool {
+        let start = match self.variation_start(node) {
+            Some(start) => start,
+            None => return false,
+        };
+        if let Some(parent) = self.nodes[start].parent {
+            self.nodes[parent].children.retain(|&child| child != start);
+            self.current = parent;
+        }
+        true
+    }
+
+    /// Takes back the current move, removing it with everything after it, and goes to the
+    /// node before it. Returns that node, or `None` at the start of the game
+    pub fn take_back(&mut self) -> Option<NodeId> {
+        let current = self.current;
+        let parent = self.nodes[current].parent?;
+        self.nodes[parent]
+            .children
+            .retain(|&child| child != current);
+        self.current = parent;
+        Some(parent)
+    }
+
+    /// The nodes following the first child from `node` on
+    pub fn line(&self, node: NodeId) -> Vec<NodeId> {
+        let mut line = Vec::new();
+        let mut node = node;
+        while let Some(&next) = self.nodes[node].children.first() {
+            line.push(next);
+            node = next;
+        }
+        line
+    }
+
+    /// Finds a node reached by the game with this position, the main line first
+    pub fn find(&self, position: &Position) -> Option<NodeId> {
+        let mut stack = vec![ROOT];
+        while let Some(node) = stack.pop() {
+            if self.nodes[node].position == *position {
+                return Some(node);
+            }
+            stack.extend(self.nodes[node].children.iter().rev());
+        }
+        None
+    }
+
+    /// The move number prefix of `node`, `3.` for white, `3...` for black when `always`
+    fn move_number(&self, node: NodeId, always: bool) -> Option<String> {
+        let before = self.position(self.nodes[node].parent?);
+        match before.turn {
+            PieceColor::White => Some(format!("{}.", before.fullmove_number)),
+            PieceColor::Black if always => Some(format!("{}...", before.fullmove_number)),
+            PieceColor::Black => None,
+        }
+    }
+
+    /// The moves of the game as PGN movetext, variations in parentheses
+    fn movetext(&self, node: NodeId, always_number: bool, words: &mut Vec<String>) {
+        let children = &self.nodes[node].children;
+        let (&main, variations) = match children.split_first() {
+            Some(split) => split,
+            None => return,
+        };
+
+        words.extend(self.move_number(main, always_number));
+        self.push_move(main, words);
+        for &variation in variations {
+            let mut variation_words = Vec::new();
+            variation_words.extend(self.move_number(variation, true));
+            self.push_move(variation, &mut variation_words);
+            self.movetext(variation, false, &mut variation_words);
+            words.push(format!("({})", variation_words.join(" ")));
+        }
+        // The main line picks up again with its move number after a variation or comment
+        let number_next = !variations.is_empty() || self.nodes[main].comment.is_some();
+        self.movetext(main, number_next, words);
+    }
+
+    /// A move in PGN, followed by its NAG and comment
+    fn push_move(&self, node: NodeId, words: &mut Vec<String>) {
+        words.push(self.san(node).to_string());
+        self.push_annotations(node, words);
+    }
+
+    fn push_annotations(&self, node: NodeId, words: &mut Vec<String>) {
+        if let Some(nag) = self.nodes[node].nag {
+            words.push(format!("${}", nag));
+        }
+        if let Some(comment) = &self.nodes[node].comment {
+            // Comments can't contain their own closing brace
+            words.push(format!("{{{}}}", comment.replace('}', ")")));
+        }
+    }
+
+    /// The game in PGN, with the result taken from the end of the main line. `extra` tags
+    /// replace the default ones of the same name or are added after them
+    pub fn to_pgn_with_tags(&self, extra: &[(&'static str, String)]) -> String {
+        let start = self.position(ROOT);
+        let end = self.position(self.line(ROOT).last().copied().unwrap_or(ROOT));
+        let status = end.status();
+        let result = match (status, status.winner(end.turn)) {
+            (GameStatus::Ongoing, _) => "*",
+            (_, Some(PieceColor::White)) => "1-0",
+            (_, Some(PieceColor::Black)) => "0-1",
+            (_, None) => "1/2-1/2",
+        };
+
+        let mut tags = vec![
+            ("Event", "Casual game".to_string()),
+            ("Site", "?".to_string()),
+            ("Date", "????.??.??".to_string()),
+            ("Round", "-".to_string()),
+            ("White", "?".to_string()),
+            ("Black", "?".to_string()),
+            ("Result", result.to_string()),
+        ];
+        if start.variant != VariantKind::Standard {
+            tags.push(("Variant", start.rules().name().to_string()));
+        } else if start.is_chess960() {
+            tags.push(("Variant", "Chess960".to_string()));
+        }
+        if *start != Position::variant_start(start.variant) {
+            tags.push(("SetUp", "1".to_string()));
+            tags.push(("FEN", position_to_fen(start)));
+        }
+        for &(name, ref value) in extra {
+            match tags.iter_mut().find(|(tag, _)| *tag == name) {
+                Some(tag) => tag.1 = value.clone(),
+                None => tags.push((name, value.clone())),
+            }
+        }
+
+        let mut words = Vec::new();
+        self.push_annotations(ROOT, &mut words);
+        self.movetext(ROOT, true, &mut words);
+        words.push(result.to_string());
+
+        let mut pgn: String = tags
+            .iter()
+            .map(|(name, value)| format!("[{} \"{}\"]\n", name, value))
+            .collect();
+        pgn.push('\n');
+        pgn.push_str(&wrap(&words, 80));
+        pgn.push('\n');
+        pgn
+    }
+}
+
+/// A game read from PGN, with its tags in the order they were given
+pub struct PgnGame {
+    pub tags: Vec<(String, String)>,
+    pub tree: GameTree,
+}
+impl PgnGame {
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(tag, _)| tag == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Splits a PGN file into its games, each starting with its tags
+pub fn split_pgn(text: &str) -> Vec<String> {
+    let mut games = Vec::new();
+    let mut game = String::new();
+    let mut in_movetext = false;
+    for line in text.lines() {
+        let is_tag = line.trim_start().starts_with('[');
+        if is_tag && in_movetext {
+            games.push(std::mem::take(&mut game));
+            in_movetext = false;
+        }
+        if !is_tag && !line.trim().is_empty() {
+            in_movetext = true;
+        }
+        game.push_str(line);
+        game.push('\n');
+    }
+    if !game.trim().is_empty() {
+        games.push(game);
+    }
+    games
+}
+
+/// Reads a single game in PGN with its variations, NAGs and comments. The `FEN` and
+/// `Variant` tags give the start, and moves may be in SAN or UCI
+pub fn parse_pgn(text: &str) -> Result<PgnGame, String> {
+    let mut tags = Vec::new();
+    let mut movetext = String::new();
+    for line in text.lines() {
+        let line = line.trim();
+        match line.strip_prefix('[').and_then(|tag| tag.strip_suffix(']')) {
+            Some(tag) => {
+                let (name, value) = tag.split_at(tag.find(char::is_whitespace).unwrap_or(0));
+                let value = value.trim();
+                let value = value
+                    .strip_prefix('"')
+                    .and_then(|value| value.strip_suffix('"'))
+                    .unwrap_or(value)
+                    .replace("\\\"", "\"")
+                    .replace("\\\\", "\\");
+                tags.push((name.to_string(), value));
+            }
+            None => {
+                movetext.push_str(line);
+                movetext.push('\n');
+            }
+        }
+    }
+    let tag = |name: &str| {
+        tags.iter()
+            .find(|(tag, _)| tag == name)
+            .map(|(_, value)| value.as_str())
+    };
+
+    let variant = match tag("Variant") {
+        // Chess960 is standard chess from a FEN
+        Some(name) if !name.to_lowercase().contains("960") => {
+            VariantKind::from_name(name).ok_or_else(|| format!("Unknown variant: {}", name))?
+        }
+        _ => VariantKind::Standard,
+    };
+    let mut start = match tag("FEN") {
+        Some(fen) => parse_fen(fen)?,
+        None => Position::variant_start(variant),
+    };
+    start.variant = variant;
+
+    let mut tree = GameTree::new(start);
+    // Where to go back to when a variation ends
+    let mut variations = Vec::new();
+    for token in movetext_tokens(&movetext) {
+        let current = tree.current();
+        match token.as_str() {
+            "(" => {
+                let parent = tree
+                    .parent(current)
+                    .ok_or_else(|| "Variation before the first move".to_string())?;
+                variations.push(current);
+                tree.set_current(parent);
+            }
+            ")" => {
+                let back = variations
+                    .pop()
+                    .ok_or_else(|| "Variation closed but never opened".to_string())?;
+                tree.set_current(back);
+            }
+            "1-0" | "0-1" | "1/2-1/2" | "*" => break,
+            _ if token.starts_with('{') => {
+                let comment = token[1..token.len() - 1].trim();
+                let comment = match tree.comment(current) {
+                    Some(earlier) => format!("{} {}", earlier, comment),
+                    None => comment.to_string(),
+                };
+                tree.annotate(current, tree.nag(current), Some(comment));
+            }
+            _ if token.starts_with('$') => {
+                let nag = token[1..]
+                    .parse()
+                    .map_err(|_| format!("Not a NAG: {}", token))?;
+                let comment = tree.comment(current).map(str::to_string);
+                tree.annotate(current, Some(nag), comment);
+            }
+            _ => {
+                // Move numbers may be written against the move, as in `12.Nf3`
+                let word = match token.rfind('.') {
+                    Some(dot) => &token[dot + 1..],
+                    None => &token,
+                };
+                if word.is_empty() {
+                    continue;
+                }
+                let symbol_start = word
+                    .find(|c| c == '!' || c == '?')
+                    .unwrap_or_else(|| word.len());
+                let (san, symbol) = word.split_at(symbol_start);
+                let nag = (1..=6).find(|&nag| nag_symbol(nag) == Some(symbol));
+
+                let mut position = tree.position(current).clone();
+                let mv = parse_move(&position, san)?;
+                let san = move_to_san(&position, mv);
+                position.play(mv);
+                let node = tree.add_move(mv, san, position);
+                if nag.is_some() {
+                    tree.annotate(node, nag, None);
+                }
+            }
+        }
+    }
+
+    // The game is looked at from the start
+    tree.set_current(ROOT);
+    Ok(PgnGame { tags, tree })
+}
+
+/// Movetext as words, parentheses, NAGs and whole `{...}` comments. Comments to the end
+/// of the line (`;`) are dropped
+fn movetext_tokens(movetext: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut word = String::new();
+    let mut chars = movetext.chars();
+    while let Some(c) = chars.next() {
+        if c.is_whitespace() || "(){};".contains(c) {
+            if !word.is_empty() {
+                tokens.push(std::mem::take(&mut word));
+            }
+        }
+        match c {
+            '{' => {
+                let comment: String = chars.by_ref().take_while(|&c| c != '}').collect();
+                tokens.push(format!("{{{}}}", comment));
+            }
+            ';' => {
+                chars.by_ref().take_while(|&c| c != '\n').for_each(drop);
+            }
+            '(' | ')' => tokens.push(c.to_string()),
+            c if c.is_whitespace() || c == '}' => (),
+            c => word.push(c),
+        }
+    }
+    if !word.is_empty() {
+        tokens.push(word);
+    }
+    tokens
+}
+
+/// How common NAGs are written after a move
+pub fn nag_symbol(nag: u8) -> Option<&'static str> {
+    match nag {
+        1 => Some("!"),
+        2 => Some("?"),
+        3 => Some("!!"),
+        4 => Some("??"),
+        5 => Some("!?"),
+        6 => Some("?!"),
+        _ => None,
+    }
+}
+
+/// Joins words into lines no longer than `width`, as PGN asks for
+fn wrap(words: &[String], width: usize) -> String {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for word in words {
+        if !line.is_empty() && line.len() + 1 + word.len() > width {
+            lines.push(std::mem::take(&mut line));
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines.join("\n")
+}
+
+pub struct PgnExportSettings {
+    pub path: PathBuf,
+}
+impl Default for PgnExportSettings {
+    fn default() -> Self {
+        Self {
+            path: "game.pgn".into(),
+        }
+    }
+}
+
+/// A line of the move list: moves that can be clicked, indented by how deep the variation is
+struct MoveListLine {
+    depth: usize,
+    entries: Vec<(NodeId, String)>,
+}
+
+/// The move list as lines, each variation on its own indented line after the move it
+/// replaces, and the main line continuing below
+fn move_list_lines(tree: &GameTree) -> Vec<MoveListLine> {
+    let mut lines = Vec::new();
+    push_line(tree, ROOT, 0, &mut lines);
+    lines
+}
+
+/// Adds the line starting with `start`, and the variations branching off it
+fn push_line(tree: &GameTree, start: NodeId, depth: usize, lines: &mut Vec<MoveListLine>) {
+    let label = |node: NodeId, always_number: bool| match tree.move_number(node, always_number) {
+        Some(number) => format!("{} {}", number, tree.annotated_san(node)),
+        None => tree.annotated_san(node),
+    };
+    let new_line = || MoveListLine {
+        depth,
+        entries: Vec::new(),
+    };
+
+    let mut line = new_line();
+    let mut always_number = true;
+    if start != ROOT {
+        line.entries.push((start, label(start, true)));
+        always_number = false;
+    }
+
+    let mut node = start;
+    while let Some((&main, variations)) = tree.children(node).split_first() {
+        line.entries.push((main, label(main, always_number)));
+        always_number = false;
+
+        if !variations.is_empty() {
+            lines.push(std::mem::replace(&mut line, new_line()));
+            for &variation in variations {
+                push_line(tree, variation, depth + 1, lines);
+            }
+            // The main line picks up again with its move number
+            always_number = true;
+        }
+        node = main;
+    }
+    if !line.entries.is_empty() {
+        lines.push(line);
+    }
+}
+
+/// Clicking a move in the list goes to its position
+struct MoveButton {
+    node: NodeId,
+}
+
+enum HistoryButton {
+    Back,
+    Forward,
+    Promote,
+    Delete,
+    ExportPgn,
+}
+
+struct MoveListPanel;
+
+pub struct GameTreePlugin;
+impl Plugin for GameTreePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<GameTree>()
+            .init_resource::<PgnExportSettings>()
+            .add_startup_system(init_move_list.system())
+            .add_system(record_moves.system())
+            .add_system(follow_loaded_positions.system())
+            .add_system(move_list_update.system())
+            .add_system(move_buttons.system())
+            .add_system(history_buttons.system());
+    }
+}
+
+fn record_moves(mut tree: ResMut<GameTree>, mut move_made_events: EventReader<MoveMade>) {
+    for event in move_made_events.iter() {
+        let mv = Move {
+            from: event.from,
+            to: (event.piece.x, event.piece.y),
+            promotion: event.promotion,
+            castling: event.castling,
+            drop: if event.dropped {
+                Some(event.piece.piece_type)
+            } else {
+                None
+            },
+        };
+        let mut position = tree.position(tree.current()).clone();
+        // Analysis lets a side move out of turn
+        if position.turn != event.piece.color {
+            position.pass();
+        }
+        position.play(mv);
+        tree.add_move(mv, event.san.clone(), position);
+    }
+}
+
+/// Keeps the tree on the loaded position. A position the game never reached starts a
+/// new game from it
+fn follow_loaded_positions(
+    mut tree: ResMut<GameTree>,
+    mut load_position_events: EventReader<LoadPosition>,
+) {
+    for event in load_position_events.iter() {
+        if *tree.position(tree.current()) == event.position {
+            continue;
+        }
+        match tree.find(&event.position) {
+            Some(node) => tree.set_current(node),
+            None => *tree = GameTree::new(event.position.clone()),
+        }
+    }
+}
+
+fn init_move_list(mut commands: Commands, mut color_materials: ResMut<Assets<ColorMaterial>>) {
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Px(50.),
+                    top: Val::Px(170.),
+                    ..Default::default()
+                },
+                max_size: Size::new(Val::Px(450.), Val::Undefined),
+                flex_direction: FlexDirection::ColumnReverse,
+                ..Default::default()
+            },
+            material: color_materials.add(Color::NONE.into()),
+            ..Default::default()
+        })
+        .insert(MoveListPanel);
+}
+
+/// Rebuilds the move list whenever the tree changes, highlighting the current move
+fn move_list_update(
+    mut commands: Commands,
+    tree: Res<GameTree>,
+    asset_server: Res<AssetServer>,
+    mut color_materials: ResMut<Assets<ColorMaterial>>,
+    panel_query: Query<(Entity, Option<&Children>), With<MoveListPanel>>,
+) {
+    if !tree.is_changed() {
+        return;
+    }
+    let (panel, children) = match panel_query.iter().next() {
+        Some(panel) => panel,
+        None => return,
+    };
+    for &child in children.into_iter().flat_map(|children| children.iter()) {
+        commands.entity(child).despawn_recursive();
+    }
+
+    let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+    let button_material = color_materials.add(Color::rgb(0.25, 0.25, 0.25).into());
+    let current_material = color_materials.add(Color::rgb(0.35, 0.55, 0.35).into());
+    let row_material = color_materials.add(Color::NONE.into());
+    let text = |label: String, font_size: f32| TextBundle {
+        text: Text::with_section(
+            label,
+            TextStyle {
+                font: font.clone(),
+                font_size,
+                color: Color::rgb(0.8, 0.8, 0.8),
+            },
+            TextAlignment::default(),
+        ),
+        ..Default::default()
+    };
+
+    commands.entity(panel).with_children(|parent| {
+        parent
+            .spawn_bundle(NodeBundle {
+                material: row_material.clone(),
+                ..Default::default()
+            })
+            .with_children(|parent| {
+                for (button, label) in vec![
+                    (HistoryButton::Back, "<"),
+                    (HistoryButton::Forward, ">"),
+                    (HistoryButton::Promote, "Promote"),
+                    (HistoryButton::Delete, "Delete"),
+                    (HistoryButton::ExportPgn, "Export PGN"),
+                ] {
+                    parent
+                        .spawn_bundle(ButtonBundle {
+                            style: Style {
+                                margin: Rect::all(Val::Px(3.)),
+                                padding: Rect::all(Val::Px(4.)),
+                                ..Default::default()
+                            },
+                            material: button_material.clone(),
+                            ..Default::default()
+                        })
+                        .insert(button)
+                        .with_children(|parent| {
+                            parent.spawn_bundle(text(label.to_string(), 24.));
+                        });
+                }
+            });
+
+        for line in move_list_lines(&tree) {
+            parent
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        flex_wrap: FlexWrap::Wrap,
+                        margin: Rect {
+                            left: Val::Px(line.depth as f32 * 20.),
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    },
+                    material: row_material.clone(),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    for (node, label) in line.entries {
+                        parent
+                            .spawn_bundle(ButtonBundle {
+                                style: Style {
+                                    margin: Rect::all(Val::Px(2.)),
+                                    padding: Rect::all(Val::Px(2.)),
+                                    ..Default::default()
+                                },
+                                material: if node == tree.current() {
+                                    current_material.clone()
+                                } else {
+                                    row_material.clone()
+                                },
+                                ..Default::default()
+                            })
+                            .insert(MoveButton { node })
+                            .with_children(|parent| {
+                                parent.spawn_bundle(text(label, 22.));
+                            });
+                    }
+                });
+        }
+    });
+}
+
+fn go_to(tree: &mut GameTree, node: NodeId, load_position_events: &mut EventWriter<LoadPosition>) {
+    tree.set_current(node);
+    load_position_events.send(LoadPosition {
+        position: tree.position(node).clone(),
+    });
+}
+
+fn move_buttons(
+    mut tree: ResMut<GameTree>,
+    mut load_position_events: EventWriter<LoadPosition>,
+    buttons_query: Query<(&Interaction, &MoveButton), Changed<Interaction>>,
+) {
+    for (interaction, button) in buttons_query.iter() {
+        if *interaction == Interaction::Clicked && button.node != tree.current() {
+            go_to(&mut tree, button.node, &mut load_position_events);
+        }
+    }
+}
+
+/// The buttons above the move list, and Page Up/Page Down to step through the moves
+fn history_buttons(
+    keyboard_inputs: Res<Input<KeyCode>>,
+    settings: Res<PgnExportSettings>,
+    eco: Option<Res<EcoTable>>,
+    mut tree: ResMut<GameTree>,
+    mut load_position_events: EventWriter<LoadPosition>,
+    buttons_query: Query<(&Interaction, &HistoryButton), Changed<Interaction>>,
+) {
+    let mut pressed: Vec<&HistoryButton> = buttons_query
+        .iter()
+        .filter(|(interaction, _)| **interaction == Interaction::Clicked)
+        .map(|(_, button)| button)
+        .collect();
+    if keyboard_inputs.just_pressed(KeyCode::PageUp) {
+        pressed.push(&HistoryButton::Back);
+    }
+    if keyboard_inputs.just_pressed(KeyCode::PageDown) {
+        pressed.push(&HistoryButton::Forward);
+    }
+
+    for button in pressed {
+        let current = tree.current();
+        match button {
+            HistoryButton::Back => {
+                if let Some(parent) = tree.parent(current) {
+                    go_to(&mut tree, parent, &mut load_position_events);
+                }
+            }
+            HistoryButton::Forward => {
+                if let Some(&next) = tree.children(current).first() {
+                    go_to(&mut tree, next, &mut load_position_events);
+                }
+            }
+            HistoryButton::Promote => tree.promote(current),
+            HistoryButton::Delete => {
+                if tree.delete_variation(current) {
+                    let node = tree.current();
+                    go_to(&mut tree, node, &mut load_position_events);
+                }
+            }
+            HistoryButton::ExportPgn => {
+                let tags = eco.as_ref().map_or(Vec::new(), |eco| eco.pgn_tags(&tree));
+                match std::fs::write(&settings.path, tree.to_pgn_with_tags(&tags)) {
+                    Ok(()) => info!("Saved the game to {}", settings.path.display()),
+                    Err(err) => warn!("Could not save {}: {}", settings.path.display(), err),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Follows the moves given in SAN from the root, through variations as well
+    fn node_after(tree: &GameTree, sans: &[&str]) -> NodeId {
+        sans.iter().fold(ROOT, |node, san| {
+            *tree
+                .children(node)
+                .iter()
+                .find(|&&child| tree.san(child) == *san)
+                .unwrap_or_else(|| panic!("no {} after node {}", san, node))
+        })
+    }
+
+    /// The movetext of an exported game, without the tags
+    fn movetext(tree: &GameTree) -> String {
+        let pgn = tree.to_pgn_with_tags(&[]);
+        pgn.split("\n\n").nth(1).unwrap().trim().to_string()
+    }
+
+    #[test]
+    fn pgn_round_trip() {
+        let text = "[Event \"Test\"]\n\n1. e4 e5 (1... c5 2.Nf3! {Open Sicilian} d6) 2. Nf3 $14 \
+            Nc6 ; the main line\n3. Bb5 a6 *";
+        let game = parse_pgn(text).unwrap();
+        assert_eq!(game.tag("Event"), Some("Test"));
+        let expected =
+            "1. e4 e5 (1... c5 2. Nf3 $1 {Open Sicilian} 2... d6) 2. Nf3 $14 Nc6 3. Bb5 a6 *";
+        assert_eq!(movetext(&game.tree), expected);
+
+        let again = parse_pgn(&game.tree.to_pgn_with_tags(&[])).unwrap();
+        assert_eq!(movetext(&again.tree), expected);
+    }
+
+    #[test]
+    fn annotations_before_the_first_move() {
+        let game = parse_pgn("$10 {A quiet opening} 1. d4 d5 {Symmetrical} *").unwrap();
+        assert_eq!(game.tree.nag(ROOT), Some(10));
+        assert_eq!(game.tree.comment(ROOT), Some("A quiet opening"));
+        let expected = "$10 {A quiet opening} 1. d4 d5 {Symmetrical} *";
+        assert_eq!(movetext(&game.tree), expected);
+    }
+
+    #[test]
+    fn pgn_from_a_setup() {
+        let fen = "bqnb2kr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BN1KR w Kk - 2 9";
+        let text = format!(
+            "[Variant \"Chess960\"]\n[SetUp \"1\"]\n[FEN \"{}\"]\n\n9. O-O e5 10. f5 *",
+            fen
+        );
+        let game = parse_pgn(&text).unwrap();
+        assert_eq!(game.tree.line(ROOT).len(), 3);
+
+        let pgn = game
+            .tree
+            .to_pgn_with_tags(&[("White", "Fischer".to_string())]);
+        assert!(pgn.contains("[Variant \"Chess960\"]"));
+        assert!(pgn.contains(&format!("[FEN \"{}\"]", fen)));
+        assert!(pgn.contains("[White \"Fischer\"]"));
+        assert!(pgn.contains("9. O-O e5 10. f5 *"));
+
+        let crazyhouse = parse_pgn("[Variant \"Crazyhouse\"]\n\n1. e4 d5 2. exd5 Qxd5 3. P@e4 *")
+            .unwrap()
+            .tree;
+        let end = *crazyhouse.line(ROOT).last().unwrap();
+        assert_eq!(crazyhouse.position(end).variant, VariantKind::Crazyhouse);
+        assert_eq!(crazyhouse.san(end), "P@e4");
+    }
+
+    #[test]
+    fn pgn_errors() {
+        assert!(parse_pgn("1. e4 e5 2. Ke3 *").is_err());
+        assert!(parse_pgn("1. e4 e5) 2. Nf3 *").is_err());
+        assert!(parse_pgn("(1. e4) *").is_err());
+        assert!(parse_pgn("[Variant \"Shogi\"]\n\n1. e4 *").is_err());
+    }
+
+    #[test]
+    fn split_games() {
+        let text = "[Event \"One\"]\n\n1. e4 *\n\n[Event \"Two\"]\n[Site \"?\"]\n\n1. d4\nd5 *\n";
+        let games = split_pgn(text);
+        assert_eq!(games.len(), 2);
+        assert_eq!(parse_pgn(&games[1]).unwrap().tag("Event"), Some("Two"));
+        assert_eq!(parse_pgn(&games[1]).unwrap().tree.line(ROOT).len(), 2);
+    }
+
+    #[test]
+    fn delete_nested_variation() {
+        let mut tree = parse_pgn("1. e4 e5 (1... c5 2. Nf3 (2. c3 d5) 2... d6) 2. Nf3 *")
+            .unwrap()
+            .tree;
+        let nested = node_after(&tree, &["e4", "c5", "c3", "d5"]);
+        let c3 = node_after(&tree, &["e4", "c5", "c3"]);
+        assert_eq!(tree.variation_start(nested), Some(c3));
+
+        assert!(tree.delete_variation(nested));
+        let c5 = node_after(&tree, &["e4", "c5"]);
+        assert_eq!(tree.current(), c5);
+        let sans: Vec<&str> = tree.children(c5).iter().map(|&n| tree.san(n)).collect();
+        assert_eq!(sans, ["Nf3"]);
+        assert_eq!(tree.children(node_after(&tree, &["e4"])).len(), 2);
+    }
+
+    /// A move played after taking one back continues the main line
+    #[test]
+    fn take_back() {
+        let mut tree = parse_pgn("1. e4 e5 2. Nf3 *").unwrap().tree;
+        tree.set_current(node_after(&tree, &["e4", "e5", "Nf3"]));
+        let e5 = node_after(&tree, &["e4", "e5"]);
+        assert_eq!(tree.take_back(), Some(e5));
+
+        let mut position = tree.position(e5).clone();
+        let mv = parse_move(&position, "Bc4").unwrap();
+        let san = move_to_san(&position, mv);
+        position.play(mv);
+        tree.add_move(mv, san, position);
+        assert_eq!(movetext(&tree), "1. e4 e5 2. Bc4 *");
+
+        tree.set_current(ROOT);
+        assert_eq!(tree.take_back(), None);
+    }
+
+    #[test]
+    fn main_line_is_not_a_variation() {
+        let mut tree = parse_pgn("1. e4 e5 (1... c5) 2. Nf3 *").unwrap().tree;
+        let nf3 = node_after(&tree, &["e4", "e5", "Nf3"]);
+        assert_eq!(tree.variation_start(nf3), None);
+        assert!(!tree.delete_variation(nf3));
+    }
+}
@@ -0,0 +1,200 @@
+use crate::{
+    accessibility::*, analysis::*, board::*, database::*, editor::*, events::*, notation::*,
+    pieces::*, position::*, puzzle::*,
+};
+use bevy::prelude::*;
+
+/// The square picked with the arrow keys, highlighted like the one under the mouse
+#[derive(Default)]
+pub struct KeyboardCursor {
+    pub square: Option<(u8, u8)>,
+}
+
+/// A move being typed in SAN or UCI notation, or a query starting with `?`: `?e4` tells
+/// what stands on e4 and a lone `?` lists the whole position
+#[derive(Default)]
+pub struct MoveInput {
+    pub text: String,
+    /// Why the last submitted move was rejected
+    pub error: Option<String>,
+}
+
+/// Characters that can appear in a SAN or UCI move. Letters used as shortcuts elsewhere,
+/// like T for themes, never show up in a move
+const MOVE_CHARACTERS: &str = "abcdefghij1234567890KQRBNPACMqrbnacmxO-=+#?@";
+
+pub struct KeyboardPlugin;
+impl Plugin for KeyboardPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<KeyboardCursor>()
+            .init_resource::<MoveInput>()
+            .add_system(move_keyboard_cursor.system())
+            .add_system(type_move.system())
+            .add_system(confirm_with_keyboard.system());
+    }
+}
+
+fn move_keyboard_cursor(
+    keyboard_inputs: Res<Input<KeyCode>>,
+    turn: Res<PlayerTurn>,
+    position: Res<Position>,
+    mut keyboard_cursor: ResMut<KeyboardCursor>,
+) {
+    // Up moves towards black's side of the board, right towards the h-file
+    let offset = if keyboard_inputs.just_pressed(KeyCode::Up) {
+        (1, 0)
+    } else if keyboard_inputs.just_pressed(KeyCode::Down) {
+        (-1, 0)
+    } else if keyboard_inputs.just_pressed(KeyCode::Right) {
+        (0, 1)
+    } else if keyboard_inputs.just_pressed(KeyCode::Left) {
+        (0, -1)
+    } else {
+        return;
+    };
+
+    keyboard_cursor.square = Some(match keyboard_cursor.square {
+        Some((x, y)) => (
+            (x as i8 + offset.0).max(0).min(position.ranks() as i8 - 1) as u8,
+            (y as i8 + offset.1).max(0).min(position.files() as i8 - 1) as u8,
+        ),
+        // The first key press puts the cursor on the middle file of the side to move
+        None => (position.back_rank(turn.color), position.files() / 2),
+    });
+}
+
+fn type_move(
+    keyboard_inputs: Res<Input<KeyCode>>,
+    browser: Res<DatabaseBrowser>,
+    mut received_characters: EventReader<ReceivedCharacter>,
+    mut move_input: ResMut<MoveInput>,
+) {
+    // The characters are read even while the browser has the keyboard, so what was typed
+    // into its search doesn't turn up in the move once it closes
+    for event in received_characters.iter() {
+        if !browser.open && MOVE_CHARACTERS.contains(event.char) {
+            move_input.text.push(event.char);
+            move_input.error = None;
+        }
+    }
+    if browser.open {
+        return;
+    }
+    if keyboard_inputs.just_pressed(KeyCode::Back) && move_input.text.pop().is_some() {
+        move_input.error = None;
+    }
+}
+
+/// Enter plays the typed move, or selects and moves with the keyboard cursor when nothing
+/// was typed. Esc clears the typed move, or else the selection
+fn confirm_with_keyboard(
+    keyboard_inputs: Res<Input<KeyCode>>,
+    position: Res<Position>,
+    turn: Res<PlayerTurn>,
+    analysis: Res<AnalysisMode>,
+    puzzles: Res<PuzzleMode>,
+    editor: Res<EditorMode>,
+    browser: Res<DatabaseBrowser>,
+    keyboard_cursor: Res<KeyboardCursor>,
+    mut move_input: ResMut<MoveInput>,
+    mut selected_square: ResMut<SelectedSquare>,
+    mut selected_piece: ResMut<SelectedPiece>,
+    mut move_requested_events: EventWriter<MoveRequested>,
+    mut drop_requested_events: EventWriter<DropRequested>,
+    mut announcements: EventWriter<Announcement>,
+    pieces_query: Query<(Entity, &Piece)>,
+) {
+    if browser.open {
+        return;
+    }
+    let piece_at = |square: (u8, u8)| {
+        pieces_query
+            .iter()
+            .find(|(_, piece)| (piece.x, piece.y) == square)
+    };
+
+    if keyboard_inputs.just_pressed(KeyCode::Escape) {
+        if move_input.text.is_empty() {
+            selected_square.square = None;
+            selected_piece.entity = None;
+        } else {
+            *move_input = MoveInput::default();
+        }
+        return;
+    }
+
+    if !keyboard_inputs.just_pressed(KeyCode::Return)
+        && !keyboard_inputs.just_pressed(KeyCode::NumpadEnter)
+    {
+        return;
+    }
+    if editor.active {
+        move_input.error = Some("Leave the setup (F5) to play".to_string());
+        return;
+    }
+
+    if let Some(query) = move_input.text.strip_prefix('?') {
+        let text = if query.is_empty() {
+            describe_position(&position)
+        } else {
+            match parse_square(query) {
+                Some(square) => describe_square(&position, square),
+                None => format!("Not a square: {}", query),
+            }
+        };
+        announcements.send(Announcement { text });
+        *move_input = MoveInput::default();
+        return;
+    }
+
+    if !move_input.text.is_empty() {
+        match parse_move(&position, &move_input.text) {
+            Ok(_) if !puzzles.may_move(position.turn) => {
+                move_input.error = Some("Wait for the opponent's move".to_string())
+            }
+            Ok(mv) => {
+                if let Some(piece_type) = mv.drop {
+                    drop_requested_events.send(DropRequested {
+                        color: position.turn,
+                        piece_type,
+                        to: mv.to,
+                    });
+                } else if let Some((entity, _)) = piece_at(mv.from) {
+                    move_requested_events.send(MoveRequested {
+                        entity,
+                        to: mv.to,
+                        promotion: mv.promotion,
+                    });
+                }
+                *move_input = MoveInput::default();
+                selected_square.square = None;
+                selected_piece.entity = None;
+            }
+            Err(err) => move_input.error = Some(err),
+        }
+        return;
+    }
+
+    let square = match keyboard_cursor.square {
+        Some(square) => square,
+        None => return,
+    };
+    match piece_at(square) {
+        Some((entity, piece))
+            if analysis.may_move(piece.color, &turn) && puzzles.may_move(piece.color) =>
+        {
+            selected_square.square = Some(square);
+            selected_piece.entity = Some(entity);
+        }
+        _ => {
+            if let Some(entity) = selected_piece.entity.take() {
+                move_requested_events.send(MoveRequested {
+                    entity,
+                    to: square,
+                    promotion: None,
+                });
+            }
+            selected_square.square = Some(square);
+        }
+    }
+}
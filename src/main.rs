@@ -1,14 +1,75 @@
 use bevy::prelude::*;
 use bevy_mod_picking::*;
+use rand::Rng;
 
+mod accessibility;
+mod analysis;
+mod animation;
+mod betza;
 mod board;
+mod captured;
+mod cli;
+mod database;
+mod eco;
+mod editor;
+mod engine;
+mod events;
+mod game_tree;
+mod keyboard;
+mod notation;
+mod opening_book;
+mod piece_set;
 mod pieces;
+mod pocket;
+mod position;
+mod puzzle;
+mod review;
+mod saves;
+mod sound;
+mod tablebase;
+mod theme;
 mod ui;
+mod variant;
+mod view2d;
+use accessibility::*;
+use analysis::*;
+use animation::*;
 use board::*;
+use captured::*;
+use database::*;
+use eco::*;
+use editor::*;
+use events::*;
+use game_tree::*;
+use keyboard::*;
+use notation::*;
+use opening_book::*;
+use piece_set::*;
 use pieces::*;
+use pocket::*;
+use position::*;
+use puzzle::*;
+use review::*;
+use saves::*;
+use sound::*;
+use tablebase::*;
+use theme::*;
 use ui::*;
+use variant::*;
+use view2d::*;
 
 fn main() {
+    // `cli`, `--cli` or `--headless` plays in the terminal, for machines without a display
+    if std::env::args().nth(1).as_deref() == Some("cli")
+        || std::env::args().any(|arg| arg == "--cli" || arg == "--headless")
+    {
+        let book = OpeningBook::load(&opening_book_settings()).ok();
+        let tablebase = Tablebase::load(&tablebase_settings()).ok();
+        let ascii = std::env::args().any(|arg| arg == "--ascii");
+        cli::run(starting_position(), book, tablebase, ascii);
+        return;
+    }
+
     App::build()
         .insert_resource(Msaa { samples: 4 })
         .insert_resource(WindowDescriptor {
@@ -17,18 +78,154 @@ fn main() {
             height: 1600.,
             ..Default::default()
         })
+        // Sound is played through `SoundPlugin` instead, since bevy's own audio output panics
+        // when there is no audio device and has no volume control
         .add_plugins_with(DefaultPlugins, |plugins| {
             plugins.disable::<bevy::audio::AudioPlugin>()
         })
+        .insert_resource(SoundSettings {
+            enabled: !std::env::args().any(|arg| arg == "--no-sound"),
+            ..Default::default()
+        })
+        .insert_resource(ReviewSettings {
+            enabled: !std::env::args().any(|arg| arg == "--no-review"),
+        })
+        .insert_resource(AccessibilitySettings {
+            stdout: std::env::args().any(|arg| arg == "--announce"),
+            speech_command: arg_value("--speech-command"),
+        })
+        .insert_resource(PieceSetSettings {
+            name: arg_value("--piece-set").unwrap_or_else(|| PieceSetSettings::default().name),
+        })
+        .insert_resource(opening_book_settings())
+        .insert_resource(tablebase_settings())
+        .insert_resource(PgnExportSettings {
+            path: arg_value("--pgn")
+                .map(Into::into)
+                .unwrap_or_else(|| PgnExportSettings::default().path),
+        })
+        .insert_resource(PuzzleSettings {
+            path: arg_value("--puzzles")
+                .map(Into::into)
+                .unwrap_or_else(|| PuzzleSettings::default().path),
+        })
+        .insert_resource(EditorSettings {
+            path: arg_value("--fen-file")
+                .map(Into::into)
+                .unwrap_or_else(|| EditorSettings::default().path),
+        })
+        .insert_resource(SaveSettings {
+            dir: arg_value("--saves")
+                .map(Into::into)
+                .unwrap_or_else(|| SaveSettings::default().dir),
+        })
+        .insert_resource(DatabaseSettings {
+            path: arg_value("--database")
+                .map(Into::into)
+                .unwrap_or_else(|| DatabaseSettings::default().path),
+            import: arg_value("--import").map(Into::into),
+        })
+        .insert_resource(starting_position())
         .init_resource::<PickingCamera>()
         .add_plugin(PickingPlugin)
+        .add_plugin(ThemePlugin)
+        .add_plugin(GameEventsPlugin)
         .add_plugin(BoardPlugin)
+        .add_plugin(View2dPlugin)
+        .add_plugin(KeyboardPlugin)
+        .add_plugin(AccessibilityPlugin)
         .add_plugin(PiecesPlugin)
+        .add_plugin(AnimationPlugin)
+        .add_plugin(CapturedPlugin)
+        .add_plugin(SoundPlugin)
+        .add_plugin(OpeningBookPlugin)
+        .add_plugin(TablebasePlugin)
+        .add_plugin(AnalysisPlugin)
+        .add_plugin(GameTreePlugin)
+        .add_plugin(EcoPlugin)
+        .add_plugin(PocketPlugin)
+        .add_plugin(PuzzlePlugin)
+        .add_plugin(EditorPlugin)
+        .add_plugin(SavePlugin)
+        .add_plugin(DatabasePlugin)
+        .add_plugin(ReviewPlugin)
         .add_plugin(UIPlugin)
         .add_startup_system(setup.system())
         .run();
 }
 
+/// The value following `name` on the command line, as in `--piece-set staunton`
+fn arg_value(name: &str) -> Option<String> {
+    let mut args = std::env::args().skip_while(|arg| arg != name);
+    args.next();
+    args.next()
+}
+
+/// The Polyglot book in `assets/books` named with `--book`
+fn opening_book_settings() -> OpeningBookSettings {
+    OpeningBookSettings {
+        name: arg_value("--book").unwrap_or_else(|| OpeningBookSettings::default().name),
+    }
+}
+
+/// Syzygy tables from the directory given with `--syzygy`
+fn tablebase_settings() -> TablebaseSettings {
+    TablebaseSettings {
+        path: arg_value("--syzygy")
+            .map(Into::into)
+            .unwrap_or_else(|| TablebaseSettings::default().path),
+    }
+}
+
+/// The position given with `--fen`, or the Chess960 setup numbered by `--chess960`
+/// (`--chess960 random` picks one), falling back to the start of the variant chosen with
+/// `--variant`, such as `--variant horde`. A position the variant can't be played from
+/// falls back to the start as well
+fn starting_position() -> Position {
+    let variant = match arg_value("--variant") {
+        Some(name) => VariantKind::from_name(&name).unwrap_or_else(|| {
+            warn!("Unknown variant {:?}, playing standard chess", name);
+            VariantKind::Standard
+        }),
+        None => VariantKind::Standard,
+    };
+
+    let mut position = None;
+    if let Some(fen) = arg_value("--fen") {
+        match parse_fen(&fen) {
+            Ok(fen_position) => position = Some(fen_position),
+            Err(error) => warn!("Ignoring FEN {:?}: {}", fen, error),
+        }
+    }
+    if let (None, Some(index)) = (&position, arg_value("--chess960")) {
+        match index.parse::<u16>() {
+            Ok(index) if index < 960 => position = Some(Position::chess960(index)),
+            _ if index == "random" => {
+                position = Some(Position::chess960(rand::thread_rng().gen_range(0..960)))
+            }
+            _ => warn!(
+                "Ignoring Chess960 position {:?}, expected 0-959 or random",
+                index
+            ),
+        }
+    }
+
+    match position {
+        Some(mut position) => {
+            position.variant = variant;
+            // The same check the setup editor makes before play starts
+            match position.check_setup() {
+                Ok(()) => position,
+                Err(error) => {
+                    warn!("Can't play from the position given: {}", error);
+                    Position::variant_start(variant)
+                }
+            }
+        }
+        None => Position::variant_start(variant),
+    }
+}
+
 fn setup(mut commands: Commands) {
     commands
         .spawn_bundle(PerspectiveCameraBundle {
@@ -0,0 +1,721 @@
+use crate::{pieces::*, position::*};
+
+pub fn square_name(square: (u8, u8)) -> String {
+    format!("{}{}", (b'a' + square.1) as char, square.0 + 1)
+}
+
+/// A file letter followed by the rank, which takes two digits on a board with ten ranks
+pub fn parse_square(text: &str) -> Option<(u8, u8)> {
+    let mut chars = text.chars();
+    let file = file_from_char(chars.next()?)?;
+    let rank = rank_from_digits(chars.as_str())?;
+    Some((rank, file))
+}
+
+fn file_from_char(c: char) -> Option<u8> {
+    if c.is_ascii_lowercase() && (c as u8 - b'a') < MAX_BOARD_SIZE {
+        Some(c as u8 - b'a')
+    } else {
+        None
+    }
+}
+
+fn rank_from_digits(digits: &str) -> Option<u8> {
+    if digits.is_empty() || digits.starts_with('0') || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    match digits.parse::<u8>() {
+        Ok(rank) if rank <= MAX_BOARD_SIZE => Some(rank - 1),
+        _ => None,
+    }
+}
+
+/// Letter of a piece in English algebraic notation, `P` for pawns
+pub fn piece_letter(piece_type: PieceType) -> char {
+    piece_type.letter()
+}
+
+pub fn piece_from_letter(c: char) -> Option<PieceType> {
+    let c = c.to_ascii_uppercase();
+    PieceType::ALL
+        .iter()
+        .copied()
+        .find(|piece_type| piece_type.letter() == c)
+}
+
+/// Long algebraic notation as used by UCI, e.g. `g1f3`, `e7e8q` or the drop `N@f3`.
+/// Castling is the king's move, `e1g1`, except in Chess960, where the king may not move
+/// at all and castling is written as the king taking its own rook, e.g. `b1a1`
+pub fn move_to_uci(position: &Position, mv: Move) -> String {
+    if let Some(dropped) = mv.drop {
+        return drop_text(dropped, mv.to);
+    }
+    let to = match position.castling_rook(mv) {
+        Some((rook_from, _)) if position.is_chess960() => rook_from,
+        _ => mv.to,
+    };
+    let mut uci = format!("{}{}", square_name(mv.from), square_name(to));
+    if let Some(promotion) = mv.promotion {
+        uci.push(piece_letter(promotion).to_ascii_lowercase());
+    }
+    uci
+}
+
+fn drop_text(piece_type: PieceType, to: (u8, u8)) -> String {
+    format!("{}@{}", piece_letter(piece_type), square_name(to))
+}
+
+/// Standard algebraic notation for a legal move in `position`, e.g. `Nf3`, `exd5`, `O-O+`
+/// or the drop `N@f3`
+pub fn move_to_san(position: &Position, mv: Move) -> String {
+    let mut san = if let Some(dropped) = mv.drop {
+        drop_text(dropped, mv.to)
+    } else if mv.castling {
+        if position.castling_side(mv) == KING_SIDE {
+            "O-O".to_string()
+        } else {
+            "O-O-O".to_string()
+        }
+    } else {
+        let piece_type = match position.piece_at(mv.from) {
+            Some(piece) => piece.piece_type,
+            None => return move_to_uci(position, mv),
+        };
+        let capture = position.captured_square(mv).is_some();
+        let mut san = String::new();
+
+        if piece_type == PieceType::Pawn {
+            if capture {
+                san.push((b'a' + mv.from.1) as char);
+            }
+        } else {
+            san.push(piece_letter(piece_type));
+
+            // Name the file, the rank or both when another piece of the same kind could
+            // also move there
+            let rivals: Vec<Move> = position
+                .legal_moves()
+                .into_iter()
+                .filter(|other| {
+                    other.to == mv.to
+                        && other.from != mv.from
+                        && !other.castling
+                        && position.piece_at(other.from).map(|piece| piece.piece_type)
+                            == Some(piece_type)
+                })
+                .collect();
+            if !rivals.is_empty() {
+                let from = square_name(mv.from);
+                if rivals.iter().all(|other| other.from.1 != mv.from.1) {
+                    san.push_str(&from[..1]);
+                } else if rivals.iter().all(|other| other.from.0 != mv.from.0) {
+                    san.push_str(&from[1..]);
+                } else {
+                    san.push_str(&from);
+                }
+            }
+        }
+
+        if capture {
+            san.push('x');
+        }
+        san.push_str(&square_name(mv.to));
+        if let Some(promotion) = mv.promotion {
+            san.push('=');
+            san.push(piece_letter(promotion));
+        }
+        san
+    };
+
+    let mut after = position.clone();
+    after.play(mv);
+    if after.is_in_check(after.turn) {
+        san.push(if after.legal_moves().is_empty() {
+            '#'
+        } else {
+            '+'
+        });
+    }
+    san
+}
+
+/// Reads a move typed in SAN (`Nf3`, `exd5`, `e8=Q`, `O-O`), UCI long algebraic
+/// notation (`g1f3`, `e7e8q`) or a drop (`N@f3`, `@e4`) and finds the legal move it
+/// stands for
+pub fn parse_move(position: &Position, text: &str) -> Result<Move, String> {
+    let text = text.trim().trim_end_matches(|c| "+#!?".contains(c));
+    if text.is_empty() {
+        return Err("No move given".to_string());
+    }
+    let legal_moves = position.legal_moves();
+
+    if let Some(mv) = parse_uci(text) {
+        // The king taking its own rook is castling. Otherwise a plain move between the two
+        // squares comes before castling that shares them, as it may in Chess960
+        let preference = |legal: &Move| {
+            let rook_from = position
+                .castling_rook(*legal)
+                .map(|(rook_from, _)| rook_from);
+            if legal.from != mv.from {
+                None
+            } else if rook_from == Some(mv.to) {
+                Some(0)
+            } else if legal.to == mv.to && legal.promotion == mv.promotion {
+                Some(if legal.castling { 2 } else { 1 })
+            } else {
+                None
+            }
+        };
+        return legal_moves
+            .into_iter()
+            .filter_map(|legal| preference(&legal).map(|rank| (rank, legal)))
+            .min_by_key(|&(rank, _)| rank)
+            .map(|(_, legal)| legal)
+            .ok_or_else(|| format!("Illegal move: {}", text));
+    }
+
+    if let Some(at) = text.find('@') {
+        let piece_type = match &text[..at] {
+            "" => PieceType::Pawn,
+            letter => letter
+                .chars()
+                .next()
+                .filter(|_| letter.len() == 1)
+                .and_then(piece_from_letter)
+                .ok_or_else(|| format!("Unknown piece: {}", letter))?,
+        };
+        let to = parse_square(&text[at + 1..])
+            .ok_or_else(|| format!("Not a square: {}", &text[at + 1..]))?;
+        return legal_moves
+            .into_iter()
+            .find(|legal| legal.drop == Some(piece_type) && legal.to == to)
+            .ok_or_else(|| format!("Illegal drop: {}", text));
+    }
+
+    let castling = match text {
+        "O-O" | "0-0" => Some(KING_SIDE),
+        "O-O-O" | "0-0-0" => Some(QUEEN_SIDE),
+        _ => None,
+    };
+    if let Some(side) = castling {
+        return legal_moves
+            .into_iter()
+            .find(|legal| legal.castling && position.castling_side(*legal) == side)
+            .ok_or_else(|| format!("Cannot castle: {}", text));
+    }
+
+    // What's left looks like [piece][file][rank][x]square[=promotion]
+    let mut chars: Vec<char> = text.chars().filter(|&c| c != 'x' && c != '=').collect();
+    let piece_type = match chars.first() {
+        Some(&c) if c.is_ascii_uppercase() => {
+            chars.remove(0);
+            piece_from_letter(c).ok_or_else(|| format!("Unknown piece: {}", c))?
+        }
+        _ => PieceType::Pawn,
+    };
+    let promotion = match chars.last() {
+        Some(&c) if piece_type == PieceType::Pawn && c.is_ascii_alphabetic() && chars.len() > 2 => {
+            chars.pop();
+            Some(piece_from_letter(c).ok_or_else(|| format!("Unknown piece: {}", c))?)
+        }
+        _ => None,
+    };
+    // The target square is the last letter and the digits after it
+    let rank_digits = chars
+        .iter()
+        .rev()
+        .take_while(|c| c.is_ascii_digit())
+        .count();
+    if rank_digits == 0 || chars.len() <= rank_digits {
+        return Err(format!("Not a move: {}", text));
+    }
+    let to: String = chars
+        .split_off(chars.len() - rank_digits - 1)
+        .into_iter()
+        .collect();
+    let to = parse_square(&to).ok_or_else(|| format!("Not a square: {}", to))?;
+
+    let mut from_file = None;
+    let mut from_rank_digits = String::new();
+    for c in chars {
+        if let Some(file) = file_from_char(c) {
+            from_file = Some(file);
+        } else if c.is_ascii_digit() {
+            from_rank_digits.push(c);
+        } else {
+            return Err(format!("Not a move: {}", text));
+        }
+    }
+    let from_rank = match from_rank_digits.as_str() {
+        "" => None,
+        digits => Some(rank_from_digits(digits).ok_or_else(|| format!("Not a rank: {}", digits))?),
+    };
+
+    let matches = |legal: &Move| {
+        !legal.castling
+            && legal.to == to
+            && position.piece_at(legal.from).map(|piece| piece.piece_type) == Some(piece_type)
+            && from_file.map_or(true, |file| legal.from.1 == file)
+            && from_rank.map_or(true, |rank| legal.from.0 == rank)
+    };
+    let candidates: Vec<Move> = legal_moves
+        .iter()
+        .copied()
+        .filter(|legal| matches(legal) && legal.promotion == promotion)
+        .collect();
+    match candidates.as_slice() {
+        [mv] => Ok(*mv),
+        [] if promotion.is_none() && legal_moves.iter().any(matches) => Err(format!(
+            "Say what the pawn becomes, as in {}=Q",
+            square_name(to)
+        )),
+        [] => Err(format!("Illegal move: {}", text)),
+        _ => Err(format!("Ambiguous move: {}", text)),
+    }
+}
+
+fn parse_uci(text: &str) -> Option<Move> {
+    if !text.is_ascii() {
+        return None;
+    }
+    // Squares may be two or three characters long, so split them after the rank digits
+    fn split_square(text: &str) -> Option<((u8, u8), &str)> {
+        let end = text
+            .char_indices()
+            .skip(1)
+            .find(|&(_, c)| !c.is_ascii_digit())
+            .map_or(text.len(), |(end, _)| end);
+        parse_square(&text[..end]).map(|square| (square, &text[end..]))
+    }
+    let (from, rest) = split_square(text)?;
+    let (to, rest) = split_square(rest)?;
+    let mut rest = rest.chars();
+    let promotion = match (rest.next(), rest.next()) {
+        (Some(c), None) => Some(piece_from_letter(c)?),
+        (None, _) => None,
+        _ => return None,
+    };
+    Some(Move {
+        from,
+        to,
+        promotion,
+        castling: false,
+        drop: None,
+    })
+}
+
+/// Forsyth-Edwards Notation of a position, e.g. the start position is
+/// `rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1`. Castling rights with a rook
+/// that isn't the outermost on its side are written by file, as X-FEN does for Chess960
+pub fn position_to_fen(position: &Position) -> String {
+    fen(position, false)
+}
+
+/// FEN with every castling right written as the file of its rook, e.g. `HAha`, as
+/// Shredder-FEN does for Chess960
+pub fn position_to_shredder_fen(position: &Position) -> String {
+    fen(position, true)
+}
+
+fn fen(position: &Position, shredder: bool) -> String {
+    let mut ranks = Vec::new();
+    for rank in (0..position.ranks()).rev() {
+        let mut text = String::new();
+        let mut empty = 0;
+        for file in 0..position.files() {
+            match position.piece_at((rank, file)) {
+                Some(piece) => {
+                    if empty > 0 {
+                        text.push_str(&empty.to_string());
+                        empty = 0;
+                    }
+                    text.push(colored_letter(piece.color, piece_letter(piece.piece_type)));
+                    if position.is_promoted((rank, file)) {
+                        text.push('~');
+                    }
+                }
+                None => empty += 1,
+            }
+        }
+        if empty > 0 {
+            text.push_str(&empty.to_string());
+        }
+        ranks.push(text);
+    }
+
+    let mut castling = String::new();
+    for &color in [PieceColor::White, PieceColor::Black].iter() {
+        let rank = position.back_rank(color);
+        let rights = position.castling[color_index(color)];
+
+        for &side in [KING_SIDE, QUEEN_SIDE].iter() {
+            let rook_file = match rights[side] {
+                Some(rook_file) => rook_file,
+                None => continue,
+            };
+            // Another rook further out on the same side would make K or Q ambiguous
+            let outer_files: Vec<u8> = if side == KING_SIDE {
+                (rook_file + 1..position.files()).collect()
+            } else {
+                (0..rook_file).collect()
+            };
+            let outermost = !outer_files.iter().any(|&file| {
+                position.piece_at((rank, file)).map_or(false, |piece| {
+                    piece.color == color && piece.piece_type == PieceType::Rook
+                })
+            });
+
+            let letter = if shredder || !outermost {
+                (b'A' + rook_file) as char
+            } else if side == KING_SIDE {
+                'K'
+            } else {
+                'Q'
+            };
+            castling.push(colored_letter(color, letter));
+        }
+    }
+    if castling.is_empty() {
+        castling.push('-');
+    }
+
+    // Crazyhouse adds the pockets after the board, as in `[Qn]`
+    let mut board = ranks.join("/");
+    if position.rules().has_drops() {
+        board.push('[');
+        for &color in [PieceColor::White, PieceColor::Black].iter() {
+            let pocket = position.pockets[color_index(color)];
+            for &piece_type in Pocket::PIECE_TYPES.iter() {
+                for _ in 0..pocket.count(piece_type) {
+                    board.push(colored_letter(color, piece_letter(piece_type)));
+                }
+            }
+        }
+        board.push(']');
+    }
+
+    format!(
+        "{} {} {} {} {} {}",
+        board,
+        match position.turn {
+            PieceColor::White => 'w',
+            PieceColor::Black => 'b',
+        },
+        castling,
+        position
+            .en_passant
+            .map_or_else(|| "-".to_string(), square_name),
+        position.halfmove_clock,
+        position.fullmove_number
+    )
+}
+
+/// Upper case for white, lower case for black
+fn colored_letter(color: PieceColor, letter: char) -> char {
+    match color {
+        PieceColor::White => letter.to_ascii_uppercase(),
+        PieceColor::Black => letter.to_ascii_lowercase(),
+    }
+}
+
+/// Reads a position in FEN. Castling rights may be given as `KQkq`, by rook file as in
+/// Shredder-FEN (`HAha`), or mixed as in X-FEN. The move counters may be left out
+pub fn parse_fen(text: &str) -> Result<Position, String> {
+    let fields: Vec<&str> = text.split_whitespace().collect();
+    if fields.len() < 4 {
+        return Err(format!("Not a FEN: {}", text));
+    }
+    // Crazyhouse pockets follow the board in brackets
+    let (board, pockets) = match fields[0].find('[') {
+        Some(bracket) => (
+            &fields[0][..bracket],
+            fields[0][bracket + 1..].trim_end_matches(']'),
+        ),
+        None => (fields[0], ""),
+    };
+
+    // The board's size is read from the FEN itself, as every rank has to be as long as
+    // the first
+    let mut rows: Vec<Vec<Option<(PieceColor, PieceType, bool)>>> = Vec::new();
+    for rank_text in board.split('/') {
+        let mut row = Vec::new();
+        let mut chars = rank_text.chars().peekable();
+        while let Some(c) = chars.next() {
+            // Ten empty squares are written as 10
+            if let Some(mut skip) = c.to_digit(10) {
+                while let Some(digit) = chars.peek().and_then(|c| c.to_digit(10)) {
+                    skip = skip.saturating_mul(10).saturating_add(digit);
+                    chars.next();
+                }
+                if row.len() + skip as usize > MAX_BOARD_SIZE as usize {
+                    return Err(format!("Rank too long: {}", rank_text));
+                }
+                row.extend((0..skip).map(|_| None));
+                continue;
+            }
+            // A promoted piece, which goes back into a Crazyhouse pocket as a pawn
+            if c == '~' {
+                if let Some(Some((_, _, promoted))) = row.last_mut() {
+                    *promoted = true;
+                }
+                continue;
+            }
+            let piece_type = piece_from_letter(c).ok_or_else(|| format!("Unknown piece: {}", c))?;
+            if row.len() >= MAX_BOARD_SIZE as usize {
+                return Err(format!("Rank too long: {}", rank_text));
+            }
+            let color = if c.is_ascii_uppercase() {
+                PieceColor::White
+            } else {
+                PieceColor::Black
+            };
+            row.push(Some((color, piece_type, false)));
+        }
+        if !rows.is_empty() && row.len() != rows[0].len() {
+            return Err(format!("Rank of the wrong length: {}", rank_text));
+        }
+        rows.push(row);
+    }
+    let size_allowed =
+        |size: usize| (MIN_BOARD_SIZE as usize..=MAX_BOARD_SIZE as usize).contains(&size);
+    if !size_allowed(rows.len()) || !size_allowed(rows[0].len()) {
+        return Err(format!(
+            "Boards go from {0}x{0} to {1}x{1}: {2}",
+            MIN_BOARD_SIZE, MAX_BOARD_SIZE, board
+        ));
+    }
+
+    let mut position = Position::empty_board(rows.len() as u8, rows[0].len() as u8);
+    for (i, row) in rows.iter().enumerate() {
+        let rank = position.ranks() - 1 - i as u8;
+        for (file, &square) in row.iter().enumerate() {
+            if let Some((color, piece_type, promoted)) = square {
+                position.set((rank, file as u8), Some((color, piece_type)));
+                position.set_promoted((rank, file as u8), promoted);
+            }
+        }
+    }
+
+    for c in pockets.chars() {
+        let piece_type = piece_from_letter(c).ok_or_else(|| format!("Unknown piece: {}", c))?;
+        let color = if c.is_ascii_uppercase() {
+            PieceColor::White
+        } else {
+            PieceColor::Black
+        };
+        position.pockets[color_index(color)].add(piece_type);
+    }
+
+    position.turn = match fields[1] {
+        "w" => PieceColor::White,
+        "b" => PieceColor::Black,
+        turn => return Err(format!("Unknown side to move: {}", turn)),
+    };
+
+    if fields[2] != "-" {
+        for c in fields[2].chars() {
+            let color = if c.is_ascii_uppercase() {
+                PieceColor::White
+            } else {
+                PieceColor::Black
+            };
+            let rank = position.back_rank(color);
+            let king_file = match position.king_square(color) {
+                Some((king_rank, king_file)) if king_rank == rank => king_file,
+                _ => return Err(format!("Castling without a king on the back rank: {}", c)),
+            };
+            let is_own_rook = |file: u8| {
+                position.piece_at((rank, file)).map_or(false, |piece| {
+                    piece.color == color && piece.piece_type == PieceType::Rook
+                })
+            };
+
+            let rook_file = match c.to_ascii_uppercase() {
+                'K' => (king_file + 1..position.files())
+                    .rev()
+                    .find(|&file| is_own_rook(file)),
+                'Q' => (0..king_file).find(|&file| is_own_rook(file)),
+                letter @ 'A'..='J' => Some(letter as u8 - b'A')
+                    .filter(|&file| file < position.files() && is_own_rook(file)),
+                _ => return Err(format!("Unknown castling right: {}", c)),
+            }
+            .ok_or_else(|| format!("No rook to castle with: {}", c))?;
+            let side = if rook_file > king_file {
+                KING_SIDE
+            } else {
+                QUEEN_SIDE
+            };
+            position.castling[color_index(color)][side] = Some(rook_file);
+        }
+    }
+
+    position.en_passant = match fields[3] {
+        "-" => None,
+        text => {
+            let square = parse_square(text).ok_or_else(|| format!("Not a square: {}", text))?;
+            // The square skipped by a double step of the side that just moved: the third
+            // rank for white, the sixth for black on a board of eight
+            let skipped_rank = match position.turn {
+                PieceColor::White => position.ranks() - 3,
+                PieceColor::Black => 2,
+            };
+            if square.0 != skipped_rank || square.1 >= position.files() {
+                return Err(format!("Not an en passant square: {}", text));
+            }
+            Some(square)
+        }
+    };
+    if let Some(halfmove_clock) = fields.get(4) {
+        position.halfmove_clock = halfmove_clock
+            .parse()
+            .map_err(|_| format!("Not a number: {}", halfmove_clock))?;
+    }
+    if let Some(fullmove_number) = fields.get(5) {
+        position.fullmove_number = fullmove_number
+            .parse()
+            .map_err(|_| format!("Not a number: {}", fullmove_number))?;
+    }
+    Ok(position)
+}
+
+/// A line of moves in SAN with move numbers, e.g. `1. e4 e5 2. Nf3`, or `3... Nc6 4. Bb5`
+/// when it starts with black
+pub fn line_to_san(position: &Position, moves: &[Move]) -> String {
+    let mut position = position.clone();
+    let mut words = Vec::new();
+    for (i, &mv) in moves.iter().enumerate() {
+        match position.turn {
+            PieceColor::White => words.push(format!("{}.", position.fullmove_number)),
+            PieceColor::Black if i == 0 => words.push(format!("{}...", position.fullmove_number)),
+            PieceColor::Black => (),
+        }
+        words.push(move_to_san(&position, mv));
+        position.play(mv);
+    }
+    words.join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::variant::*;
+
+    const KIWIPETE: &str = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+
+    #[test]
+    fn fen_round_trip() {
+        let fens = [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            KIWIPETE,
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 b - - 12 40",
+            // X-FEN names the rook by its file when another one stands further out
+            "rk2r3/8/8/8/8/8/8/RK2R2R w EQk - 0 1",
+            "rnabqkbcnr/pppppppppp/10/10/10/10/PPPPPPPPPP/RNABQKBCNR w KQkq - 0 1",
+        ];
+        for &fen in fens.iter() {
+            assert_eq!(position_to_fen(&parse_fen(fen).unwrap()), fen);
+        }
+
+        let shredder = "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9";
+        let position = parse_fen(shredder).unwrap();
+        assert_eq!(position_to_shredder_fen(&position), shredder);
+        assert_eq!(
+            position_to_fen(&position),
+            "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w KQkq - 2 9"
+        );
+
+        let crazyhouse =
+            "r1bqk2r/pppp1ppp/2n1p3/4P3/1b1Pn3/2NB1N2/PPP2PPP/R1BQ~K2R[Pn] b KQkq - 0 6";
+        let mut position = parse_fen(crazyhouse).unwrap();
+        position.variant = VariantKind::Crazyhouse;
+        assert_eq!(position_to_fen(&position), crazyhouse);
+    }
+
+    #[test]
+    fn fen_errors() {
+        let fens = [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBXR w KQkq - 0 1",
+            "rnbqkbnr/pppppppp/9/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            // En passant squares off the rank a double step skips
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq a1 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR b KQkq e6 0 1",
+            "rnabqkbcnr/pppppppppp/10/10/10/10/PPPPPPPPPP/RNABQKBCNR b KQkq j10 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq j6 0 1",
+        ];
+        for &fen in fens.iter() {
+            assert!(parse_fen(fen).is_err(), "{}", fen);
+        }
+    }
+
+    /// Every legal move written in SAN reads back as the same move
+    #[test]
+    fn san_round_trip() {
+        let fens = [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            KIWIPETE,
+            // Promotions, with and without capturing
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 b kq - 0 1",
+            // Knights and rooks that need their file, rank or both to tell them apart
+            "4k3/8/8/2N1N3/1R5R/2N1N3/8/4K3 w - - 0 1",
+            // Chess960 castling where the king doesn't move
+            "bqnb2kr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BN1KR w Hh - 2 9",
+            "rnabqkbcnr/pppppppppp/10/10/10/10/PPPPPPPPPP/RNABQKBCNR w KQkq - 0 1",
+        ];
+        for &fen in fens.iter() {
+            let position = parse_fen(fen).unwrap();
+            for mv in position.legal_moves() {
+                let san = move_to_san(&position, mv);
+                assert_eq!(parse_move(&position, &san), Ok(mv), "{} in {}", san, fen);
+            }
+        }
+
+        let position = parse_fen(fens[4]).unwrap();
+        assert!(position
+            .legal_moves()
+            .iter()
+            .any(|mv| mv.castling && mv.from == mv.to));
+
+        let mut position = parse_fen("4k3/8/8/8/8/8/8/4K3[Nq] w - - 0 1").unwrap();
+        position.variant = VariantKind::Crazyhouse;
+        for mv in position.legal_moves() {
+            let san = move_to_san(&position, mv);
+            assert_eq!(parse_move(&position, &san), Ok(mv), "{}", san);
+        }
+    }
+
+    #[test]
+    fn uci() {
+        let fens = [
+            KIWIPETE,
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 b kq - 0 1",
+            "bqnb2kr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BN1KR w Hh - 2 9",
+            "1r1bkqbr/pppp1ppp/2nnp3/8/2P5/N4P2/PP1PP1PP/1RNBKQBR w Hh - 0 9",
+        ];
+        for &fen in fens.iter() {
+            let position = parse_fen(fen).unwrap();
+            for mv in position.legal_moves() {
+                let uci = move_to_uci(&position, mv);
+                assert_eq!(parse_move(&position, &uci), Ok(mv), "{} in {}", uci, fen);
+            }
+        }
+
+        // The king's move in standard chess, the king taking the rook in Chess960
+        let castle = |fen: &str| {
+            let position = parse_fen(fen).unwrap();
+            let mv = parse_move(&position, "O-O").unwrap();
+            move_to_uci(&position, mv)
+        };
+        assert_eq!(castle(KIWIPETE), "e1g1");
+        assert_eq!(castle(fens[2]), "g1h1");
+
+        // Either form is understood, and a plain king move keeps its squares
+        let position = parse_fen(KIWIPETE).unwrap();
+        assert!(parse_move(&position, "e1h1").unwrap().castling);
+        assert!(parse_move(&position, "e1g1").unwrap().castling);
+        let position = parse_fen("4k3/8/8/8/8/8/8/R4K1R w HA - 0 1").unwrap();
+        assert!(!parse_move(&position, "f1g1").unwrap().castling);
+        assert!(parse_move(&position, "f1h1").unwrap().castling);
+    }
+}
@@ -0,0 +1,162 @@
+use crate::pieces::*;
+use bevy::{asset::FileAssetIo, prelude::*};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// Manifests describing the available 3D chess sets, one `.ron` file per set
+const PIECE_SETS_DIR: &str = "assets/piece_sets";
+
+/// Used whenever the requested manifest is missing or broken
+const DEFAULT_PIECE_SET: &str = include_str!("../assets/piece_sets/chess_kit.ron");
+
+#[derive(Deserialize)]
+struct PiecePartDescriptor {
+    /// Asset path of the mesh, e.g. `models/chess_kit/pieces.glb#Mesh0/Primitive0`
+    mesh: String,
+    /// In board units, not affected by `scale`
+    #[serde(default)]
+    offset: [f32; 3],
+    #[serde(default)]
+    scale: Option<f32>,
+    /// Rotation around the vertical axis, in degrees
+    #[serde(default)]
+    rotation: f32,
+}
+
+#[derive(Deserialize)]
+struct PieceSetManifest {
+    name: String,
+    /// Scale applied to every part that doesn't set its own
+    scale: f32,
+    pieces: HashMap<PieceType, Vec<PiecePartDescriptor>>,
+}
+
+pub struct PieceSetSettings {
+    /// File name of the manifest in `assets/piece_sets`, without the extension
+    pub name: String,
+}
+impl Default for PieceSetSettings {
+    fn default() -> Self {
+        Self {
+            name: "chess_kit".to_string(),
+        }
+    }
+}
+
+struct PiecePart {
+    mesh: Handle<Mesh>,
+    transform: Transform,
+}
+
+/// Mesh handles for every piece type, shared by the pieces on the board and the captured
+/// pieces tray
+pub struct PieceSet {
+    parts: HashMap<PieceType, Vec<PiecePart>>,
+}
+impl PieceSet {
+    pub fn load(settings: &PieceSetSettings, asset_server: &AssetServer) -> Self {
+        let path = FileAssetIo::get_root_path()
+            .join(PIECE_SETS_DIR)
+            .join(format!("{}.ron", settings.name));
+
+        let manifest = std::fs::read_to_string(&path)
+            .map_err(|err| err.to_string())
+            .and_then(|contents| PieceSetManifest::parse(&contents));
+        let manifest = match manifest {
+            Ok(manifest) => manifest,
+            Err(err) => {
+                warn!(
+                    "Could not load piece set {}, using the default one: {}",
+                    path.display(),
+                    err
+                );
+                PieceSetManifest::parse(DEFAULT_PIECE_SET).expect("default piece set is valid")
+            }
+        };
+
+        info!("Using piece set {}", manifest.name);
+        let default_scale = manifest.scale;
+        let parts = manifest
+            .pieces
+            .into_iter()
+            .map(|(piece_type, descriptors)| {
+                let parts = descriptors
+                    .into_iter()
+                    .map(|descriptor| PiecePart {
+                        mesh: asset_server.load(descriptor.mesh.as_str()),
+                        transform: Transform {
+                            translation: descriptor.offset.into(),
+                            rotation: Quat::from_rotation_y(descriptor.rotation.to_radians()),
+                            scale: Vec3::splat(descriptor.scale.unwrap_or(default_scale)),
+                        },
+                    })
+                    .collect();
+                (piece_type, parts)
+            })
+            .collect();
+
+        Self { parts }
+    }
+}
+
+impl PieceSetManifest {
+    fn parse(contents: &str) -> Result<Self, String> {
+        let manifest: PieceSetManifest =
+            ron::de::from_str(contents).map_err(|err| err.to_string())?;
+
+        for piece_type in PieceType::STANDARD.iter() {
+            if manifest
+                .pieces
+                .get(piece_type)
+                .map_or(true, |parts| parts.is_empty())
+            {
+                return Err(format!("no meshes for {:?}", piece_type));
+            }
+        }
+        Ok(manifest)
+    }
+}
+
+/// How far apart the stand-in models of a fairy piece stand, and how much they shrink
+const STAND_IN_SPACING: f32 = 0.25;
+const STAND_IN_SCALE: f32 = 0.75;
+
+/// Spawns the meshes of a piece as children of `parent`. Fairy pieces the set has no
+/// models for are shown as their standard parts side by side, e.g. a bishop and a knight
+/// for an archbishop
+pub fn spawn_piece_model(
+    parent: &mut ChildBuilder,
+    piece_set: &PieceSet,
+    material: Handle<StandardMaterial>,
+    piece_type: PieceType,
+) {
+    let stand_ins = piece_type.stand_ins();
+    if piece_set.parts.contains_key(&piece_type) || stand_ins.is_empty() {
+        for part in piece_set.parts.get(&piece_type).into_iter().flatten() {
+            parent.spawn_bundle(PbrBundle {
+                mesh: part.mesh.clone(),
+                material: material.clone(),
+                transform: part.transform,
+                ..Default::default()
+            });
+        }
+        return;
+    }
+
+    let first = -(stand_ins.len() as f32 - 1.) / 2.;
+    for (i, &stand_in) in stand_ins.iter().enumerate() {
+        let offset = (first + i as f32) * STAND_IN_SPACING;
+        parent
+            .spawn_bundle(PbrBundle {
+                transform: Transform {
+                    translation: Vec3::new(0., 0., offset),
+                    scale: Vec3::splat(STAND_IN_SCALE),
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .with_children(|parent| {
+                spawn_piece_model(parent, piece_set, material.clone(), stand_in);
+            });
+    }
+}
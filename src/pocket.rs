@@ -0,0 +1,165 @@
+use crate::{
+    analysis::*, board::*, editor::*, events::*, notation::*, pieces::*, position::*, puzzle::*,
+};
+use bevy::prelude::*;
+
+/// Pockets are only shown in variants with drops
+struct PocketPanel;
+
+/// Picks a piece from a pocket for dropping
+struct PocketButton {
+    color: PieceColor,
+    piece_type: PieceType,
+}
+
+pub struct PocketPlugin;
+impl Plugin for PocketPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_startup_system(init_pocket_panel.system())
+            .add_system(pocket_panel_update.system())
+            .add_system(pocket_buttons.system());
+    }
+}
+
+fn init_pocket_panel(mut commands: Commands, mut color_materials: ResMut<Assets<ColorMaterial>>) {
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                display: Display::None,
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    right: Val::Px(10.),
+                    bottom: Val::Px(200.),
+                    ..Default::default()
+                },
+                flex_direction: FlexDirection::ColumnReverse,
+                align_items: AlignItems::FlexEnd,
+                ..Default::default()
+            },
+            material: color_materials.add(Color::NONE.into()),
+            ..Default::default()
+        })
+        .insert(PocketPanel);
+}
+
+/// One row per side, with a button for each kind of piece in the pocket, e.g. "N x2".
+/// The piece picked for dropping is highlighted
+fn pocket_panel_update(
+    mut commands: Commands,
+    position: Res<Position>,
+    selected_drop: Res<SelectedDrop>,
+    asset_server: Res<AssetServer>,
+    mut color_materials: ResMut<Assets<ColorMaterial>>,
+    mut panel_query: Query<(Entity, &mut Style, Option<&Children>), With<PocketPanel>>,
+) {
+    if !position.is_changed() && !selected_drop.is_changed() {
+        return;
+    }
+    let (panel, mut style, children) = match panel_query.iter_mut().next() {
+        Some(panel) => panel,
+        None => return,
+    };
+    for &child in children.into_iter().flat_map(|children| children.iter()) {
+        commands.entity(child).despawn_recursive();
+    }
+    if !position.rules().has_drops() {
+        style.display = Display::None;
+        return;
+    }
+    style.display = Display::Flex;
+
+    let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+    let button_material = color_materials.add(Color::rgb(0.25, 0.25, 0.25).into());
+    let selected_material = color_materials.add(Color::rgb(0.35, 0.55, 0.35).into());
+    let row_material = color_materials.add(Color::NONE.into());
+    let text = |label: String| TextBundle {
+        text: Text::with_section(
+            label,
+            TextStyle {
+                font: font.clone(),
+                font_size: 26.0,
+                color: Color::rgb(0.8, 0.8, 0.8),
+            },
+            TextAlignment::default(),
+        ),
+        ..Default::default()
+    };
+
+    commands.entity(panel).with_children(|parent| {
+        for &color in [PieceColor::White, PieceColor::Black].iter() {
+            let pocket = position.pockets[color_index(color)];
+            parent
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        align_items: AlignItems::Center,
+                        ..Default::default()
+                    },
+                    material: row_material.clone(),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    parent.spawn_bundle(text(format!("{} pocket:", color_name(color))));
+                    if pocket.is_empty() {
+                        parent.spawn_bundle(text(" empty".to_string()));
+                    }
+                    for &piece_type in Pocket::PIECE_TYPES.iter() {
+                        let count = pocket.count(piece_type);
+                        if count == 0 {
+                            continue;
+                        }
+                        parent
+                            .spawn_bundle(ButtonBundle {
+                                style: Style {
+                                    margin: Rect::all(Val::Px(3.)),
+                                    padding: Rect::all(Val::Px(4.)),
+                                    ..Default::default()
+                                },
+                                material: if selected_drop.piece == Some((color, piece_type)) {
+                                    selected_material.clone()
+                                } else {
+                                    button_material.clone()
+                                },
+                                ..Default::default()
+                            })
+                            .insert(PocketButton { color, piece_type })
+                            .with_children(|parent| {
+                                parent.spawn_bundle(text(format!(
+                                    "{} x{}",
+                                    piece_letter(piece_type),
+                                    count
+                                )));
+                            });
+                    }
+                });
+        }
+    });
+}
+
+/// Clicking a pocketed piece of the side to move picks it up, and clicking it again puts
+/// it back. Where it goes is chosen by clicking the board
+fn pocket_buttons(
+    turn: Res<PlayerTurn>,
+    analysis: Res<AnalysisMode>,
+    puzzles: Res<PuzzleMode>,
+    editor: Res<EditorMode>,
+    mut selected_piece: ResMut<SelectedPiece>,
+    mut selected_drop: ResMut<SelectedDrop>,
+    buttons_query: Query<(&Interaction, &PocketButton), Changed<Interaction>>,
+) {
+    for (interaction, button) in buttons_query.iter() {
+        if *interaction != Interaction::Clicked
+            || !analysis.may_move(button.color, &turn)
+            || !puzzles.may_move(button.color)
+            || editor.active
+        {
+            continue;
+        }
+        let piece = Some((button.color, button.piece_type));
+        selected_drop.piece = if selected_drop.piece == piece {
+            None
+        } else {
+            piece
+        };
+        selected_piece.entity = None;
+    }
+}
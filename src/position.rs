@@ -0,0 +1,975 @@
+use crate::{betza, events::color_name, notation::square_name, pieces::*, variant::*};
+
+/// The largest board a position can hold, enough for 10x10 Grand Chess
+pub const MAX_BOARD_SIZE: u8 = 10;
+/// The smallest, leaving room for castling and both sides' pawns
+pub const MIN_BOARD_SIZE: u8 = 4;
+
+/// Indexes into `Position::castling`
+pub const KING_SIDE: usize = 0;
+pub const QUEEN_SIDE: usize = 1;
+
+pub const STANDARD_BACK_RANK: [PieceType; 8] = [
+    PieceType::Rook,
+    PieceType::Knight,
+    PieceType::Bishop,
+    PieceType::Queen,
+    PieceType::King,
+    PieceType::Bishop,
+    PieceType::Knight,
+    PieceType::Rook,
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Move {
+    pub from: (u8, u8),
+    pub to: (u8, u8),
+    pub promotion: Option<PieceType>,
+    /// Castling moves go from the king's square to the king's destination
+    pub castling: bool,
+    /// A piece put on `to` from the pocket, in which case `from` is `to` as well
+    pub drop: Option<PieceType>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GameStatus {
+    Ongoing,
+    Checkmate,
+    Stalemate,
+    FiftyMoveRule,
+    InsufficientMaterial,
+    /// Decided by a variant's own rule, e.g. "reaching the centre" in King of the Hill
+    VariantWin {
+        winner: PieceColor,
+        reason: &'static str,
+    },
+}
+impl GameStatus {
+    /// Who won, given the side that would move next
+    pub fn winner(self, turn: PieceColor) -> Option<PieceColor> {
+        match self {
+            GameStatus::Checkmate => Some(turn.opposite()),
+            GameStatus::VariantWin { winner, .. } => Some(winner),
+            _ => None,
+        }
+    }
+}
+
+/// Pieces taken in Crazyhouse, waiting to be dropped back on the board by the side that
+/// took them
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Pocket {
+    counts: [u8; 5],
+}
+
+impl Pocket {
+    /// Everything but the king, most valuable first
+    pub const PIECE_TYPES: [PieceType; 5] = [
+        PieceType::Queen,
+        PieceType::Rook,
+        PieceType::Bishop,
+        PieceType::Knight,
+        PieceType::Pawn,
+    ];
+
+    fn index(piece_type: PieceType) -> Option<usize> {
+        Self::PIECE_TYPES
+            .iter()
+            .position(|&pocketed| pocketed == piece_type)
+    }
+
+    pub fn count(&self, piece_type: PieceType) -> u8 {
+        Self::index(piece_type).map_or(0, |index| self.counts[index])
+    }
+
+    pub fn add(&mut self, piece_type: PieceType) {
+        if let Some(index) = Self::index(piece_type) {
+            self.counts[index] += 1;
+        }
+    }
+
+    pub fn take(&mut self, piece_type: PieceType) {
+        if let Some(index) = Self::index(piece_type) {
+            self.counts[index] = self.counts[index].saturating_sub(1);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.counts.iter().all(|&count| count == 0)
+    }
+}
+
+/// The state of a game independent of any entities: where the pieces stand, who moves
+/// next and what special moves are still available
+#[derive(Clone, PartialEq)]
+pub struct Position {
+    board: [[Option<(PieceColor, PieceType)>; MAX_BOARD_SIZE as usize]; MAX_BOARD_SIZE as usize],
+    ranks: u8,
+    files: u8,
+    pub turn: PieceColor,
+    /// File of the rook each side may still castle with, indexed by color then side
+    pub castling: [[Option<u8>; 2]; 2],
+    /// The square a pawn skipped over on the previous move
+    pub en_passant: Option<(u8, u8)>,
+    /// Moves since the last capture or pawn move, for the fifty-move rule
+    pub halfmove_clock: u32,
+    pub fullmove_number: u32,
+    pub variant: VariantKind,
+    /// Checks each side has given, which only Three-check keeps count of
+    pub checks_given: [u8; 2],
+    /// Pieces each side may drop, in variants with drops
+    pub pockets: [Pocket; 2],
+    /// Squares holding promoted pieces, one bit per square, which go back into the pocket
+    /// as pawns. Only kept in variants with drops
+    promoted: u128,
+}
+impl Default for Position {
+    fn default() -> Self {
+        Self::start()
+    }
+}
+
+impl Position {
+    pub fn empty() -> Self {
+        Self::empty_board(8, 8)
+    }
+
+    /// An empty board of any size from `MIN_BOARD_SIZE` to `MAX_BOARD_SIZE` either way
+    pub fn empty_board(ranks: u8, files: u8) -> Self {
+        Self {
+            board: Default::default(),
+            ranks: ranks.max(MIN_BOARD_SIZE).min(MAX_BOARD_SIZE),
+            files: files.max(MIN_BOARD_SIZE).min(MAX_BOARD_SIZE),
+            turn: PieceColor::White,
+            castling: [[None; 2]; 2],
+            en_passant: None,
+            halfmove_clock: 0,
+            fullmove_number: 1,
+            variant: VariantKind::Standard,
+            checks_given: [0; 2],
+            pockets: [Pocket::default(); 2],
+            promoted: 0,
+        }
+    }
+
+    pub fn start() -> Self {
+        Self::with_back_rank(&STANDARD_BACK_RANK)
+    }
+
+    /// The starting position of a variant
+    pub fn variant_start(variant: VariantKind) -> Self {
+        let mut position = variant.rules().start();
+        position.variant = variant;
+        position
+    }
+
+    /// The rules this position is played under
+    pub fn rules(&self) -> &'static dyn Variant {
+        self.variant.rules()
+    }
+
+    /// One of the 960 Chess960 starting positions, numbered as usual so that 518 is the
+    /// standard one
+    pub fn chess960(index: u16) -> Self {
+        Self::with_back_rank(&chess960_back_rank(index))
+    }
+
+    /// The pieces lined up on the back ranks of an eight-rank board as wide as the line
+    /// of pieces, black's mirroring white's, with pawns in front and castling allowed with
+    /// both rooks
+    pub fn with_back_rank(back_rank_pieces: &[PieceType]) -> Self {
+        let mut position = Self::empty_board(8, back_rank_pieces.len() as u8);
+        for &color in [PieceColor::White, PieceColor::Black].iter() {
+            for (y, &piece_type) in back_rank_pieces.iter().enumerate() {
+                let (back_rank, pawn_rank) = (position.back_rank(color), position.pawn_rank(color));
+                position.set((back_rank, y as u8), Some((color, piece_type)));
+                position.set((pawn_rank, y as u8), Some((color, PieceType::Pawn)));
+            }
+
+            let king_file = back_rank_pieces
+                .iter()
+                .position(|&piece_type| piece_type == PieceType::King)
+                .unwrap_or(back_rank_pieces.len() / 2) as u8;
+            let rook_files: Vec<u8> = (0..back_rank_pieces.len() as u8)
+                .filter(|&y| back_rank_pieces[y as usize] == PieceType::Rook)
+                .collect();
+            position.castling[color_index(color)] = [
+                rook_files.iter().copied().find(|&file| file > king_file),
+                rook_files.iter().copied().find(|&file| file < king_file),
+            ];
+        }
+        position
+    }
+
+    /// Whether castling rights only make sense in Chess960, because a king or rook that
+    /// may still castle stands somewhere other than in the standard setup: the king on the
+    /// middle file and the rooks in the corners
+    pub fn is_chess960(&self) -> bool {
+        let standard_rights = [Some(self.files - 1), Some(0)];
+        [PieceColor::White, PieceColor::Black].iter().any(|&color| {
+            let rights = self.castling[color_index(color)];
+            rights
+                .iter()
+                .zip(standard_rights.iter())
+                .any(|(right, standard_right)| right.is_some() && right != standard_right)
+                || (rights.iter().any(Option::is_some)
+                    && self.king_square(color) != Some((self.back_rank(color), self.files / 2)))
+        })
+    }
+
+    /// Whether this is a plain 8x8 board with nothing but the standard pieces on it, as
+    /// opening books and tablebases expect
+    pub fn is_orthodox(&self) -> bool {
+        self.ranks == 8
+            && self.files == 8
+            && self
+                .pieces()
+                .iter()
+                .all(|piece| PieceType::STANDARD.contains(&piece.piece_type))
+    }
+
+    pub fn ranks(&self) -> u8 {
+        self.ranks
+    }
+
+    pub fn files(&self) -> u8 {
+        self.files
+    }
+
+    /// Every square, rank by rank
+    pub fn squares(&self) -> impl Iterator<Item = (u8, u8)> {
+        let files = self.files;
+        (0..self.ranks).flat_map(move |x| (0..files).map(move |y| (x, y)))
+    }
+
+    pub fn back_rank(&self, color: PieceColor) -> u8 {
+        match color {
+            PieceColor::White => 0,
+            PieceColor::Black => self.ranks - 1,
+        }
+    }
+
+    pub fn pawn_rank(&self, color: PieceColor) -> u8 {
+        match color {
+            PieceColor::White => 1,
+            PieceColor::Black => self.ranks - 2,
+        }
+    }
+
+    /// Which side a castling move goes to
+    pub fn castling_side(&self, mv: Move) -> usize {
+        if mv.to.1 == self.castling_files(KING_SIDE).0 {
+            KING_SIDE
+        } else {
+            QUEEN_SIDE
+        }
+    }
+
+    /// Files the king and rook end up on, wherever they started
+    fn castling_files(&self, side: usize) -> (u8, u8) {
+        if side == KING_SIDE {
+            (self.files - 2, self.files - 3)
+        } else {
+            (2, 3)
+        }
+    }
+
+    fn step(&self, square: (u8, u8), offset: (i8, i8)) -> Option<(u8, u8)> {
+        let x = square.0 as i8 + offset.0;
+        let y = square.1 as i8 + offset.1;
+        if (0..self.ranks as i8).contains(&x) && (0..self.files as i8).contains(&y) {
+            Some((x as u8, y as u8))
+        } else {
+            None
+        }
+    }
+
+    fn get(&self, square: (u8, u8)) -> Option<(PieceColor, PieceType)> {
+        self.board[square.0 as usize][square.1 as usize]
+    }
+
+    pub fn set(&mut self, square: (u8, u8), piece: Option<(PieceColor, PieceType)>) {
+        self.board[square.0 as usize][square.1 as usize] = piece;
+    }
+
+    pub fn is_promoted(&self, square: (u8, u8)) -> bool {
+        self.promoted & square_bit(square) != 0
+    }
+
+    pub fn set_promoted(&mut self, square: (u8, u8), promoted: bool) {
+        if promoted {
+            self.promoted |= square_bit(square);
+        } else {
+            self.promoted &= !square_bit(square);
+        }
+    }
+
+    pub fn piece_at(&self, square: (u8, u8)) -> Option<Piece> {
+        self.get(square).map(|(color, piece_type)| Piece {
+            color,
+            piece_type,
+            x: square.0,
+            y: square.1,
+        })
+    }
+
+    pub fn pieces(&self) -> Vec<Piece> {
+        self.squares()
+            .filter_map(|square| self.piece_at(square))
+            .collect()
+    }
+
+    pub fn king_square(&self, color: PieceColor) -> Option<(u8, u8)> {
+        self.squares()
+            .find(|&square| self.get(square) == Some((color, PieceType::King)))
+    }
+
+    /// Whether `color`'s king is attacked. Never true in variants where the king may be
+    /// captured like any other piece
+    pub fn is_in_check(&self, color: PieceColor) -> bool {
+        if !self.rules().royal_king() {
+            return false;
+        }
+        self.king_square(color)
+            .map_or(false, |square| self.is_attacked(square, color.opposite()))
+    }
+
+    /// Whether any piece of color `by` could capture on `square`
+    pub fn is_attacked(&self, square: (u8, u8), by: PieceColor) -> bool {
+        self.squares().any(|from| match self.get(from) {
+            Some((color, piece_type)) if color == by => {
+                self.attacks(from, color, piece_type, square)
+            }
+            _ => false,
+        })
+    }
+
+    /// Whether the piece on `from` could capture on `target`, were there something to take
+    fn attacks(
+        &self,
+        from: (u8, u8),
+        color: PieceColor,
+        piece_type: PieceType,
+        target: (u8, u8),
+    ) -> bool {
+        let distance = (target.0 as i8 - from.0 as i8, target.1 as i8 - from.1 as i8);
+        betza::steps(piece_type.betza())
+            .iter()
+            .filter(|step| step.captures)
+            .any(|step| {
+                let offset = oriented(step.offset, color);
+                match repeats(distance, offset) {
+                    Some(times) if times <= step.range => (1..times).all(|i| {
+                        let i = i as i8;
+                        let between = (
+                            (from.0 as i8 + offset.0 * i) as u8,
+                            (from.1 as i8 + offset.1 * i) as u8,
+                        );
+                        self.get(between).is_none()
+                    }),
+                    _ => false,
+                }
+            })
+    }
+
+    /// Every legal move for the side to move
+    pub fn legal_moves(&self) -> Vec<Move> {
+        let color = self.turn;
+        let moves = self
+            .pseudo_legal_moves()
+            .into_iter()
+            .filter(|&mv| {
+                let mut after = self.clone();
+                after.play(mv);
+                !after.is_in_check(color)
+            })
+            .collect();
+        self.rules().filter_moves(self, moves)
+    }
+
+    /// Moves that follow the movement rules but may leave the own king in check
+    fn pseudo_legal_moves(&self) -> Vec<Move> {
+        let mut moves = Vec::new();
+        for from in self.squares() {
+            match self.get(from) {
+                Some((color, piece_type)) if color == self.turn => {
+                    self.piece_moves(from, piece_type, &mut moves)
+                }
+                _ => (),
+            }
+        }
+        self.castling_moves(&mut moves);
+        if self.rules().has_drops() {
+            self.drop_moves(&mut moves);
+        }
+        moves
+    }
+
+    /// Moves as the piece's Betza description allows, see `PieceType::betza`
+    fn piece_moves(&self, from: (u8, u8), piece_type: PieceType, moves: &mut Vec<Move>) {
+        if piece_type == PieceType::Pawn {
+            return self.pawn_moves(from, moves);
+        }
+
+        let color = self.turn;
+        for step in betza::steps(piece_type.betza()).iter() {
+            let offset = oriented(step.offset, color);
+            let mut current = from;
+            for _ in 0..step.range {
+                let to = match self.step(current, offset) {
+                    Some(to) => to,
+                    None => break,
+                };
+                let target = self.get(to);
+                let allowed = match target {
+                    None => step.moves,
+                    Some((target_color, _)) => step.captures && target_color != color,
+                };
+                if allowed {
+                    moves.push(Move {
+                        from,
+                        to,
+                        promotion: None,
+                        castling: false,
+                        drop: None,
+                    });
+                }
+                if target.is_some() {
+                    break;
+                }
+                current = to;
+            }
+        }
+    }
+
+    /// Pawns step as described too, but may also move twice as far from their own rank,
+    /// take en passant and promote
+    fn pawn_moves(&self, from: (u8, u8), moves: &mut Vec<Move>) {
+        let color = self.turn;
+        let mut add = |to: (u8, u8)| {
+            if to.0 == self.back_rank(color.opposite()) {
+                for &promotion in self.rules().promotions() {
+                    moves.push(Move {
+                        from,
+                        to,
+                        promotion: Some(promotion),
+                        castling: false,
+                        drop: None,
+                    });
+                }
+            } else {
+                moves.push(Move {
+                    from,
+                    to,
+                    promotion: None,
+                    castling: false,
+                    drop: None,
+                });
+            }
+        };
+
+        for step in betza::steps(PieceType::Pawn.betza()).iter() {
+            let offset = oriented(step.offset, color);
+            let to = match self.step(from, offset) {
+                Some(to) => to,
+                None => continue,
+            };
+            match self.get(to) {
+                None if step.moves => {
+                    add(to);
+                    if self.rules().may_double_step(self, from.0, color) {
+                        if let Some(to) = self.step(to, offset) {
+                            if self.get(to).is_none() {
+                                add(to);
+                            }
+                        }
+                    }
+                }
+                None if step.captures && self.en_passant == Some(to) => add(to),
+                Some((target_color, _)) if step.captures && target_color != color => add(to),
+                _ => (),
+            }
+        }
+    }
+
+    fn castling_moves(&self, moves: &mut Vec<Move>) {
+        let color = self.turn;
+        let rank = self.back_rank(color);
+        let king = match self.king_square(color) {
+            Some(king) if king.0 == rank => king,
+            _ => return,
+        };
+        if self.is_attacked(king, color.opposite()) {
+            return;
+        }
+
+        for side in [KING_SIDE, QUEEN_SIDE].iter().copied() {
+            let rook_file = match self.castling[color_index(color)][side] {
+                Some(rook_file)
+                    if self.get((rank, rook_file)) == Some((color, PieceType::Rook)) =>
+                {
+                    rook_file
+                }
+                _ => continue,
+            };
+            let (king_to, rook_to) = self.castling_files(side);
+
+            // Every square either piece crosses must be empty, apart from the two pieces
+            let low = king.1.min(king_to).min(rook_file).min(rook_to);
+            let high = king.1.max(king_to).max(rook_file).max(rook_to);
+            let blocked = (low..=high).any(|file| {
+                file != king.1 && file != rook_file && self.get((rank, file)).is_some()
+            });
+            // and the king may not pass through check
+            let crosses_check = range_inclusive(king.1, king_to)
+                .any(|file| self.is_attacked((rank, file), color.opposite()));
+
+            if !blocked && !crosses_check {
+                moves.push(Move {
+                    from: king,
+                    to: (rank, king_to),
+                    promotion: None,
+                    castling: true,
+                    drop: None,
+                });
+            }
+        }
+    }
+
+    /// Any pocketed piece may go on any empty square, except pawns on the first and last
+    /// ranks
+    fn drop_moves(&self, moves: &mut Vec<Move>) {
+        let pocket = self.pockets[color_index(self.turn)];
+        for &piece_type in Pocket::PIECE_TYPES.iter() {
+            if pocket.count(piece_type) == 0 {
+                continue;
+            }
+            for to in self.squares() {
+                let pawn_barred = piece_type == PieceType::Pawn
+                    && (to.0 == self.back_rank(PieceColor::White)
+                        || to.0 == self.back_rank(PieceColor::Black));
+                if self.get(to).is_none() && !pawn_barred {
+                    moves.push(Move {
+                        from: to,
+                        to,
+                        promotion: None,
+                        castling: false,
+                        drop: Some(piece_type),
+                    });
+                }
+            }
+        }
+    }
+
+    /// The square of the piece `mv` takes, which differs from the target for en passant
+    pub fn captured_square(&self, mv: Move) -> Option<(u8, u8)> {
+        if mv.castling || mv.drop.is_some() {
+            return None;
+        }
+        if self.get(mv.to).is_some() {
+            return Some(mv.to);
+        }
+        match self.get(mv.from) {
+            Some((_, PieceType::Pawn)) if self.en_passant == Some(mv.to) => {
+                Some((mv.from.0, mv.to.1))
+            }
+            _ => None,
+        }
+    }
+
+    /// Where the rook starts and ends for a castling move
+    pub fn castling_rook(&self, mv: Move) -> Option<((u8, u8), (u8, u8))> {
+        if !mv.castling {
+            return None;
+        }
+        let color = self.get(mv.from)?.0;
+        let side = self.castling_side(mv);
+        let rook_file = self.castling[color_index(color)][side]?;
+        let (_, rook_to) = self.castling_files(side);
+        Some(((mv.from.0, rook_file), (mv.from.0, rook_to)))
+    }
+
+    /// Plays a move without checking it, updating castling rights, en passant and clocks
+    pub fn play(&mut self, mv: Move) {
+        let (color, piece_type) = match (mv.drop, self.get(mv.from)) {
+            (Some(dropped), _) => (self.turn, dropped),
+            (None, Some(piece)) => piece,
+            (None, None) => return,
+        };
+        let captured = self.captured_square(mv);
+
+        if self.rules().has_drops() {
+            // Taken pieces change sides, promoted ones going back to being pawns
+            if let Some(captured) = captured {
+                if let Some((_, captured_type)) = self.get(captured) {
+                    let pocketed = if self.is_promoted(captured) {
+                        PieceType::Pawn
+                    } else {
+                        captured_type
+                    };
+                    self.pockets[color_index(color)].add(pocketed);
+                }
+                self.set_promoted(captured, false);
+            }
+            let promoted = mv.promotion.is_some() || self.is_promoted(mv.from);
+            self.set_promoted(mv.from, false);
+            self.set_promoted(mv.to, promoted);
+        }
+
+        if mv.drop.is_some() {
+            self.pockets[color_index(color)].take(piece_type);
+            self.set(mv.to, Some((color, piece_type)));
+        } else if let Some((rook_from, rook_to)) = self.castling_rook(mv) {
+            // The king and rook may swap places in Chess960, so clear both before placing
+            self.set(mv.from, None);
+            self.set(rook_from, None);
+            self.set(rook_to, Some((color, PieceType::Rook)));
+            self.set(mv.to, Some((color, PieceType::King)));
+        } else {
+            if let Some(captured) = captured {
+                self.set(captured, None);
+            }
+            self.set(mv.from, None);
+            self.set(mv.to, Some((color, mv.promotion.unwrap_or(piece_type))));
+        }
+
+        // Only a double step from the pawns' own rank may be taken en passant, not one a
+        // horde pawn makes from the first rank
+        self.en_passant = None;
+        if piece_type == PieceType::Pawn
+            && (mv.to.0 as i8 - mv.from.0 as i8).abs() == 2
+            && mv.from.0 == self.pawn_rank(color)
+        {
+            self.en_passant = Some(((mv.from.0 + mv.to.0) / 2, mv.from.1));
+        }
+
+        if piece_type == PieceType::King {
+            self.castling[color_index(color)] = [None, None];
+        }
+        // Moving a rook, or taking one, loses the right to castle with it
+        for &rook_color in [PieceColor::White, PieceColor::Black].iter() {
+            let rank = self.back_rank(rook_color);
+            for right in self.castling[color_index(rook_color)].iter_mut() {
+                if let Some(file) = *right {
+                    let square = (rank, file);
+                    if mv.from == square || mv.to == square {
+                        *right = None;
+                    }
+                }
+            }
+        }
+
+        if piece_type == PieceType::Pawn || captured.is_some() {
+            self.halfmove_clock = 0;
+        } else {
+            self.halfmove_clock += 1;
+        }
+        if color == PieceColor::Black {
+            self.fullmove_number += 1;
+        }
+        self.turn = color.opposite();
+        self.rules().after_move(self, color);
+    }
+
+    /// Hands the move to the other side without playing one, as when exploring moves
+    /// for either color. En passant is lost, as it would be after any move
+    pub fn pass(&mut self) {
+        self.en_passant = None;
+        self.turn = self.turn.opposite();
+    }
+
+    pub fn status(&self) -> GameStatus {
+        let legal_moves = self.legal_moves();
+        if let Some(status) = self.variant_outcome(&legal_moves) {
+            status
+        } else if legal_moves.is_empty() {
+            if self.is_in_check(self.turn) {
+                GameStatus::Checkmate
+            } else {
+                GameStatus::Stalemate
+            }
+        } else if self.halfmove_clock >= 100 {
+            GameStatus::FiftyMoveRule
+        } else if self.has_insufficient_material() {
+            GameStatus::InsufficientMaterial
+        } else {
+            GameStatus::Ongoing
+        }
+    }
+
+    /// A result the variant's own rules have already reached, before checkmate and the
+    /// usual draws are looked at
+    pub fn variant_outcome(&self, legal_moves: &[Move]) -> Option<GameStatus> {
+        self.rules().outcome(self, legal_moves)
+    }
+
+    pub fn has_insufficient_material(&self) -> bool {
+        self.rules().has_insufficient_material(self)
+    }
+
+    /// Why a position set up by hand can't be played from, if it can't: the pieces the
+    /// variant asks for (see `Variant::check_setup`), the side that just moved still in
+    /// check, or castling and en passant rights the pieces don't allow
+    pub fn check_setup(&self) -> Result<(), String> {
+        self.rules().check_setup(self)?;
+
+        let mover = self.turn.opposite();
+        if self.is_in_check(mover) {
+            return Err(format!(
+                "{} is in check without being to move",
+                color_name(mover)
+            ));
+        }
+        for &color in [PieceColor::White, PieceColor::Black].iter() {
+            for &side in [KING_SIDE, QUEEN_SIDE].iter() {
+                match self.castling[color_index(color)][side] {
+                    Some(rook_file) if !self.may_castle_with(color, side, rook_file) => {
+                        return Err(format!(
+                            "{} can't castle {} side without its king and rook in place",
+                            color_name(color),
+                            if side == KING_SIDE { "king" } else { "queen" }
+                        ))
+                    }
+                    _ => (),
+                }
+            }
+        }
+        match self.en_passant {
+            Some(square) if !self.en_passant_squares().contains(&square) => Err(format!(
+                "No pawn just skipped {} to be taken en passant",
+                square_name(square)
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Whether `color` keeps its king on the back rank with its own rook on `rook_file`,
+    /// on the `side` of the king castling goes to
+    pub fn may_castle_with(&self, color: PieceColor, side: usize, rook_file: u8) -> bool {
+        let rank = self.back_rank(color);
+        let king_file = match self.king_square(color) {
+            Some((king_rank, king_file)) if king_rank == rank => king_file,
+            _ => return false,
+        };
+        rook_file < self.files
+            && (rook_file > king_file) == (side == KING_SIDE)
+            && self.get((rank, rook_file)) == Some((color, PieceType::Rook))
+    }
+
+    /// Squares a pawn of the side that just moved could have skipped with a double step
+    /// from its own rank, as it stands two squares ahead with the squares behind it empty
+    pub fn en_passant_squares(&self) -> Vec<(u8, u8)> {
+        let mover = self.turn.opposite();
+        let backward = oriented((-1, 0), mover);
+        self.squares()
+            .filter(|&square| self.get(square) == Some((mover, PieceType::Pawn)))
+            .filter_map(|square| {
+                let skipped = self.step(square, backward)?;
+                let from = self.step(skipped, backward)?;
+                let empty = self.get(skipped).is_none() && self.get(from).is_none();
+                if empty && from.0 == self.pawn_rank(mover) {
+                    Some(skipped)
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// The number of move sequences `depth` plies long, stopping at games the variant has
+    /// already decided. Compared with known counts, it tells whether move generation is right
+    pub fn perft(&self, depth: u32) -> u64 {
+        if depth == 0 {
+            return 1;
+        }
+        let moves = self.legal_moves();
+        if self.variant_outcome(&moves).is_some() {
+            return 0;
+        }
+        if depth == 1 {
+            return moves.len() as u64;
+        }
+        moves
+            .into_iter()
+            .map(|mv| {
+                let mut next = self.clone();
+                next.play(mv);
+                next.perft(depth - 1)
+            })
+            .sum()
+    }
+}
+
+/// The back rank of a Chess960 position from its number, 0 to 959. The digits of the
+/// number place the light-squared bishop, the dark-squared bishop, the queen and the
+/// knights in turn, and the king ends up between the rooks on the three squares left
+pub fn chess960_back_rank(index: u16) -> [PieceType; 8] {
+    // Where the two knights go among the five squares left after bishops and queen
+    const KNIGHTS: [(usize, usize); 10] = [
+        (0, 1),
+        (0, 2),
+        (0, 3),
+        (0, 4),
+        (1, 2),
+        (1, 3),
+        (1, 4),
+        (2, 3),
+        (2, 4),
+        (3, 4),
+    ];
+
+    let mut rank: [Option<PieceType>; 8] = Default::default();
+    let mut n = (index % 960) as usize;
+    rank[2 * (n % 4) + 1] = Some(PieceType::Bishop);
+    n /= 4;
+    rank[2 * (n % 4)] = Some(PieceType::Bishop);
+    n /= 4;
+
+    let empty = |rank: &[Option<PieceType>]| -> Vec<usize> {
+        (0..rank.len()).filter(|&y| rank[y].is_none()).collect()
+    };
+    rank[empty(&rank)[n % 6]] = Some(PieceType::Queen);
+    n /= 6;
+
+    let free = empty(&rank);
+    let (first, second) = KNIGHTS[n];
+    rank[free[first]] = Some(PieceType::Knight);
+    rank[free[second]] = Some(PieceType::Knight);
+
+    for (&y, &piece_type) in empty(&rank)
+        .iter()
+        .zip([PieceType::Rook, PieceType::King, PieceType::Rook].iter())
+    {
+        rank[y] = Some(piece_type);
+    }
+
+    let mut back_rank_pieces = STANDARD_BACK_RANK;
+    for (y, piece_type) in rank.iter().enumerate() {
+        if let Some(piece_type) = piece_type {
+            back_rank_pieces[y] = *piece_type;
+        }
+    }
+    back_rank_pieces
+}
+
+pub fn color_index(color: PieceColor) -> usize {
+    match color {
+        PieceColor::White => 0,
+        PieceColor::Black => 1,
+    }
+}
+
+/// A Betza step as seen from `color`'s side of the board, turned around for black
+fn oriented(offset: (i8, i8), color: PieceColor) -> (i8, i8) {
+    match color {
+        PieceColor::White => offset,
+        PieceColor::Black => (-offset.0, -offset.1),
+    }
+}
+
+/// How many times `offset` has to be repeated to cover `distance`, if it ever does
+fn repeats(distance: (i8, i8), offset: (i8, i8)) -> Option<u8> {
+    let times = if offset.0 != 0 {
+        distance.0 / offset.0
+    } else {
+        distance.1 / offset.1
+    };
+    if times > 0 && (offset.0 * times, offset.1 * times) == distance {
+        Some(times as u8)
+    } else {
+        None
+    }
+}
+
+fn square_bit(square: (u8, u8)) -> u128 {
+    1 << (square.0 as u32 * MAX_BOARD_SIZE as u32 + square.1 as u32)
+}
+
+fn range_inclusive(a: u8, b: u8) -> std::ops::RangeInclusive<u8> {
+    a.min(b)..=a.max(b)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::notation::*;
+
+    /// Checks the move counts of the first plies against the published ones
+    pub fn assert_perft(position: &Position, counts: &[u64]) {
+        for (depth, &count) in counts.iter().enumerate() {
+            assert_eq!(
+                position.perft(depth as u32 + 1),
+                count,
+                "{}",
+                position_to_fen(position)
+            );
+        }
+    }
+
+    #[test]
+    fn perft_start() {
+        let position = Position::start();
+        assert_eq!(position.perft(1), 20);
+        assert_eq!(position.perft(2), 400);
+        assert_eq!(position.perft(3), 8902);
+        assert_eq!(position.perft(4), 197281);
+    }
+
+    #[test]
+    fn perft_kiwipete() {
+        assert_perft(
+            &parse_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1")
+                .unwrap(),
+            &[48, 2039, 97862],
+        );
+    }
+
+    /// En passant that would expose the king along the rank
+    #[test]
+    fn perft_position_3() {
+        assert_perft(
+            &parse_fen("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1").unwrap(),
+            &[14, 191, 2812, 43238],
+        );
+    }
+
+    /// Promotions, including captures that promote, and castling out of a pin
+    #[test]
+    fn perft_position_4() {
+        assert_perft(
+            &parse_fen("r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1").unwrap(),
+            &[6, 264, 9467],
+        );
+    }
+
+    #[test]
+    fn perft_position_5() {
+        assert_perft(
+            &parse_fen("rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8").unwrap(),
+            &[44, 1486, 62379],
+        );
+    }
+
+    /// Chess960 castling, where the king and rook may start next to or on each other's
+    /// target squares
+    #[test]
+    fn perft_chess960() {
+        assert_perft(
+            &parse_fen("bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9")
+                .unwrap(),
+            &[21, 528, 12189],
+        );
+        assert_perft(
+            &parse_fen("2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w HEhe - 1 9").unwrap(),
+            &[21, 807, 18002],
+        );
+        assert_perft(
+            &parse_fen("b1q1rrkb/pppppppp/3nn3/8/P7/1PPP4/4PPPP/BQNNRKRB w GE - 1 9").unwrap(),
+            &[20, 479, 10471],
+        );
+        assert_perft(
+            &parse_fen("1r1bkqbr/pppp1ppp/2nnp3/8/2P5/N4P2/PP1PP1PP/1RNBKQBR w Hh - 0 9").unwrap(),
+            &[28, 810, 22844],
+        );
+    }
+}
@@ -0,0 +1,833 @@
+use crate::{
+    animation::*, editor::*, events::*, game_tree::*, notation::*, pieces::*, position::*,
+};
+use bevy::{asset::FileAssetIo, prelude::*};
+use rand::seq::SliceRandom;
+use std::path::PathBuf;
+
+/// Where puzzles are read from unless `--puzzles` names another file
+const PUZZLES_FILE: &str = "assets/puzzles/puzzles.csv";
+
+/// The solver's rating before the first puzzle, and the most a single puzzle can move it
+const START_RATING: f32 = 1500.;
+const RATING_K: f32 = 32.;
+
+/// Puzzles are picked among those rated at most this far from the solver, when there are
+/// any
+const RATING_WINDOW: f32 = 200.;
+
+/// How long the opponent waits before its first move, so the puzzle can be seen first.
+/// Later replies come as soon as the solver's piece has landed
+const FIRST_REPLY_DELAY: f32 = 0.6;
+
+pub struct PuzzleSettings {
+    pub path: PathBuf,
+}
+impl Default for PuzzleSettings {
+    fn default() -> Self {
+        Self {
+            path: FileAssetIo::get_root_path().join(PUZZLES_FILE),
+        }
+    }
+}
+
+/// A tactics puzzle as the Lichess puzzle database has them: the opponent plays the first
+/// move of the solution from `fen`, and the solver has to find every other one
+#[derive(Clone, Debug)]
+pub struct Puzzle {
+    pub id: String,
+    pub fen: String,
+    /// The solution in UCI notation, starting with the opponent's move
+    pub moves: Vec<String>,
+    pub rating: u32,
+    pub themes: Vec<String>,
+}
+
+/// Which CSV column holds what. Without a header line the columns are FEN, moves, rating
+/// and themes, in that order
+struct Columns {
+    id: Option<usize>,
+    fen: usize,
+    moves: usize,
+    rating: Option<usize>,
+    themes: Option<usize>,
+}
+impl Default for Columns {
+    fn default() -> Self {
+        Self {
+            id: None,
+            fen: 0,
+            moves: 1,
+            rating: Some(2),
+            themes: Some(3),
+        }
+    }
+}
+
+/// Every puzzle read from the CSV file, and which of them were already tried
+pub struct PuzzleCollection {
+    puzzles: Vec<Puzzle>,
+    attempted: Vec<bool>,
+}
+impl PuzzleCollection {
+    fn load(settings: &PuzzleSettings) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(&settings.path)
+            .map_err(|err| format!("{}: {}", settings.path.display(), err))?;
+        let puzzles = Self::parse(&contents)?;
+        Ok(Self {
+            attempted: vec![false; puzzles.len()],
+            puzzles,
+        })
+    }
+
+    /// Reads puzzles from CSV. A header line, as in the Lichess database
+    /// (`PuzzleId,FEN,Moves,Rating,...,Themes,...`), says where the columns are
+    fn parse(contents: &str) -> Result<Vec<Puzzle>, String> {
+        let mut lines = contents
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .peekable();
+
+        let header = lines
+            .peek()
+            .map(|(_, line)| csv_fields(line))
+            .filter(|fields| fields.iter().any(|field| field.eq_ignore_ascii_case("fen")));
+        let columns = match header {
+            Some(fields) => {
+                lines.next();
+                let find = |name: &str| {
+                    fields
+                        .iter()
+                        .position(|field| field.eq_ignore_ascii_case(name))
+                };
+                Columns {
+                    id: find("PuzzleId"),
+                    fen: find("FEN").ok_or("No FEN column")?,
+                    moves: find("Moves").ok_or("No Moves column")?,
+                    rating: find("Rating"),
+                    themes: find("Themes"),
+                }
+            }
+            None => Columns::default(),
+        };
+
+        let mut puzzles = Vec::new();
+        let mut skipped = 0;
+        for (i, line) in lines {
+            let fields = csv_fields(line);
+            let field = |column: Option<usize>| {
+                column
+                    .and_then(|column| fields.get(column))
+                    .map_or("", String::as_str)
+            };
+            let fen = field(Some(columns.fen));
+            let moves: Vec<String> = field(Some(columns.moves))
+                .split_whitespace()
+                .map(str::to_string)
+                .collect();
+            // The opponent's move and at least one reply
+            if fen.is_empty() || moves.len() < 2 {
+                skipped += 1;
+                continue;
+            }
+            puzzles.push(Puzzle {
+                id: match field(columns.id) {
+                    "" => format!("#{}", i + 1),
+                    id => id.to_string(),
+                },
+                fen: fen.to_string(),
+                moves,
+                rating: field(columns.rating).parse().unwrap_or(START_RATING as u32),
+                themes: field(columns.themes)
+                    .split_whitespace()
+                    .map(str::to_string)
+                    .collect(),
+            });
+        }
+
+        if skipped > 0 {
+            warn!("Skipped {} lines that aren't puzzles", skipped);
+        }
+        if puzzles.is_empty() {
+            return Err("no puzzles".to_string());
+        }
+        Ok(puzzles)
+    }
+
+    /// A puzzle not tried yet, at random among those rated close to `rating` or else the
+    /// closest. Once every puzzle was tried they all count as new again
+    fn pick(&mut self, rating: f32) -> Option<Puzzle> {
+        if self.attempted.iter().all(|&attempted| attempted) {
+            self.attempted
+                .iter_mut()
+                .for_each(|attempted| *attempted = false);
+        }
+        let open: Vec<usize> = (0..self.puzzles.len())
+            .filter(|&i| !self.attempted[i])
+            .collect();
+        let distance = |i: usize| (self.puzzles[i].rating as f32 - rating).abs();
+        let close: Vec<usize> = open
+            .iter()
+            .copied()
+            .filter(|&i| distance(i) <= RATING_WINDOW)
+            .collect();
+
+        let index = match close.choose(&mut rand::thread_rng()) {
+            Some(&index) => index,
+            None => open.into_iter().min_by(|&a, &b| {
+                distance(a)
+                    .partial_cmp(&distance(b))
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            })?,
+        };
+        self.attempted[index] = true;
+        Some(self.puzzles[index].clone())
+    }
+
+    /// Drops a puzzle that turned out not to work, so it isn't picked again
+    fn remove(&mut self, id: &str) {
+        if let Some(index) = self.puzzles.iter().position(|puzzle| puzzle.id == id) {
+            self.puzzles.remove(index);
+            self.attempted.remove(index);
+        }
+    }
+}
+
+/// Splits a line of CSV, where fields may be quoted to hold commas, and doubled quotes
+/// stand for one
+fn csv_fields(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+        .iter()
+        .map(|field| field.trim().to_string())
+        .collect()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum PuzzleState {
+    Playing,
+    Solved,
+    Failed,
+}
+
+/// The puzzle on the board and how far the solver got with it
+struct ActivePuzzle {
+    puzzle: Puzzle,
+    start: Position,
+    /// Where the puzzle stands now
+    position: Position,
+    solution: Vec<Move>,
+    /// How many moves of the solution were played. The opponent plays the even ones
+    played: usize,
+    solver: PieceColor,
+    state: PuzzleState,
+    /// Whether the result counts for the rating, which only the first try does
+    rated: bool,
+    /// Tells tries apart, including retries of the same puzzle
+    attempt: u32,
+}
+impl ActivePuzzle {
+    fn new(puzzle: Puzzle, rated: bool, attempt: u32) -> Result<Self, String> {
+        let start = parse_fen(&puzzle.fen)?;
+        let mut position = start.clone();
+        let mut solution = Vec::new();
+        for uci in puzzle.moves.iter() {
+            let mv = parse_move(&position, uci)?;
+            position.play(mv);
+            solution.push(mv);
+        }
+        Ok(Self {
+            puzzle,
+            position: start.clone(),
+            solver: start.turn.opposite(),
+            start,
+            solution,
+            played: 0,
+            state: PuzzleState::Playing,
+            rated,
+            attempt,
+        })
+    }
+
+    fn solvers_turn(&self) -> bool {
+        self.state == PuzzleState::Playing && self.played % 2 == 1
+    }
+
+    fn awaits_reply(&self) -> bool {
+        self.state == PuzzleState::Playing && self.played % 2 == 0
+    }
+
+    /// Plays a move made on the board against the solution, and tells where that leaves
+    /// the puzzle. Any move of the solver that mates solves it, as there is no refuting it
+    fn follow(&mut self, mv: Move) -> PuzzleState {
+        let expected = self.solution[self.played];
+        let solvers_move = self.solvers_turn();
+        self.position.play(mv);
+
+        let mates = self.position.status() == GameStatus::Checkmate;
+        let correct =
+            (mv.from, mv.to, mv.promotion) == (expected.from, expected.to, expected.promotion);
+        if correct || (solvers_move && mates) {
+            self.played += 1;
+            if self.played == self.solution.len() || mates {
+                PuzzleState::Solved
+            } else {
+                PuzzleState::Playing
+            }
+        } else {
+            PuzzleState::Failed
+        }
+    }
+}
+
+/// Instead of playing a game, the board shows tactics puzzles to solve one after another.
+/// The game is put aside meanwhile, and comes back when the solver leaves the puzzles
+pub struct PuzzleMode {
+    pub active: bool,
+    puzzle: Option<ActivePuzzle>,
+    pub rating: f32,
+    /// How the last rated result moved the rating
+    rating_change: Option<f32>,
+    pub solved: u32,
+    pub failed: u32,
+    attempts: u32,
+    saved_game: Option<GameTree>,
+}
+impl Default for PuzzleMode {
+    fn default() -> Self {
+        Self {
+            active: false,
+            puzzle: None,
+            rating: START_RATING,
+            rating_change: None,
+            solved: 0,
+            failed: 0,
+            attempts: 0,
+            saved_game: None,
+        }
+    }
+}
+
+impl PuzzleMode {
+    /// Whether a piece of `color` may be moved: any outside of puzzles, but during one
+    /// only the solver's, on their turn
+    pub fn may_move(&self, color: PieceColor) -> bool {
+        if !self.active {
+            return true;
+        }
+        self.puzzle.as_ref().map_or(false, |puzzle| {
+            puzzle.solvers_turn() && color == puzzle.solver
+        })
+    }
+
+    /// Ends the puzzle and updates the rating, Elo style, against the puzzle's own
+    fn finish(&mut self, state: PuzzleState) {
+        let (puzzle_rating, rated) = match self.puzzle.as_mut() {
+            Some(puzzle) if puzzle.state == PuzzleState::Playing => {
+                puzzle.state = state;
+                (puzzle.puzzle.rating as f32, puzzle.rated)
+            }
+            _ => return,
+        };
+        let score = if state == PuzzleState::Solved {
+            self.solved += 1;
+            1.
+        } else {
+            self.failed += 1;
+            0.
+        };
+
+        self.rating_change = None;
+        if rated {
+            let expected = 1. / (1. + 10f32.powf((puzzle_rating - self.rating) / 400.));
+            let change = RATING_K * (score - expected);
+            self.rating += change;
+            self.rating_change = Some(change);
+        }
+    }
+}
+
+/// Resets the board to a new puzzle, or to `retry` again without it counting for the
+/// rating. Puzzles whose FEN or moves don't work are skipped
+fn start_puzzle(
+    mode: &mut PuzzleMode,
+    collection: &mut PuzzleCollection,
+    retry: Option<Puzzle>,
+    load_position_events: &mut EventWriter<LoadPosition>,
+) {
+    let rated = retry.is_none();
+    let mut next = retry;
+    mode.attempts += 1;
+    let attempt = mode.attempts;
+    mode.puzzle = loop {
+        let puzzle = match next.take().or_else(|| collection.pick(mode.rating)) {
+            Some(puzzle) => puzzle,
+            None => break None,
+        };
+        let id = puzzle.id.clone();
+        match ActivePuzzle::new(puzzle, rated, attempt) {
+            Ok(active) => break Some(active),
+            Err(err) => {
+                warn!("Skipping puzzle {}: {}", id, err);
+                collection.remove(&id);
+            }
+        }
+    };
+
+    if let Some(puzzle) = &mode.puzzle {
+        load_position_events.send(LoadPosition {
+            position: puzzle.start.clone(),
+        });
+    }
+}
+
+/// The opponent's reply to a given move of a given try, so a new puzzle never gets the
+/// reply meant for the last one
+#[derive(Default)]
+struct PendingReply {
+    key: Option<(u32, usize)>,
+    /// Counts down to the first move, which has no move of the solver's to wait for
+    timer: Timer,
+    /// The solver's piece that is still on its way to its square
+    landing: Option<Entity>,
+    requested: bool,
+}
+
+enum PuzzleButton {
+    Retry,
+    Next,
+}
+
+struct PuzzlePanel;
+
+pub struct PuzzlePlugin;
+impl Plugin for PuzzlePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<PuzzleSettings>()
+            .init_resource::<PuzzleMode>();
+        let collection = app
+            .world()
+            .get_resource::<PuzzleSettings>()
+            .map(PuzzleCollection::load);
+
+        match collection {
+            Some(Ok(collection)) => {
+                info!(
+                    "Loaded {} puzzles, F4 to solve them",
+                    collection.puzzles.len()
+                );
+                app.insert_resource(collection)
+                    .add_startup_system(init_puzzle_panel.system())
+                    .add_system(toggle_puzzles.system())
+                    .add_system(play_replies.system())
+                    .add_system(check_puzzle_moves.system())
+                    .add_system(puzzle_buttons.system())
+                    .add_system(puzzle_panel_update.system());
+            }
+            Some(Err(err)) => info!("No puzzles: {}", err),
+            None => (),
+        }
+    }
+}
+
+/// F4 puts the game aside for puzzles, and brings it back
+fn toggle_puzzles(
+    keyboard_inputs: Res<Input<KeyCode>>,
+    editor: Res<EditorMode>,
+    mut mode: ResMut<PuzzleMode>,
+    mut collection: ResMut<PuzzleCollection>,
+    mut tree: ResMut<GameTree>,
+    mut load_position_events: EventWriter<LoadPosition>,
+) {
+    if !keyboard_inputs.just_pressed(KeyCode::F4) || editor.active {
+        return;
+    }
+
+    if mode.active {
+        mode.active = false;
+        mode.puzzle = None;
+        // The tree is back on the game's position before the board is, so it carries on
+        // with the game instead of starting a new one
+        if let Some(game) = mode.saved_game.take() {
+            let position = game.position(game.current()).clone();
+            *tree = game;
+            load_position_events.send(LoadPosition { position });
+        }
+    } else {
+        mode.active = true;
+        mode.saved_game = Some(tree.clone());
+        start_puzzle(&mut mode, &mut collection, None, &mut load_position_events);
+    }
+}
+
+/// Plays the opponent's moves of the solution: the first a moment after the puzzle is on
+/// the board, the others once the solver's piece has landed
+fn play_replies(
+    time: Res<Time>,
+    mode: Res<PuzzleMode>,
+    mut pending: Local<PendingReply>,
+    mut move_made_events: EventReader<MoveMade>,
+    mut animation_finished_events: EventReader<AnimationFinished>,
+    mut move_requested_events: EventWriter<MoveRequested>,
+    pieces_query: Query<(Entity, &Piece)>,
+) {
+    let puzzle = match mode.puzzle.as_ref() {
+        Some(puzzle) if mode.active => puzzle,
+        _ => return,
+    };
+    // A piece lands at least a frame after its move, so the move is always seen first
+    for event in move_made_events.iter() {
+        if event.piece.color == puzzle.solver {
+            pending.landing = Some(event.entity);
+        }
+    }
+    for event in animation_finished_events.iter() {
+        if pending.landing == Some(event.entity) {
+            pending.landing = None;
+        }
+    }
+
+    if !puzzle.awaits_reply() {
+        return;
+    }
+    let key = (puzzle.attempt, puzzle.played);
+    let reply = puzzle.solution[puzzle.played];
+    if pending.key != Some(key) {
+        pending.key = Some(key);
+        pending.timer = Timer::from_seconds(FIRST_REPLY_DELAY, false);
+        pending.requested = false;
+    }
+    if pending.requested {
+        return;
+    }
+    let ready = if puzzle.played == 0 {
+        pending.timer.tick(time.delta()).finished()
+    } else {
+        pending.landing.is_none()
+    };
+    if !ready {
+        return;
+    }
+
+    if let Some((entity, _)) = pieces_query
+        .iter()
+        .find(|(_, piece)| (piece.x, piece.y) == reply.from)
+    {
+        move_requested_events.send(MoveRequested {
+            entity,
+            to: reply.to,
+            promotion: reply.promotion,
+        });
+        pending.requested = true;
+    }
+}
+
+/// Follows the moves made against the solution
+fn check_puzzle_moves(mut mode: ResMut<PuzzleMode>, mut move_made_events: EventReader<MoveMade>) {
+    for event in move_made_events.iter() {
+        let puzzle = match mode.puzzle.as_mut() {
+            Some(puzzle) if puzzle.state == PuzzleState::Playing => puzzle,
+            _ => continue,
+        };
+        let state = puzzle.follow(Move {
+            from: event.from,
+            to: (event.piece.x, event.piece.y),
+            promotion: event.promotion,
+            castling: event.castling,
+            drop: None,
+        });
+        if state != PuzzleState::Playing {
+            mode.finish(state);
+        }
+    }
+}
+
+fn puzzle_buttons(
+    mut mode: ResMut<PuzzleMode>,
+    mut collection: ResMut<PuzzleCollection>,
+    mut load_position_events: EventWriter<LoadPosition>,
+    buttons_query: Query<(&Interaction, &PuzzleButton), Changed<Interaction>>,
+) {
+    for (interaction, button) in buttons_query.iter() {
+        if *interaction != Interaction::Clicked || !mode.active {
+            continue;
+        }
+        match button {
+            PuzzleButton::Retry => {
+                let retry = mode.puzzle.as_ref().map(|puzzle| puzzle.puzzle.clone());
+                start_puzzle(&mut mode, &mut collection, retry, &mut load_position_events);
+            }
+            // Moving on from an unsolved puzzle gives it up
+            PuzzleButton::Next => {
+                mode.finish(PuzzleState::Failed);
+                start_puzzle(&mut mode, &mut collection, None, &mut load_position_events);
+            }
+        }
+    }
+}
+
+fn init_puzzle_panel(mut commands: Commands, mut color_materials: ResMut<Assets<ColorMaterial>>) {
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                display: Display::None,
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    right: Val::Px(10.),
+                    top: Val::Px(500.),
+                    ..Default::default()
+                },
+                flex_direction: FlexDirection::ColumnReverse,
+                align_items: AlignItems::FlexEnd,
+                max_size: Size::new(Val::Px(600.), Val::Undefined),
+                ..Default::default()
+            },
+            material: color_materials.add(Color::NONE.into()),
+            ..Default::default()
+        })
+        .insert(PuzzlePanel);
+}
+
+/// What the puzzle is about, how the solver is doing and what they can do next
+fn puzzle_panel_update(
+    mut commands: Commands,
+    mode: Res<PuzzleMode>,
+    asset_server: Res<AssetServer>,
+    mut color_materials: ResMut<Assets<ColorMaterial>>,
+    mut panel_query: Query<(Entity, &mut Style, Option<&Children>), With<PuzzlePanel>>,
+) {
+    if !mode.is_changed() {
+        return;
+    }
+    let (panel, mut style, children) = match panel_query.iter_mut().next() {
+        Some(panel) => panel,
+        None => return,
+    };
+    for &child in children.into_iter().flat_map(|children| children.iter()) {
+        commands.entity(child).despawn_recursive();
+    }
+    if !mode.active {
+        style.display = Display::None;
+        return;
+    }
+    style.display = Display::Flex;
+
+    let mut lines = Vec::new();
+    let mut buttons = Vec::new();
+    match &mode.puzzle {
+        Some(puzzle) => {
+            lines.push(format!(
+                "Puzzle {}, rated {}",
+                puzzle.puzzle.id, puzzle.puzzle.rating
+            ));
+            if !puzzle.puzzle.themes.is_empty() {
+                lines.push(puzzle.puzzle.themes.join(", "));
+            }
+            lines.push(match puzzle.state {
+                PuzzleState::Playing if puzzle.played == 0 => {
+                    "Watch the opponent's move".to_string()
+                }
+                PuzzleState::Playing if puzzle.played == 1 => {
+                    format!(
+                        "Find the best move for {}",
+                        color_name(puzzle.solver).to_lowercase()
+                    )
+                }
+                PuzzleState::Playing if puzzle.solvers_turn() => "Correct, keep going".to_string(),
+                PuzzleState::Playing => "Correct".to_string(),
+                PuzzleState::Solved => "Solved!".to_string(),
+                PuzzleState::Failed => format!(
+                    "Not the solution, which was {}",
+                    line_to_san(&puzzle.start, &puzzle.solution)
+                ),
+            });
+            if puzzle.state != PuzzleState::Playing {
+                buttons.push((PuzzleButton::Retry, "Retry"));
+            }
+            buttons.push((PuzzleButton::Next, "Next puzzle"));
+        }
+        None => lines.push("No puzzles left to try".to_string()),
+    }
+    lines.push(format!(
+        "Rating {:.0}{}, {} solved, {} failed",
+        mode.rating,
+        mode.rating_change
+            .map_or_else(String::new, |change| format!(" ({:+.0})", change)),
+        mode.solved,
+        mode.failed
+    ));
+    lines.push("F4 goes back to the game".to_string());
+
+    let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+    let button_material = color_materials.add(Color::rgb(0.25, 0.25, 0.25).into());
+    let row_material = color_materials.add(Color::NONE.into());
+    let text = |label: &str| TextBundle {
+        text: Text::with_section(
+            label,
+            TextStyle {
+                font: font.clone(),
+                font_size: 26.0,
+                color: Color::rgb(0.8, 0.8, 0.8),
+            },
+            TextAlignment::default(),
+        ),
+        ..Default::default()
+    };
+
+    commands.entity(panel).with_children(|parent| {
+        for line in lines.iter() {
+            parent.spawn_bundle(text(line));
+        }
+        parent
+            .spawn_bundle(NodeBundle {
+                material: row_material.clone(),
+                ..Default::default()
+            })
+            .with_children(|parent| {
+                for (button, label) in buttons {
+                    parent
+                        .spawn_bundle(ButtonBundle {
+                            style: Style {
+                                margin: Rect::all(Val::Px(3.)),
+                                padding: Rect::all(Val::Px(4.)),
+                                ..Default::default()
+                            },
+                            material: button_material.clone(),
+                            ..Default::default()
+                        })
+                        .insert(button)
+                        .with_children(|parent| {
+                            parent.spawn_bundle(text(label));
+                        });
+                }
+            });
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Black's pawn move lets either white rook mate on the back rank
+    const BACK_RANK: &str = "6k1/2p2ppp/8/8/8/8/5PPP/RR4K1 b - - 0 1";
+
+    fn active_puzzle() -> ActivePuzzle {
+        let puzzle = Puzzle {
+            id: "test".to_string(),
+            fen: BACK_RANK.to_string(),
+            moves: vec!["c7c6".to_string(), "a1a8".to_string()],
+            rating: 1200,
+            themes: Vec::new(),
+        };
+        ActivePuzzle::new(puzzle, true, 1).unwrap()
+    }
+
+    /// Plays a move given in UCI on the puzzle
+    fn follow(puzzle: &mut ActivePuzzle, uci: &str) -> PuzzleState {
+        let mv = parse_move(&puzzle.position, uci).unwrap();
+        puzzle.follow(mv)
+    }
+
+    #[test]
+    fn parse_with_header() {
+        let csv = format!(
+            "PuzzleId,FEN,Moves,Rating,RatingDeviation,Popularity,NbPlays,Themes,GameUrl\n\
+             00sHx,{},c7c6 a1a8,1180,75,95,300,backRankMate mateIn1 short,https://lichess.org\n",
+            BACK_RANK
+        );
+        let puzzles = PuzzleCollection::parse(&csv).unwrap();
+        assert_eq!(puzzles.len(), 1);
+        assert_eq!(puzzles[0].id, "00sHx");
+        assert_eq!(puzzles[0].fen, BACK_RANK);
+        assert_eq!(puzzles[0].moves, ["c7c6", "a1a8"]);
+        assert_eq!(puzzles[0].rating, 1180);
+        assert_eq!(puzzles[0].themes, ["backRankMate", "mateIn1", "short"]);
+    }
+
+    #[test]
+    fn parse_without_header() {
+        let csv = format!(
+            "{0},c7c6 a1a8,1300,mateIn1\n\n{0},c7c6,1300,mateIn1\n",
+            BACK_RANK
+        );
+        let puzzles = PuzzleCollection::parse(&csv).unwrap();
+        // The second puzzle has no move for the solver and is skipped
+        assert_eq!(puzzles.len(), 1);
+        assert_eq!(puzzles[0].id, "#1");
+        assert_eq!(puzzles[0].rating, 1300);
+        assert_eq!(puzzles[0].themes, ["mateIn1"]);
+
+        assert!(PuzzleCollection::parse("not,a,puzzle").is_err());
+        assert!(PuzzleCollection::parse("PuzzleId,FEN\nx,8/8/8/8/8/8/8/8 w - - 0 1").is_err());
+    }
+
+    #[test]
+    fn parse_quoted_fields() {
+        let csv = format!(
+            "\"{}\",\"c7c6 a1a8\",\"1,400\",\"mateIn1 \"\"short\"\"\"",
+            BACK_RANK
+        );
+        assert_eq!(
+            csv_fields(&csv),
+            [BACK_RANK, "c7c6 a1a8", "1,400", "mateIn1 \"short\""]
+        );
+        let puzzles = PuzzleCollection::parse(&csv).unwrap();
+        assert_eq!(puzzles[0].moves, ["c7c6", "a1a8"]);
+        // A rating that isn't a number counts as the starting one
+        assert_eq!(puzzles[0].rating, START_RATING as u32);
+    }
+
+    #[test]
+    fn correct_moves_solve() {
+        let mut puzzle = active_puzzle();
+        assert!(puzzle.awaits_reply());
+        assert_eq!(follow(&mut puzzle, "c7c6"), PuzzleState::Playing);
+        assert!(puzzle.solvers_turn());
+        assert_eq!(follow(&mut puzzle, "a1a8"), PuzzleState::Solved);
+    }
+
+    #[test]
+    fn wrong_move_fails() {
+        let mut puzzle = active_puzzle();
+        follow(&mut puzzle, "c7c6");
+        assert_eq!(follow(&mut puzzle, "h2h3"), PuzzleState::Failed);
+    }
+
+    #[test]
+    fn other_mate_solves() {
+        let mut puzzle = active_puzzle();
+        follow(&mut puzzle, "c7c6");
+        assert_eq!(follow(&mut puzzle, "b1b8"), PuzzleState::Solved);
+    }
+
+    #[test]
+    fn rating_moves_with_rated_results() {
+        let mut mode = PuzzleMode {
+            puzzle: Some(active_puzzle()),
+            ..Default::default()
+        };
+        mode.finish(PuzzleState::Solved);
+        assert_eq!(mode.solved, 1);
+        assert!(mode.rating > START_RATING);
+
+        // A puzzle is only finished once
+        mode.finish(PuzzleState::Failed);
+        assert_eq!((mode.solved, mode.failed), (1, 0));
+    }
+}
@@ -0,0 +1,525 @@
+use crate::{
+    analysis::*, engine::*, events::*, game_tree::*, notation::*, pieces::*, position::*,
+    puzzle::*, tablebase::*,
+};
+use bevy::prelude::*;
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+/// How deep every position of a finished game is searched
+const REVIEW_DEPTH: u32 = 4;
+
+/// Evaluations are capped here, so a mate missed in a won position doesn't weigh more
+/// than a piece blundered in an even one
+const SCORE_CAP: i32 = 1000;
+
+/// Centipawns lost from which a move counts as an inaccuracy, a mistake or a blunder
+const INACCURACY: i32 = 50;
+const MISTAKE: i32 = 100;
+const BLUNDER: i32 = 300;
+
+pub struct ReviewSettings {
+    /// Review games when they end. The window then stays open to show the report
+    pub enabled: bool,
+}
+impl Default for ReviewSettings {
+    fn default() -> Self {
+        Self { enabled: true }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MoveClass {
+    Good,
+    Inaccuracy,
+    Mistake,
+    Blunder,
+}
+impl MoveClass {
+    fn from_loss(loss: i32) -> Self {
+        if loss >= BLUNDER {
+            MoveClass::Blunder
+        } else if loss >= MISTAKE {
+            MoveClass::Mistake
+        } else if loss >= INACCURACY {
+            MoveClass::Inaccuracy
+        } else {
+            MoveClass::Good
+        }
+    }
+
+    /// The NAG for `?!`, `?` and `??`
+    fn nag(self) -> Option<u8> {
+        match self {
+            MoveClass::Good => None,
+            MoveClass::Inaccuracy => Some(6),
+            MoveClass::Mistake => Some(2),
+            MoveClass::Blunder => Some(4),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            MoveClass::Good => "Good move",
+            MoveClass::Inaccuracy => "Inaccuracy",
+            MoveClass::Mistake => "Mistake",
+            MoveClass::Blunder => "Blunder",
+        }
+    }
+}
+
+/// How one move of the game held up
+#[derive(Clone, Debug)]
+pub struct ReviewedMove {
+    pub node: NodeId,
+    pub color: PieceColor,
+    /// Evaluations before and after the move, from white's point of view
+    pub score_before: i32,
+    pub score_after: i32,
+    pub loss: i32,
+    pub class: MoveClass,
+    /// What the engine preferred, when that wasn't the move played
+    pub best: Option<String>,
+    /// From 0 to 100, by how much the move lowered the mover's winning chances
+    pub accuracy: f32,
+}
+
+/// The mover's chances in percent for a score from white's point of view
+fn win_chance(score: i32, color: PieceColor) -> f32 {
+    let score = match color {
+        PieceColor::White => score,
+        PieceColor::Black => -score,
+    };
+    50. + 50. * (2. / (1. + (-0.003_682_08 * score as f32).exp()) - 1.)
+}
+
+/// Lichess's formula for how accurate a move was given the winning chances it threw away
+fn move_accuracy(win_before: f32, win_after: f32) -> f32 {
+    let accuracy = 103.1668 * (-0.04354 * (win_before - win_after).max(0.)).exp() - 3.1669;
+    accuracy.max(0.).min(100.)
+}
+
+/// Engine evaluation of a position from white's point of view, capped at `SCORE_CAP`
+fn evaluate_position(
+    position: &Position,
+    tablebase: Option<&Tablebase>,
+    stop: &AtomicBool,
+) -> (i32, Option<Move>) {
+    let (score, best) = match search(position, REVIEW_DEPTH, tablebase, stop, |_| ()) {
+        Some(info) => (info.score, info.pv.first().copied()),
+        // The game is over
+        None => (game_over_score(position.status(), position.turn, 0), None),
+    };
+    let score = score.max(-SCORE_CAP).min(SCORE_CAP);
+    match position.turn {
+        PieceColor::White => (score, best),
+        PieceColor::Black => (-score, best),
+    }
+}
+
+/// Reviews the moves of a line, the positions before and after each one given in order
+fn review_line(
+    line: &[(NodeId, Move, Position)],
+    start: &Position,
+    tablebase: Option<&Tablebase>,
+    stop: &AtomicBool,
+    progress: &Mutex<ReviewProgress>,
+) -> Vec<ReviewedMove> {
+    let mut reviewed = Vec::new();
+    let mut before = start.clone();
+    let mut evaluation = evaluate_position(&before, tablebase, stop);
+
+    for (i, (node, mv, after)) in line.iter().enumerate() {
+        let next_evaluation = evaluate_position(after, tablebase, stop);
+        let color = before.turn;
+        let (score_before, best) = evaluation;
+        let score_after = next_evaluation.0;
+
+        let loss = match color {
+            PieceColor::White => score_before - score_after,
+            PieceColor::Black => score_after - score_before,
+        }
+        .max(0);
+        let class = MoveClass::from_loss(loss);
+        reviewed.push(ReviewedMove {
+            node: *node,
+            color,
+            score_before,
+            score_after,
+            loss,
+            class,
+            best: best
+                .filter(|best| best != mv)
+                .map(|best| move_to_san(&before, best)),
+            accuracy: move_accuracy(
+                win_chance(score_before, color),
+                win_chance(score_after, color),
+            ),
+        });
+
+        if let Ok(mut progress) = progress.lock() {
+            progress.done = i + 1;
+        }
+        before = after.clone();
+        evaluation = next_evaluation;
+    }
+    reviewed
+}
+
+/// Per-player totals of a review
+pub struct ReviewSummary {
+    pub accuracy: f32,
+    pub average_loss: f32,
+    pub inaccuracies: usize,
+    pub mistakes: usize,
+    pub blunders: usize,
+}
+
+pub fn summarize(moves: &[ReviewedMove], color: PieceColor) -> ReviewSummary {
+    let moves: Vec<&ReviewedMove> = moves.iter().filter(|mv| mv.color == color).collect();
+    let count = |class| moves.iter().filter(|mv| mv.class == class).count();
+    let average = |value: &dyn Fn(&ReviewedMove) -> f32| {
+        if moves.is_empty() {
+            0.
+        } else {
+            moves.iter().map(|mv| value(mv)).sum::<f32>() / moves.len() as f32
+        }
+    };
+    ReviewSummary {
+        accuracy: average(&|mv| mv.accuracy),
+        average_loss: average(&|mv| mv.loss as f32),
+        inaccuracies: count(MoveClass::Inaccuracy),
+        mistakes: count(MoveClass::Mistake),
+        blunders: count(MoveClass::Blunder),
+    }
+}
+
+/// The comment written into the PGN, e.g. "Blunder (+0.35 -> -2.80). Best was Nf3"
+fn review_comment(reviewed: &ReviewedMove) -> String {
+    let mut comment = format!(
+        "{} ({} -> {})",
+        reviewed.class.name(),
+        score_text(reviewed.score_before, PieceColor::White),
+        score_text(reviewed.score_after, PieceColor::White)
+    );
+    if let Some(best) = &reviewed.best {
+        comment.push_str(&format!(". Best was {}", best));
+    }
+    comment
+}
+
+#[derive(Default)]
+struct ReviewProgress {
+    done: usize,
+    total: usize,
+    moves: Option<Vec<ReviewedMove>>,
+}
+
+/// The review of the last finished game, worked out on its own thread
+#[derive(Default)]
+pub struct GameReview {
+    progress: Option<Arc<Mutex<ReviewProgress>>>,
+    stop: Arc<AtomicBool>,
+    /// Set once the review is done and its annotations are in the game tree
+    pub moves: Option<Vec<ReviewedMove>>,
+    /// Asked for a game that didn't end on the board, such as one loaded from a database
+    requested: bool,
+}
+impl GameReview {
+    /// Reviews the main line of the game tree as it is, finished or not
+    pub fn request(&mut self) {
+        self.requested = true;
+    }
+}
+
+struct ReviewText;
+
+pub struct ReviewPlugin;
+impl Plugin for ReviewPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<ReviewSettings>()
+            .init_resource::<GameReview>()
+            .add_startup_system(init_review_text.system())
+            .add_system(start_review.system())
+            .add_system(finish_review.system())
+            .add_system(review_text_update.system());
+    }
+}
+
+/// Starts the review once the game tree holds the final move
+fn start_review(
+    settings: Res<ReviewSettings>,
+    analysis: Res<AnalysisMode>,
+    puzzles: Res<PuzzleMode>,
+    tree: Res<GameTree>,
+    tablebase: Option<Res<Tablebase>>,
+    mut review: ResMut<GameReview>,
+    mut pending: Local<bool>,
+    mut game_ended_events: EventReader<GameEnded>,
+) {
+    if game_ended_events.iter().next().is_some()
+        && settings.enabled
+        && !analysis.active
+        && !puzzles.active
+    {
+        *pending = true;
+    }
+    let finished = *pending && tree.position(tree.current()).status() != GameStatus::Ongoing;
+    if !finished && !review.requested {
+        return;
+    }
+    *pending = false;
+
+    let start = tree.position(ROOT).clone();
+    let line: Vec<(NodeId, Move, Position)> = tree
+        .line(ROOT)
+        .into_iter()
+        .filter_map(|node| Some((node, tree.mv(node)?, tree.position(node).clone())))
+        .collect();
+    let progress = Arc::new(Mutex::new(ReviewProgress {
+        total: line.len(),
+        ..Default::default()
+    }));
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let tablebase = tablebase.as_deref().cloned();
+    let thread_stop = stop.clone();
+    let thread_progress = progress.clone();
+    thread::spawn(move || {
+        let moves = review_line(
+            &line,
+            &start,
+            tablebase.as_ref(),
+            &thread_stop,
+            &thread_progress,
+        );
+        if let Ok(mut progress) = thread_progress.lock() {
+            progress.moves = Some(moves);
+        }
+    });
+
+    // A review still running for an earlier game is dropped
+    review.stop.store(true, Ordering::Relaxed);
+    *review = GameReview {
+        progress: Some(progress),
+        stop,
+        moves: None,
+        requested: false,
+    };
+}
+
+/// Writes the finished review into the game tree as NAGs and comments, so it goes out
+/// with the PGN export
+fn finish_review(mut review: ResMut<GameReview>, mut tree: ResMut<GameTree>) {
+    let moves = match review
+        .progress
+        .as_ref()
+        .and_then(|progress| progress.lock().ok()?.moves.take())
+    {
+        Some(moves) => moves,
+        None => return,
+    };
+
+    for reviewed in moves.iter() {
+        let comment = match reviewed.class {
+            MoveClass::Good => None,
+            _ => Some(review_comment(reviewed)),
+        };
+        tree.annotate(reviewed.node, reviewed.class.nag(), comment);
+    }
+    review.progress = None;
+    review.moves = Some(moves);
+}
+
+fn init_review_text(
+    mut commands: Commands,
+    asset_server: ResMut<AssetServer>,
+    mut color_materials: ResMut<Assets<ColorMaterial>>,
+) {
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    right: Val::Px(10.),
+                    bottom: Val::Px(100.),
+                    ..Default::default()
+                },
+                max_size: Size::new(Val::Px(700.), Val::Undefined),
+                ..Default::default()
+            },
+            material: color_materials.add(Color::NONE.into()),
+            ..Default::default()
+        })
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(TextBundle {
+                    text: Text::with_section(
+                        String::new(),
+                        TextStyle {
+                            font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                            font_size: 26.0,
+                            color: Color::rgb(0.8, 0.8, 0.8),
+                        },
+                        TextAlignment::default(),
+                    ),
+                    ..Default::default()
+                })
+                .insert(ReviewText);
+        });
+}
+
+/// Shows how far the review got, then the accuracy of both players and the flagged moves
+fn review_text_update(
+    review: Res<GameReview>,
+    tree: Res<GameTree>,
+    mut shown: Local<Option<usize>>,
+    mut query: Query<&mut Text, With<ReviewText>>,
+) {
+    let value = if let Some(moves) = &review.moves {
+        if !review.is_changed() {
+            return;
+        }
+        *shown = None;
+        let mut lines = vec!["Game review".to_string()];
+        for &color in [PieceColor::White, PieceColor::Black].iter() {
+            let summary = summarize(moves, color);
+            lines.push(format!(
+                "{}: accuracy {:.0}%, average loss {:.0}, {} inaccuracies, {} mistakes, {} blunders",
+                color_name(color),
+                summary.accuracy,
+                summary.average_loss,
+                summary.inaccuracies,
+                summary.mistakes,
+                summary.blunders
+            ));
+        }
+        for reviewed in moves.iter().filter(|mv| mv.class != MoveClass::Good) {
+            lines.push(format!(
+                "{} {}",
+                tree.annotated_san(reviewed.node),
+                review_comment(reviewed)
+            ));
+        }
+        lines.push("Export PGN saves the annotated game".to_string());
+        lines.join("\n")
+    } else if let Some(progress) = &review.progress {
+        let (done, total) = match progress.lock() {
+            Ok(progress) => (progress.done, progress.total),
+            Err(_) => return,
+        };
+        if *shown == Some(done) {
+            return;
+        }
+        *shown = Some(done);
+        format!("Reviewing the game... {}/{} moves", done, total)
+    } else {
+        return;
+    };
+
+    for mut text in query.iter_mut() {
+        text.sections[0].value = value.clone();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A move by `color` between two evaluations from white's point of view
+    fn reviewed(color: PieceColor, score_before: i32, score_after: i32) -> ReviewedMove {
+        let loss = match color {
+            PieceColor::White => score_before - score_after,
+            PieceColor::Black => score_after - score_before,
+        }
+        .max(0);
+        ReviewedMove {
+            node: ROOT,
+            color,
+            score_before,
+            score_after,
+            loss,
+            class: MoveClass::from_loss(loss),
+            best: None,
+            accuracy: move_accuracy(
+                win_chance(score_before, color),
+                win_chance(score_after, color),
+            ),
+        }
+    }
+
+    #[test]
+    fn classes_start_at_their_thresholds() {
+        let class = MoveClass::from_loss;
+        assert_eq!(class(0), MoveClass::Good);
+        assert_eq!(class(INACCURACY - 1), MoveClass::Good);
+        assert_eq!(class(INACCURACY), MoveClass::Inaccuracy);
+        assert_eq!(class(MISTAKE - 1), MoveClass::Inaccuracy);
+        assert_eq!(class(MISTAKE), MoveClass::Mistake);
+        assert_eq!(class(BLUNDER - 1), MoveClass::Mistake);
+        assert_eq!(class(BLUNDER), MoveClass::Blunder);
+        assert_eq!(class(SCORE_CAP * 2), MoveClass::Blunder);
+    }
+
+    #[test]
+    fn accuracy() {
+        // Keeping or improving the winning chances is perfect
+        assert!(move_accuracy(50., 50.) > 99.9);
+        assert!((move_accuracy(40., 60.) - move_accuracy(50., 50.)).abs() < 1e-3);
+        // The more thrown away, the less accurate, down to nothing
+        assert!(move_accuracy(50., 45.) > move_accuracy(50., 30.));
+        assert!(move_accuracy(100., 0.).abs() < 1e-3);
+
+        // Each side's accuracy comes from its own winning chances
+        assert!(reviewed(PieceColor::White, 0, -300).accuracy < 60.);
+        assert!(reviewed(PieceColor::Black, 0, -300).accuracy > 99.9);
+    }
+
+    #[test]
+    fn totals_per_color() {
+        let moves = [
+            reviewed(PieceColor::White, 20, 20),
+            reviewed(PieceColor::Black, 20, -400),
+            reviewed(PieceColor::White, -400, -800),
+            reviewed(PieceColor::Black, -800, -700),
+            reviewed(PieceColor::White, -700, -760),
+            reviewed(PieceColor::Black, -760, -700),
+        ];
+
+        let white = summarize(&moves, PieceColor::White);
+        assert_eq!(
+            (white.inaccuracies, white.mistakes, white.blunders),
+            (1, 0, 1)
+        );
+        assert!((white.average_loss - 460. / 3.).abs() < 1e-3);
+        let black = summarize(&moves, PieceColor::Black);
+        assert_eq!(
+            (black.inaccuracies, black.mistakes, black.blunders),
+            (1, 1, 0)
+        );
+        assert!((black.average_loss - 160. / 3.).abs() < 1e-3);
+        assert!(white.accuracy < black.accuracy);
+
+        let nothing = summarize(&moves[..1], PieceColor::Black);
+        assert_eq!(nothing.blunders, 0);
+        assert!(nothing.accuracy.abs() < 1e-3 && nothing.average_loss.abs() < 1e-3);
+    }
+
+    #[test]
+    fn comments() {
+        let mut blunder = reviewed(PieceColor::White, 35, -280);
+        blunder.best = Some("Nf3".to_string());
+        assert_eq!(
+            review_comment(&blunder),
+            "Blunder (+0.35 -> -2.80). Best was Nf3"
+        );
+        // Scores read from white's point of view whoever moved
+        let black = reviewed(PieceColor::Black, -100, 20);
+        assert_eq!(review_comment(&black), "Mistake (-1.00 -> +0.20)");
+    }
+}
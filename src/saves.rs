@@ -0,0 +1,654 @@
+use crate::{
+    accessibility::*, animation::*, editor::*, events::*, game_tree::*, notation::*, position::*,
+    puzzle::*, sound::*, theme::*, variant::*, view2d::*,
+};
+use bevy::prelude::*;
+use ron::ser::PrettyConfig;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// The slot written after every move, and offered for resuming at startup
+const AUTOSAVE: &str = "autosave";
+
+/// Slots the save menu always offers to save into
+const SLOTS: [&str; 5] = ["slot 1", "slot 2", "slot 3", "slot 4", "slot 5"];
+
+pub struct SaveSettings {
+    /// Directory holding one `.ron` file per slot
+    pub dir: PathBuf,
+}
+impl Default for SaveSettings {
+    fn default() -> Self {
+        Self {
+            dir: "saves".into(),
+        }
+    }
+}
+impl SaveSettings {
+    fn path(&self, slot: &str) -> PathBuf {
+        self.dir.join(format!("{}.ron", slot))
+    }
+}
+
+/// A move of the saved tree, following the start (0) or an earlier move (its index + 1)
+#[derive(Serialize, Deserialize)]
+struct SavedMove {
+    parent: usize,
+    uci: String,
+    /// Tells castling apart from a king move to the same square in Chess960
+    castling: bool,
+    nag: Option<u8>,
+    comment: Option<String>,
+}
+
+/// Sound, animation and announcement settings. The speech command is left out, as loading
+/// a file shouldn't start a program
+#[derive(Serialize, Deserialize)]
+struct SavedSettings {
+    volume: f32,
+    muted: bool,
+    animation_speed: f32,
+    arc_height: f32,
+    capture_duration: f32,
+    easing: String,
+    announce: bool,
+}
+impl SavedSettings {
+    fn new(
+        sound: &SoundSettings,
+        animation: &AnimationSettings,
+        accessibility: &AccessibilitySettings,
+    ) -> Self {
+        Self {
+            volume: sound.volume,
+            muted: sound.muted,
+            animation_speed: animation.speed,
+            arc_height: animation.arc_height,
+            capture_duration: animation.capture_duration,
+            easing: match animation.easing {
+                Easing::QuadIn => "quad-in",
+                Easing::CubicInOut => "cubic-in-out",
+            }
+            .to_string(),
+            announce: accessibility.stdout,
+        }
+    }
+
+    fn apply(
+        &self,
+        sound: &mut SoundSettings,
+        animation: &mut AnimationSettings,
+        accessibility: &mut AccessibilitySettings,
+    ) {
+        sound.volume = self.volume.max(0.).min(1.);
+        sound.muted = self.muted;
+        // A speed of zero would leave pieces standing still halfway
+        if self.animation_speed > 0. {
+            animation.speed = self.animation_speed;
+        }
+        animation.arc_height = self.arc_height;
+        animation.capture_duration = self.capture_duration.max(0.);
+        animation.easing = match self.easing.as_str() {
+            "quad-in" => Easing::QuadIn,
+            _ => Easing::CubicInOut,
+        };
+        accessibility.stdout = self.announce;
+    }
+}
+
+/// A game with all its variations and annotations, and the settings it was played with.
+/// Moves are kept in UCI and played back on loading, so a save can't hold an illegal game
+#[derive(Serialize, Deserialize)]
+struct SaveFile {
+    variant: String,
+    /// Shredder-FEN, so every castling right comes back as it was
+    start: String,
+    checks_given: [u8; 2],
+    moves: Vec<SavedMove>,
+    current: usize,
+    theme: String,
+    view: String,
+    /// Missing from older saves, which keep the settings already in use
+    #[serde(default)]
+    settings: Option<SavedSettings>,
+}
+impl SaveFile {
+    fn new(tree: &GameTree, theme: &str, view_mode: ViewMode, settings: SavedSettings) -> Self {
+        let start = tree.position(ROOT);
+        let mut moves = Vec::new();
+        let mut current = 0;
+
+        // Depth first with the children in order, so each line comes back in its place
+        let mut stack: Vec<(NodeId, usize)> = tree
+            .children(ROOT)
+            .iter()
+            .rev()
+            .map(|&child| (child, 0))
+            .collect();
+        while let Some((node, parent)) = stack.pop() {
+            let mv = match tree.mv(node) {
+                Some(mv) => mv,
+                None => continue,
+            };
+            moves.push(SavedMove {
+                parent,
+                uci: move_to_uci(tree.position(tree.parent(node).unwrap_or(ROOT)), mv),
+                castling: mv.castling,
+                nag: tree.nag(node),
+                comment: tree.comment(node).map(str::to_string),
+            });
+            let index = moves.len();
+            if node == tree.current() {
+                current = index;
+            }
+            stack.extend(
+                tree.children(node)
+                    .iter()
+                    .rev()
+                    .map(|&child| (child, index)),
+            );
+        }
+
+        Self {
+            variant: start.rules().name().to_string(),
+            start: position_to_shredder_fen(start),
+            checks_given: start.checks_given,
+            moves,
+            current,
+            theme: theme.to_string(),
+            view: match view_mode {
+                ViewMode::ThreeD => "3d",
+                ViewMode::TwoD => "2d",
+            }
+            .to_string(),
+            settings: Some(settings),
+        }
+    }
+
+    fn read(path: &Path) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path).map_err(|err| err.to_string())?;
+        ron::de::from_str(&contents).map_err(|err| err.to_string())
+    }
+
+    fn write(&self, path: &Path) -> Result<(), String> {
+        let contents =
+            ron::ser::to_string_pretty(self, PrettyConfig::new()).map_err(|err| err.to_string())?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(|err| err.to_string())?;
+        }
+        std::fs::write(path, contents).map_err(|err| err.to_string())
+    }
+
+    /// Plays the game back into a tree
+    fn game(&self) -> Result<GameTree, String> {
+        let variant = VariantKind::from_name(&self.variant)
+            .ok_or_else(|| format!("Unknown variant {:?}", self.variant))?;
+        let mut start = parse_fen(&self.start)?;
+        start.variant = variant;
+        start.checks_given = self.checks_given;
+
+        let mut tree = GameTree::new(start);
+        for (index, saved) in self.moves.iter().enumerate() {
+            if saved.parent > index {
+                return Err(format!("{} comes before the move it follows", saved.uci));
+            }
+            let find = |position: &Position| {
+                position.legal_moves().into_iter().find(|&mv| {
+                    move_to_uci(position, mv) == saved.uci && mv.castling == saved.castling
+                })
+            };
+            // Moves made out of turn while analysing were played after a pass
+            let mut position = tree.position(saved.parent).clone();
+            let mv = match find(&position) {
+                Some(mv) => mv,
+                None => {
+                    position.pass();
+                    find(&position).ok_or_else(|| format!("Illegal move {}", saved.uci))?
+                }
+            };
+
+            let san = move_to_san(&position, mv);
+            position.play(mv);
+            tree.set_current(saved.parent);
+            let node = tree.add_move(mv, san, position);
+            if node != index + 1 {
+                return Err(format!("{} is saved twice", saved.uci));
+            }
+            tree.annotate(node, saved.nag, saved.comment.clone());
+        }
+
+        if self.current > self.moves.len() {
+            return Err(format!("No move {} to continue from", self.current));
+        }
+        tree.set_current(self.current);
+        Ok(tree)
+    }
+
+    fn view_mode(&self) -> ViewMode {
+        match self.view.as_str() {
+            "2d" => ViewMode::TwoD,
+            _ => ViewMode::ThreeD,
+        }
+    }
+}
+
+/// e.g. "Standard, move 14, white to move"
+fn describe(tree: &GameTree) -> String {
+    let position = tree.position(tree.current());
+    format!(
+        "{}, move {}, {} to move",
+        position.rules().name(),
+        position.fullmove_number,
+        color_name(position.turn).to_lowercase()
+    )
+}
+
+/// The load and save menu, and the game offered for resuming at startup
+#[derive(Default)]
+pub struct SaveMenu {
+    pub open: bool,
+    /// The unfinished game autosaved last time, until it is resumed or discarded, or a new
+    /// game is started by playing a move. Nothing is autosaved before then, so it can't be
+    /// lost by accident
+    resume: Option<(SaveFile, String)>,
+    /// Every slot with a description of the game in it, if there is one
+    slots: Vec<(String, Option<String>)>,
+    /// How the last save or load went
+    message: Option<String>,
+}
+impl SaveMenu {
+    /// Lists the numbered slots and any other saves found: the autosave, and any slot
+    /// renamed by hand
+    fn refresh(&mut self, settings: &SaveSettings) {
+        let mut names: Vec<String> = SLOTS.iter().map(|slot| slot.to_string()).collect();
+        if let Ok(entries) = std::fs::read_dir(&settings.dir) {
+            let mut found: Vec<String> = entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| {
+                    path.extension()
+                        .map_or(false, |extension| extension == "ron")
+                })
+                .filter_map(|path| Some(path.file_stem()?.to_str()?.to_string()))
+                .filter(|name| !names.contains(name))
+                .collect();
+            found.sort();
+            names.extend(found);
+        }
+
+        self.slots = names
+            .into_iter()
+            .map(|name| {
+                let description = SaveFile::read(&settings.path(&name))
+                    .and_then(|save| save.game())
+                    .map(|game| describe(&game))
+                    .ok();
+                (name, description)
+            })
+            .collect();
+    }
+}
+
+enum SaveButton {
+    Resume,
+    Discard,
+    Save(String),
+    Load(String),
+}
+
+struct SavePanel;
+
+pub struct SavePlugin;
+impl Plugin for SavePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<SaveSettings>();
+        let mut menu = SaveMenu::default();
+        if let Some(settings) = app.world().get_resource::<SaveSettings>() {
+            let path = settings.path(AUTOSAVE);
+            match SaveFile::read(&path).and_then(|save| Ok((save.game()?, save))) {
+                Ok((game, save)) => {
+                    let unfinished = !game.line(ROOT).is_empty()
+                        && game.position(game.current()).status() == GameStatus::Ongoing;
+                    if unfinished {
+                        menu.resume = Some((save, describe(&game)));
+                    }
+                }
+                Err(err) if path.exists() => warn!("Could not read {}: {}", path.display(), err),
+                Err(_) => (),
+            }
+        }
+
+        app.insert_resource(menu)
+            .add_startup_system(init_save_panel.system())
+            .add_system(toggle_save_menu.system())
+            .add_system(drop_resume_offer.system())
+            .add_system(autosave.system())
+            .add_system(save_buttons.system())
+            .add_system(save_panel_update.system());
+    }
+}
+
+/// Playing on instead of answering the offer to resume starts a new game, which is autosaved
+/// from then on in place of the old one
+fn drop_resume_offer(
+    puzzles: Res<PuzzleMode>,
+    editor: Res<EditorMode>,
+    mut menu: ResMut<SaveMenu>,
+    mut move_made_events: EventReader<MoveMade>,
+) {
+    // Puzzles and the editor don't touch the game that would be autosaved
+    let played = move_made_events.iter().count() > 0;
+    if played && menu.resume.is_some() && !puzzles.active && !editor.active {
+        menu.resume = None;
+    }
+}
+
+/// Writes the game to the autosave slot whenever it or the settings saved with it change
+fn autosave(
+    settings: Res<SaveSettings>,
+    menu: Res<SaveMenu>,
+    tree: Res<GameTree>,
+    themes: Res<Themes>,
+    view_mode: Res<ViewMode>,
+    sound: Res<SoundSettings>,
+    animation: Res<AnimationSettings>,
+    accessibility: Res<AccessibilitySettings>,
+    puzzles: Res<PuzzleMode>,
+    editor: Res<EditorMode>,
+    mut saved: Local<Option<String>>,
+) {
+    let settings_changed = themes.is_changed()
+        || view_mode.is_changed()
+        || sound.is_changed()
+        || animation.is_changed()
+        || accessibility.is_changed();
+    if !tree.is_changed() && !settings_changed {
+        return;
+    }
+    // Puzzles and the editor put the game aside, and it comes back when they end
+    if menu.resume.is_some() || puzzles.active || editor.active {
+        return;
+    }
+
+    let save = SaveFile::new(
+        &tree,
+        &themes.current().name,
+        *view_mode,
+        SavedSettings::new(&sound, &animation, &accessibility),
+    );
+    let contents = ron::ser::to_string(&save).ok();
+    if contents.is_none() || *saved == contents {
+        return;
+    }
+    match save.write(&settings.path(AUTOSAVE)) {
+        Ok(()) => *saved = contents,
+        Err(err) => warn!("Could not autosave: {}", err),
+    }
+}
+
+/// F6 opens and closes the list of save slots
+fn toggle_save_menu(
+    keyboard_inputs: Res<Input<KeyCode>>,
+    settings: Res<SaveSettings>,
+    puzzles: Res<PuzzleMode>,
+    editor: Res<EditorMode>,
+    mut menu: ResMut<SaveMenu>,
+) {
+    if !keyboard_inputs.just_pressed(KeyCode::F6) || puzzles.active || editor.active {
+        return;
+    }
+    menu.open = !menu.open;
+    menu.message = None;
+    if menu.open {
+        menu.refresh(&settings);
+    }
+}
+
+/// Puts a saved game on the board
+fn load_game(
+    save: &SaveFile,
+    tree: &mut GameTree,
+    load_position_events: &mut EventWriter<LoadPosition>,
+) -> Result<(), String> {
+    let game = save.game()?;
+    let position = game.position(game.current()).clone();
+    *tree = game;
+    load_position_events.send(LoadPosition { position });
+    Ok(())
+}
+
+fn save_buttons(
+    settings: Res<SaveSettings>,
+    mut menu: ResMut<SaveMenu>,
+    mut tree: ResMut<GameTree>,
+    mut themes: ResMut<Themes>,
+    mut view_mode: ResMut<ViewMode>,
+    mut sound: ResMut<SoundSettings>,
+    mut animation: ResMut<AnimationSettings>,
+    mut accessibility: ResMut<AccessibilitySettings>,
+    mut load_position_events: EventWriter<LoadPosition>,
+    buttons_query: Query<(&Interaction, &SaveButton), Changed<Interaction>>,
+) {
+    for (interaction, button) in buttons_query.iter() {
+        if *interaction != Interaction::Clicked {
+            continue;
+        }
+        // The game comes back with the theme, view and other settings it was saved with
+        let loaded = match button {
+            SaveButton::Resume => match menu.resume.take() {
+                Some((save, _)) => match load_game(&save, &mut tree, &mut load_position_events) {
+                    Ok(()) => Some(save),
+                    Err(err) => {
+                        warn!("Could not resume the game: {}", err);
+                        None
+                    }
+                },
+                None => None,
+            },
+            SaveButton::Discard => {
+                menu.resume = None;
+                None
+            }
+            SaveButton::Save(slot) => {
+                let save = SaveFile::new(
+                    &tree,
+                    &themes.current().name,
+                    *view_mode,
+                    SavedSettings::new(&sound, &animation, &accessibility),
+                );
+                menu.message = Some(match save.write(&settings.path(slot)) {
+                    Ok(()) => format!("Saved to {}", slot),
+                    Err(err) => format!("Could not save to {}: {}", slot, err),
+                });
+                menu.refresh(&settings);
+                None
+            }
+            SaveButton::Load(slot) => {
+                let loaded = SaveFile::read(&settings.path(slot)).and_then(|save| {
+                    load_game(&save, &mut tree, &mut load_position_events).map(|()| save)
+                });
+                match loaded {
+                    Ok(save) => {
+                        menu.open = false;
+                        Some(save)
+                    }
+                    Err(err) => {
+                        menu.message = Some(format!("Could not load {}: {}", slot, err));
+                        None
+                    }
+                }
+            }
+        };
+
+        if let Some(save) = loaded {
+            themes.select(&save.theme);
+            *view_mode = save.view_mode();
+            if let Some(saved_settings) = &save.settings {
+                saved_settings.apply(&mut sound, &mut animation, &mut accessibility);
+            }
+        }
+    }
+}
+
+fn init_save_panel(mut commands: Commands, mut color_materials: ResMut<Assets<ColorMaterial>>) {
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                display: Display::None,
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    right: Val::Px(10.),
+                    top: Val::Px(250.),
+                    ..Default::default()
+                },
+                flex_direction: FlexDirection::ColumnReverse,
+                align_items: AlignItems::FlexEnd,
+                max_size: Size::new(Val::Px(700.), Val::Undefined),
+                ..Default::default()
+            },
+            material: color_materials.add(Color::NONE.into()),
+            ..Default::default()
+        })
+        .insert(SavePanel);
+}
+
+/// The offer to resume, or else one row per slot with what is saved in it
+fn save_panel_update(
+    mut commands: Commands,
+    menu: Res<SaveMenu>,
+    asset_server: Res<AssetServer>,
+    mut color_materials: ResMut<Assets<ColorMaterial>>,
+    mut panel_query: Query<(Entity, &mut Style, Option<&Children>), With<SavePanel>>,
+) {
+    if !menu.is_changed() {
+        return;
+    }
+    let (panel, mut style, children) = match panel_query.iter_mut().next() {
+        Some(panel) => panel,
+        None => return,
+    };
+    for &child in children.into_iter().flat_map(|children| children.iter()) {
+        commands.entity(child).despawn_recursive();
+    }
+
+    let mut rows: Vec<(String, Vec<(SaveButton, &str)>)> = Vec::new();
+    if let Some((_, description)) = &menu.resume {
+        rows.push((
+            format!("Resume the unfinished game? {}", description),
+            vec![
+                (SaveButton::Resume, "Resume"),
+                (SaveButton::Discard, "New game"),
+            ],
+        ));
+    } else if menu.open {
+        rows.push(("Saved games (F6 to close)".to_string(), Vec::new()));
+        for (slot, description) in menu.slots.iter() {
+            let mut buttons = vec![(SaveButton::Save(slot.clone()), "Save")];
+            if description.is_some() {
+                buttons.push((SaveButton::Load(slot.clone()), "Load"));
+            }
+            rows.push((
+                format!("{}: {}", slot, description.as_deref().unwrap_or("empty")),
+                buttons,
+            ));
+        }
+        rows.extend(menu.message.clone().map(|message| (message, Vec::new())));
+    } else {
+        style.display = Display::None;
+        return;
+    }
+    style.display = Display::Flex;
+
+    let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+    let button_material = color_materials.add(Color::rgb(0.25, 0.25, 0.25).into());
+    let row_material = color_materials.add(Color::NONE.into());
+    let text = |label: &str| TextBundle {
+        text: Text::with_section(
+            label,
+            TextStyle {
+                font: font.clone(),
+                font_size: 26.0,
+                color: Color::rgb(0.8, 0.8, 0.8),
+            },
+            TextAlignment::default(),
+        ),
+        ..Default::default()
+    };
+
+    commands.entity(panel).with_children(|parent| {
+        for (label, buttons) in rows {
+            parent
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        align_items: AlignItems::Center,
+                        ..Default::default()
+                    },
+                    material: row_material.clone(),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    parent.spawn_bundle(text(&label));
+                    for (button, label) in buttons {
+                        parent
+                            .spawn_bundle(ButtonBundle {
+                                style: Style {
+                                    margin: Rect::all(Val::Px(3.)),
+                                    padding: Rect::all(Val::Px(4.)),
+                                    ..Default::default()
+                                },
+                                material: button_material.clone(),
+                                ..Default::default()
+                            })
+                            .insert(button)
+                            .with_children(|parent| {
+                                parent.spawn_bundle(text(label));
+                            });
+                    }
+                });
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Saves the game, reads the save back and plays it into a tree again
+    fn round_trip(tree: &GameTree) -> GameTree {
+        let settings = SavedSettings::new(
+            &SoundSettings::default(),
+            &AnimationSettings::default(),
+            &AccessibilitySettings::default(),
+        );
+        let save = SaveFile::new(tree, "Classic", ViewMode::ThreeD, settings);
+        let contents = ron::ser::to_string(&save).unwrap();
+        ron::de::from_str::<SaveFile>(&contents)
+            .unwrap()
+            .game()
+            .unwrap()
+    }
+
+    #[test]
+    fn saves_load_as_they_were() {
+        let games = [
+            "1. e4 e5 (1... c5 2. Nf3 $1 {Open Sicilian} (2. c3 d5) 2... d6) 2. Nf3 $14 Nc6 \
+                {The main line} 3. Bb5 a6 *",
+            "[Variant \"Crazyhouse\"]\n\n1. e4 d5 2. exd5 Qxd5 3. P@e4 (3. Nc3 Qe5+) 3... Qxe4+ 4. Be2 P@f3 *",
+            // The king stays on g1 when castling
+            "[Variant \"Chess960\"]\n[SetUp \"1\"]\n\
+                [FEN \"bqnb2kr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BN1KR w Kk - 2 9\"]\n\n\
+                9. O-O (9. Kf1 e5) 9... O-O 10. f5 *",
+        ];
+        for pgn in games.iter() {
+            let mut tree = parse_pgn(pgn).unwrap().tree;
+            // Left in the middle of the game
+            tree.set_current(tree.line(ROOT)[2]);
+
+            let loaded = round_trip(&tree);
+            assert_eq!(loaded.to_pgn_with_tags(&[]), tree.to_pgn_with_tags(&[]));
+            assert_eq!(
+                position_to_fen(loaded.position(loaded.current())),
+                position_to_fen(tree.position(tree.current()))
+            );
+        }
+    }
+}
@@ -0,0 +1,202 @@
+use crate::{animation::*, events::*};
+use bevy::{asset::FileAssetIo, prelude::*};
+use rodio::{Decoder, OutputStream, OutputStreamHandle, Source};
+use std::{collections::HashMap, io::Cursor};
+
+pub struct SoundSettings {
+    /// Whether to open an audio device at all
+    pub enabled: bool,
+    pub volume: f32,
+    pub muted: bool,
+}
+impl Default for SoundSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            volume: 0.8,
+            muted: false,
+        }
+    }
+}
+
+/// Ordered by how much a sound tells, as only the most telling one of a frame is played
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+enum SoundKind {
+    Move,
+    Castle,
+    Capture,
+    Promotion,
+    Check,
+    GameEnd,
+}
+impl SoundKind {
+    const ALL: [SoundKind; 6] = [
+        SoundKind::Move,
+        SoundKind::Castle,
+        SoundKind::Capture,
+        SoundKind::Promotion,
+        SoundKind::Check,
+        SoundKind::GameEnd,
+    ];
+
+    fn path(self) -> &'static str {
+        match self {
+            SoundKind::Move => "assets/sounds/move.wav",
+            SoundKind::Castle => "assets/sounds/castle.wav",
+            SoundKind::Capture => "assets/sounds/capture.wav",
+            SoundKind::Promotion => "assets/sounds/promotion.wav",
+            SoundKind::Check => "assets/sounds/check.wav",
+            SoundKind::GameEnd => "assets/sounds/game_end.wav",
+        }
+    }
+}
+
+/// The audio device together with the raw sound files. Stays empty when sound is disabled
+/// or there is no output device, in which case every sound is quietly dropped
+#[derive(Default)]
+struct SoundOutput {
+    stream: Option<(OutputStream, OutputStreamHandle)>,
+    sounds: HashMap<SoundKind, Vec<u8>>,
+}
+impl SoundOutput {
+    fn open() -> Self {
+        let stream = match OutputStream::try_default() {
+            Ok(stream) => stream,
+            Err(err) => {
+                info!("No audio output available, playing without sound: {}", err);
+                return Self::default();
+            }
+        };
+
+        let root = FileAssetIo::get_root_path();
+        let sounds = SoundKind::ALL
+            .iter()
+            .filter_map(|&kind| match std::fs::read(root.join(kind.path())) {
+                Ok(bytes) => Some((kind, bytes)),
+                Err(err) => {
+                    warn!("Could not read {}: {}", kind.path(), err);
+                    None
+                }
+            })
+            .collect();
+
+        Self {
+            stream: Some(stream),
+            sounds,
+        }
+    }
+
+    fn play(&self, kind: SoundKind, volume: f32) {
+        let handle = match &self.stream {
+            Some((_, handle)) => handle,
+            None => return,
+        };
+        let bytes = match self.sounds.get(&kind) {
+            Some(bytes) => bytes.clone(),
+            None => return,
+        };
+
+        match Decoder::new(Cursor::new(bytes)) {
+            Ok(source) => {
+                if let Err(err) = handle.play_raw(source.convert_samples().amplify(volume)) {
+                    warn!("Could not play {}: {}", kind.path(), err);
+                }
+            }
+            Err(err) => warn!("Could not decode {}: {}", kind.path(), err),
+        }
+    }
+}
+
+pub struct SoundPlugin;
+impl Plugin for SoundPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<SoundSettings>();
+        let enabled = app
+            .world()
+            .get_resource::<SoundSettings>()
+            .map_or(true, |settings| settings.enabled);
+
+        app.insert_non_send_resource(if enabled {
+            SoundOutput::open()
+        } else {
+            SoundOutput::default()
+        })
+        .add_system(play_sounds.system());
+    }
+}
+
+/// A move's sound, held back until the piece that made the move lands
+struct PendingSound {
+    entity: Entity,
+    kind: SoundKind,
+    /// Frames waited so far. A piece not moving a frame after its move never will, like a
+    /// dropped one, so its sound is played then
+    frames: u32,
+}
+
+fn play_sounds(
+    output: NonSend<SoundOutput>,
+    settings: Res<SoundSettings>,
+    mut pending: Local<Vec<PendingSound>>,
+    mut move_made_events: EventReader<MoveMade>,
+    mut piece_captured_events: EventReader<PieceCaptured>,
+    mut check_given_events: EventReader<CheckGiven>,
+    mut game_ended_events: EventReader<GameEnded>,
+    mut animation_finished_events: EventReader<AnimationFinished>,
+    tween_query: Query<Entity, With<PieceTween>>,
+) {
+    let landed: Vec<Entity> = animation_finished_events
+        .iter()
+        .map(|event| event.entity)
+        .collect();
+    for pending_sound in pending.iter_mut() {
+        pending_sound.frames += 1;
+    }
+    let (ready, waiting): (Vec<PendingSound>, Vec<PendingSound>) =
+        pending.drain(..).partition(|pending_sound| {
+            landed.contains(&pending_sound.entity)
+                || (pending_sound.frames > 0 && tween_query.get(pending_sound.entity).is_err())
+        });
+    *pending = waiting;
+    let mut sound = ready.iter().map(|pending_sound| pending_sound.kind).max();
+
+    // Only the most telling sound of a move is played, so a capture giving check doesn't
+    // also knock and clatter
+    let mut move_sound = None;
+    let mut moved = None;
+    for event in move_made_events.iter() {
+        let kind = if event.promotion.is_some() {
+            SoundKind::Promotion
+        } else if event.castling {
+            SoundKind::Castle
+        } else {
+            SoundKind::Move
+        };
+        move_sound = move_sound.max(Some(kind));
+        moved = Some(event.entity);
+    }
+    if piece_captured_events.iter().next().is_some() {
+        move_sound = move_sound.max(Some(SoundKind::Capture));
+    }
+    if check_given_events.iter().next().is_some() {
+        move_sound = move_sound.max(Some(SoundKind::Check));
+    }
+    if game_ended_events.iter().next().is_some() {
+        move_sound = move_sound.max(Some(SoundKind::GameEnd));
+    }
+    match (move_sound, moved) {
+        (Some(kind), Some(entity)) => pending.push(PendingSound {
+            entity,
+            kind,
+            frames: 0,
+        }),
+        (Some(kind), None) => sound = sound.max(Some(kind)),
+        (None, _) => (),
+    }
+
+    if let Some(kind) = sound {
+        if !settings.muted {
+            output.play(kind, settings.volume);
+        }
+    }
+}
@@ -0,0 +1,213 @@
+use crate::{events::*, notation::*, position::*, variant::*};
+use bevy::{asset::FileAssetIo, prelude::*};
+use shakmaty::{fen::Fen, CastlingMode, Chess};
+use std::{path::PathBuf, sync::Arc};
+
+/// Where Syzygy `.rtbw`/`.rtbz` files are looked for unless `--syzygy` names a directory
+const TABLEBASE_DIR: &str = "assets/syzygy";
+
+pub struct TablebaseSettings {
+    pub path: PathBuf,
+}
+impl Default for TablebaseSettings {
+    fn default() -> Self {
+        Self {
+            path: FileAssetIo::get_root_path().join(TABLEBASE_DIR),
+        }
+    }
+}
+
+/// Win/draw/loss for the side to move. Cursed wins and blessed losses are decided by
+/// the board, but the fifty-move rule turns them into draws
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Wdl {
+    Loss,
+    BlessedLoss,
+    Draw,
+    CursedWin,
+    Win,
+}
+impl From<shakmaty_syzygy::Wdl> for Wdl {
+    fn from(wdl: shakmaty_syzygy::Wdl) -> Self {
+        match wdl {
+            shakmaty_syzygy::Wdl::Loss => Wdl::Loss,
+            shakmaty_syzygy::Wdl::BlessedLoss => Wdl::BlessedLoss,
+            shakmaty_syzygy::Wdl::Draw => Wdl::Draw,
+            shakmaty_syzygy::Wdl::CursedWin => Wdl::CursedWin,
+            shakmaty_syzygy::Wdl::Win => Wdl::Win,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TablebaseProbe {
+    pub wdl: Wdl,
+    /// Plies to the next capture or pawn move with best play, negative when losing
+    pub dtz: i32,
+}
+
+/// Syzygy WDL and DTZ tables for endgames with few pieces. Clones share the open files,
+/// so the engine can probe from its own thread
+#[derive(Clone)]
+pub struct Tablebase {
+    tables: Arc<shakmaty_syzygy::Tablebase<Chess>>,
+}
+impl Tablebase {
+    pub fn load(settings: &TablebaseSettings) -> Result<Self, String> {
+        let mut tables = shakmaty_syzygy::Tablebase::new();
+        let count = tables
+            .add_directory(&settings.path)
+            .map_err(|err| format!("{}: {}", settings.path.display(), err))?;
+        if count == 0 {
+            return Err(format!("{}: no table files", settings.path.display()));
+        }
+        Ok(Self {
+            tables: Arc::new(tables),
+        })
+    }
+
+    /// Most pieces, kings included, that a position may have to be probed
+    pub fn max_pieces(&self) -> usize {
+        self.tables.max_pieces()
+    }
+
+    /// The exact outcome of the position, `None` when it has too many pieces, castling
+    /// rights, is played under other rules than standard chess or its table is missing
+    pub fn probe(&self, position: &Position) -> Option<TablebaseProbe> {
+        let chess = self.to_chess(position)?;
+        let wdl = self.tables.probe_wdl_after_zeroing(&chess).ok()?;
+        let dtz = self.tables.probe_dtz(&chess).ok()?.ignore_rounding();
+        Some(TablebaseProbe {
+            wdl: wdl.into(),
+            dtz: dtz.0,
+        })
+    }
+
+    /// Only the win/draw/loss part of `probe`, which is all a search needs. Assumes the
+    /// fifty-move counter was just reset
+    pub fn probe_wdl(&self, position: &Position) -> Option<Wdl> {
+        let chess = self.to_chess(position)?;
+        self.tables
+            .probe_wdl_after_zeroing(&chess)
+            .ok()
+            .map(Into::into)
+    }
+
+    /// The move that keeps the best outcome while resetting the fifty-move counter
+    /// soonest, for an engine to play without searching
+    pub fn best_move(&self, position: &Position) -> Option<Move> {
+        let chess = self.to_chess(position)?;
+        let (mv, _) = self.tables.best_move(&chess).ok()??;
+        parse_move(position, &mv.to_uci(CastlingMode::Standard).to_string()).ok()
+    }
+
+    fn to_chess(&self, position: &Position) -> Option<Chess> {
+        if position.variant != VariantKind::Standard
+            || !position.is_orthodox()
+            || position.pieces().len() > self.max_pieces()
+        {
+            return None;
+        }
+        position_to_fen(position)
+            .parse::<Fen>()
+            .ok()?
+            .into_position(CastlingMode::Standard)
+            .ok()
+    }
+}
+
+/// How a probe reads, e.g. "White wins (DTZ 13)"
+pub fn probe_text(position: &Position, probe: TablebaseProbe) -> String {
+    let mover = color_name(position.turn);
+    let opponent = color_name(position.turn.opposite());
+    match probe.wdl {
+        Wdl::Win => format!("{} wins (DTZ {})", mover, probe.dtz.abs()),
+        Wdl::Loss => format!("{} wins (DTZ {})", opponent, probe.dtz.abs()),
+        Wdl::CursedWin => format!("Draw, {} wins without the fifty-move rule", mover),
+        Wdl::BlessedLoss => format!("Draw, {} wins without the fifty-move rule", opponent),
+        Wdl::Draw => "Draw".to_string(),
+    }
+}
+
+struct TablebaseText;
+
+pub struct TablebasePlugin;
+impl Plugin for TablebasePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<TablebaseSettings>();
+        let tablebase = app
+            .world()
+            .get_resource::<TablebaseSettings>()
+            .map(Tablebase::load);
+
+        // Without tables everything else keeps working, just without exact endgames
+        match tablebase {
+            Some(Ok(tablebase)) => {
+                info!(
+                    "Probing Syzygy tables with up to {} pieces",
+                    tablebase.max_pieces()
+                );
+                app.insert_resource(tablebase)
+                    .add_startup_system(init_tablebase_text.system())
+                    .add_system(tablebase_text_update.system());
+            }
+            Some(Err(err)) => info!("No tablebase: {}", err),
+            None => (),
+        }
+    }
+}
+
+fn init_tablebase_text(
+    mut commands: Commands,
+    asset_server: ResMut<AssetServer>,
+    mut color_materials: ResMut<Assets<ColorMaterial>>,
+) {
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Px(10.),
+                    top: Val::Px(130.),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            material: color_materials.add(Color::NONE.into()),
+            ..Default::default()
+        })
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(TextBundle {
+                    text: Text::with_section(
+                        String::new(),
+                        TextStyle {
+                            font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                            font_size: 30.0,
+                            color: Color::rgb(0.8, 0.8, 0.8),
+                        },
+                        TextAlignment::default(),
+                    ),
+                    ..Default::default()
+                })
+                .insert(TablebaseText);
+        });
+}
+
+fn tablebase_text_update(
+    position: Res<Position>,
+    tablebase: Res<Tablebase>,
+    mut query: Query<&mut Text, With<TablebaseText>>,
+) {
+    if !position.is_changed() {
+        return;
+    }
+
+    let value = tablebase
+        .probe(&position)
+        .map(|probe| format!("Tablebase: {}", probe_text(&position, probe)))
+        .unwrap_or_default();
+    for mut text in query.iter_mut() {
+        text.sections[0].value = value.clone();
+    }
+}
@@ -0,0 +1,268 @@
+use crate::{board::*, database::*, keyboard::*, pieces::*};
+use bevy::{asset::FileAssetIo, prelude::*};
+use serde::Deserialize;
+
+/// Extra themes are read from `.ron` files in this directory, next to the built-in presets
+const THEMES_DIR: &str = "assets/themes";
+
+#[derive(Clone, Deserialize)]
+pub struct PieceStyle {
+    pub color: [f32; 3],
+    pub metallic: f32,
+    pub roughness: f32,
+}
+impl PieceStyle {
+    fn apply(&self, material: &mut StandardMaterial) {
+        material.base_color = rgb(self.color);
+        material.metallic = self.metallic;
+        material.roughness = self.roughness;
+    }
+
+    pub fn material(&self) -> StandardMaterial {
+        let mut material = StandardMaterial::default();
+        self.apply(&mut material);
+        material
+    }
+}
+
+#[derive(Clone, Deserialize)]
+pub struct Theme {
+    pub name: String,
+    pub light_square: [f32; 3],
+    pub dark_square: [f32; 3],
+    pub hovered_square: [f32; 3],
+    pub selected_square: [f32; 3],
+    pub white_pieces: PieceStyle,
+    pub black_pieces: PieceStyle,
+    pub background: [f32; 3],
+}
+impl Theme {
+    fn presets() -> Vec<Theme> {
+        vec![
+            Theme {
+                name: "Default".to_string(),
+                light_square: [1., 0.9, 0.9],
+                dark_square: [0., 0.1, 0.1],
+                hovered_square: [0.8, 0.3, 0.3],
+                selected_square: [0.9, 0.1, 0.1],
+                white_pieces: PieceStyle {
+                    color: [1., 0.8, 0.8],
+                    metallic: 0.01,
+                    roughness: 0.089,
+                },
+                black_pieces: PieceStyle {
+                    color: [0., 0.2, 0.2],
+                    metallic: 0.01,
+                    roughness: 0.089,
+                },
+                background: [0.4, 0.4, 0.4],
+            },
+            Theme {
+                name: "Classic wood".to_string(),
+                light_square: [0.87, 0.72, 0.53],
+                dark_square: [0.55, 0.35, 0.17],
+                hovered_square: [0.8, 0.6, 0.3],
+                selected_square: [0.9, 0.45, 0.2],
+                white_pieces: PieceStyle {
+                    color: [0.95, 0.88, 0.75],
+                    metallic: 0.,
+                    roughness: 0.6,
+                },
+                black_pieces: PieceStyle {
+                    color: [0.25, 0.14, 0.08],
+                    metallic: 0.,
+                    roughness: 0.5,
+                },
+                background: [0.2, 0.15, 0.1],
+            },
+            Theme {
+                name: "Tournament green".to_string(),
+                light_square: [0.93, 0.93, 0.82],
+                dark_square: [0.46, 0.59, 0.34],
+                hovered_square: [0.73, 0.79, 0.43],
+                selected_square: [0.96, 0.85, 0.3],
+                white_pieces: PieceStyle {
+                    color: [1., 1., 0.95],
+                    metallic: 0.,
+                    roughness: 0.4,
+                },
+                black_pieces: PieceStyle {
+                    color: [0.1, 0.1, 0.1],
+                    metallic: 0.,
+                    roughness: 0.4,
+                },
+                background: [0.15, 0.2, 0.15],
+            },
+            Theme {
+                name: "High contrast".to_string(),
+                light_square: [0.85, 0.85, 0.85],
+                dark_square: [0.1, 0.1, 0.45],
+                hovered_square: [0., 0.7, 1.],
+                selected_square: [1., 0.8, 0.],
+                white_pieces: PieceStyle {
+                    color: [1., 1., 1.],
+                    metallic: 0.,
+                    roughness: 1.,
+                },
+                black_pieces: PieceStyle {
+                    color: [0., 0., 0.],
+                    metallic: 0.,
+                    roughness: 1.,
+                },
+                background: [0., 0., 0.],
+            },
+            Theme {
+                name: "Marble".to_string(),
+                light_square: [0.92, 0.92, 0.9],
+                dark_square: [0.45, 0.47, 0.5],
+                hovered_square: [0.7, 0.75, 0.85],
+                selected_square: [0.55, 0.7, 0.9],
+                white_pieces: PieceStyle {
+                    color: [0.97, 0.96, 0.93],
+                    metallic: 0.1,
+                    roughness: 0.2,
+                },
+                black_pieces: PieceStyle {
+                    color: [0.1, 0.1, 0.12],
+                    metallic: 0.1,
+                    roughness: 0.2,
+                },
+                background: [0.3, 0.3, 0.32],
+            },
+        ]
+    }
+
+    pub fn square_color(&self, is_white: bool) -> Color {
+        rgb(if is_white {
+            self.light_square
+        } else {
+            self.dark_square
+        })
+    }
+
+    /// Color of a square, taking the mouse and keyboard cursors and selection into account
+    pub fn square_highlight(
+        &self,
+        square: &Square,
+        board_cursor: &BoardCursor,
+        keyboard_cursor: &KeyboardCursor,
+        selected_square: &SelectedSquare,
+    ) -> Color {
+        let coordinates = Some((square.x, square.y));
+        if coordinates == board_cursor.square || coordinates == keyboard_cursor.square {
+            rgb(self.hovered_square)
+        } else if coordinates == selected_square.square {
+            rgb(self.selected_square)
+        } else {
+            self.square_color(square.is_white())
+        }
+    }
+
+    pub fn piece_style(&self, color: PieceColor) -> &PieceStyle {
+        match color {
+            PieceColor::White => &self.white_pieces,
+            PieceColor::Black => &self.black_pieces,
+        }
+    }
+}
+
+pub fn rgb(color: [f32; 3]) -> Color {
+    Color::rgb(color[0], color[1], color[2])
+}
+
+/// Every theme that can be switched to, the built-in presets first
+pub struct Themes {
+    themes: Vec<Theme>,
+    current: usize,
+}
+impl Themes {
+    fn load() -> Self {
+        let mut themes = Theme::presets();
+
+        let dir = FileAssetIo::get_root_path().join(THEMES_DIR);
+        if let Ok(entries) = std::fs::read_dir(&dir) {
+            let mut paths: Vec<_> = entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| {
+                    path.extension()
+                        .map_or(false, |extension| extension == "ron")
+                })
+                .collect();
+            paths.sort();
+
+            for path in paths {
+                let theme = std::fs::read_to_string(&path)
+                    .map_err(|err| err.to_string())
+                    .and_then(|contents| {
+                        ron::de::from_str::<Theme>(&contents).map_err(|err| err.to_string())
+                    });
+                match theme {
+                    Ok(theme) => themes.push(theme),
+                    Err(err) => warn!("Could not load theme {}: {}", path.display(), err),
+                }
+            }
+        }
+
+        Self { themes, current: 0 }
+    }
+
+    pub fn current(&self) -> &Theme {
+        &self.themes[self.current]
+    }
+
+    pub fn next(&mut self) {
+        self.current = (self.current + 1) % self.themes.len();
+    }
+
+    /// Switches to the theme called `name`, if there is one
+    pub fn select(&mut self, name: &str) -> bool {
+        match self.themes.iter().position(|theme| theme.name == name) {
+            Some(index) => {
+                self.current = index;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+pub struct ThemePlugin;
+impl Plugin for ThemePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.insert_resource(Themes::load())
+            .add_system(switch_theme.system())
+            .add_system(apply_theme.system());
+    }
+}
+
+fn switch_theme(
+    keyboard_inputs: Res<Input<KeyCode>>,
+    browser: Res<DatabaseBrowser>,
+    mut themes: ResMut<Themes>,
+) {
+    if keyboard_inputs.just_pressed(KeyCode::T) && !browser.open {
+        themes.next();
+    }
+}
+
+/// Square colors are picked up by `color_squares`, everything else is updated here
+fn apply_theme(
+    themes: Res<Themes>,
+    piece_materials: Option<Res<PieceMaterials>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut clear_color: ResMut<ClearColor>,
+) {
+    if !themes.is_changed() {
+        return;
+    }
+    let theme = themes.current();
+
+    if let Some(piece_materials) = piece_materials {
+        for &color in [PieceColor::White, PieceColor::Black].iter() {
+            if let Some(material) = materials.get_mut(piece_materials.get(color)) {
+                theme.piece_style(color).apply(material);
+            }
+        }
+    }
+    clear_color.0 = rgb(theme.background);
+}
@@ -1,13 +1,43 @@
-use crate::{board::*, pieces::*};
+use crate::{accessibility::*, captured::*, eco::*, events::*, keyboard::*, sound::*, theme::*};
 use bevy::prelude::*;
 
 struct NextMoveText;
 
+struct OpeningText;
+
+struct MaterialText;
+
+struct ThemeText;
+
+struct VolumeText;
+
+struct MoveInputText;
+
+struct AnnouncementText;
+
+enum SoundButton {
+    VolumeDown,
+    VolumeUp,
+    Mute,
+}
+
 pub struct UIPlugin;
 impl Plugin for UIPlugin {
     fn build(&self, app: &mut AppBuilder) {
         app.add_startup_system(init_next_move_text.system())
-            .add_system(next_move_text_update.system());
+            .add_startup_system(init_material_text.system())
+            .add_startup_system(init_sound_controls.system())
+            .add_startup_system(init_theme_text.system())
+            .add_startup_system(init_move_input_text.system())
+            .add_startup_system(init_announcement_text.system())
+            .add_system(next_move_text_update.system())
+            .add_system(opening_text_update.system())
+            .add_system(material_text_update.system())
+            .add_system(sound_buttons.system())
+            .add_system(volume_text_update.system())
+            .add_system(theme_text_update.system())
+            .add_system(move_input_text_update.system())
+            .add_system(announcement_text_update.system());
     }
 }
 
@@ -27,6 +57,7 @@ fn init_next_move_text(
                     top: Val::Px(10.),
                     ..Default::default()
                 },
+                flex_direction: FlexDirection::ColumnReverse,
                 ..Default::default()
             },
             material: color_materials.add(Color::NONE.into()),
@@ -47,17 +78,372 @@ fn init_next_move_text(
                     ..Default::default()
                 })
                 .insert(NextMoveText);
+            parent
+                .spawn_bundle(TextBundle {
+                    text: Text::with_section(
+                        String::new(),
+                        TextStyle {
+                            font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                            font_size: 24.0,
+                            color: Color::rgb(0.8, 0.8, 0.8),
+                        },
+                        TextAlignment::default(),
+                    ),
+                    ..Default::default()
+                })
+                .insert(OpeningText);
+        });
+}
+
+fn next_move_text_update(
+    mut turn_changed_events: EventReader<TurnChanged>,
+    mut check_given_events: EventReader<CheckGiven>,
+    mut game_ended_events: EventReader<GameEnded>,
+    mut query: Query<(&mut Text, &NextMoveText)>,
+) {
+    let mut value = None;
+    for event in turn_changed_events.iter() {
+        value = Some(format!("Next move: {}", color_name(event.color)));
+    }
+    for event in check_given_events.iter() {
+        value = Some(format!("Next move: {} (check)", color_name(event.color)));
+    }
+    for event in game_ended_events.iter() {
+        value = Some(game_result_text(event));
+    }
+
+    if let Some(value) = value {
+        for (mut text, _tag) in query.iter_mut() {
+            text.sections[0].value = value.clone();
+        }
+    }
+}
+
+fn opening_text_update(
+    opening: Res<CurrentOpening>,
+    mut query: Query<&mut Text, With<OpeningText>>,
+) {
+    if !opening.is_changed() {
+        return;
+    }
+
+    for mut text in query.iter_mut() {
+        text.sections[0].value = match &opening.opening {
+            Some(opening) => format!("{} {}", opening.eco, opening.name),
+            None => String::new(),
+        };
+    }
+}
+
+fn init_material_text(
+    mut commands: Commands,
+    asset_server: ResMut<AssetServer>,
+    mut color_materials: ResMut<Assets<ColorMaterial>>,
+) {
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Px(10.),
+                    top: Val::Px(90.),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            material: color_materials.add(Color::NONE.into()),
+            ..Default::default()
+        })
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(TextBundle {
+                    text: Text::with_section(
+                        "Material: even".to_string(),
+                        TextStyle {
+                            font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                            font_size: 30.0,
+                            color: Color::rgb(0.8, 0.8, 0.8),
+                        },
+                        TextAlignment::default(),
+                    ),
+                    ..Default::default()
+                })
+                .insert(MaterialText);
         });
 }
 
-fn next_move_text_update(turn: Res<PlayerTurn>, mut query: Query<(&mut Text, &NextMoveText)>) {
+fn material_text_update(
+    captured: Res<CapturedPieces>,
+    mut query: Query<(&mut Text, &MaterialText)>,
+) {
+    if !captured.is_changed() {
+        return;
+    }
+
     for (mut text, _tag) in query.iter_mut() {
-        text.sections[0].value = format!(
-            "Next move: {}",
-            match turn.color {
-                PieceColor::White => "White",
-                PieceColor::Black => "Black",
+        let balance = captured.balance();
+        text.sections[0].value = match balance {
+            0 => "Material: even".to_string(),
+            balance if balance > 0 => format!("Material: White +{}", balance),
+            balance => format!("Material: Black +{}", -balance),
+        };
+    }
+}
+
+fn init_sound_controls(
+    mut commands: Commands,
+    asset_server: ResMut<AssetServer>,
+    mut color_materials: ResMut<Assets<ColorMaterial>>,
+) {
+    let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+    let button_material = color_materials.add(Color::rgb(0.25, 0.25, 0.25).into());
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    right: Val::Px(10.),
+                    top: Val::Px(10.),
+                    ..Default::default()
+                },
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            material: color_materials.add(Color::NONE.into()),
+            ..Default::default()
+        })
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(TextBundle {
+                    text: Text::with_section(
+                        "Volume: 80%".to_string(),
+                        TextStyle {
+                            font: font.clone(),
+                            font_size: 30.0,
+                            color: Color::rgb(0.8, 0.8, 0.8),
+                        },
+                        TextAlignment::default(),
+                    ),
+                    ..Default::default()
+                })
+                .insert(VolumeText);
+
+            for (button, label) in vec![
+                (SoundButton::VolumeDown, "-"),
+                (SoundButton::VolumeUp, "+"),
+                (SoundButton::Mute, "Mute"),
+            ] {
+                parent
+                    .spawn_bundle(ButtonBundle {
+                        style: Style {
+                            margin: Rect::all(Val::Px(5.)),
+                            padding: Rect::all(Val::Px(5.)),
+                            ..Default::default()
+                        },
+                        material: button_material.clone(),
+                        ..Default::default()
+                    })
+                    .insert(button)
+                    .with_children(|parent| {
+                        parent.spawn_bundle(TextBundle {
+                            text: Text::with_section(
+                                label.to_string(),
+                                TextStyle {
+                                    font: font.clone(),
+                                    font_size: 30.0,
+                                    color: Color::rgb(0.8, 0.8, 0.8),
+                                },
+                                TextAlignment::default(),
+                            ),
+                            ..Default::default()
+                        });
+                    });
             }
-        );
+        });
+}
+
+fn sound_buttons(
+    mut settings: ResMut<SoundSettings>,
+    query: Query<(&Interaction, &SoundButton), Changed<Interaction>>,
+) {
+    for (interaction, button) in query.iter() {
+        if *interaction != Interaction::Clicked {
+            continue;
+        }
+
+        match button {
+            SoundButton::VolumeDown => settings.volume = (settings.volume - 0.1).max(0.),
+            SoundButton::VolumeUp => settings.volume = (settings.volume + 0.1).min(1.),
+            SoundButton::Mute => settings.muted = !settings.muted,
+        }
+    }
+}
+
+fn volume_text_update(settings: Res<SoundSettings>, mut query: Query<&mut Text, With<VolumeText>>) {
+    if !settings.is_changed() {
+        return;
+    }
+
+    for mut text in query.iter_mut() {
+        text.sections[0].value = if settings.muted {
+            "Sound: muted".to_string()
+        } else {
+            format!("Volume: {:.0}%", settings.volume * 100.)
+        };
+    }
+}
+
+fn init_theme_text(
+    mut commands: Commands,
+    asset_server: ResMut<AssetServer>,
+    mut color_materials: ResMut<Assets<ColorMaterial>>,
+) {
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Px(10.),
+                    bottom: Val::Px(10.),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            material: color_materials.add(Color::NONE.into()),
+            ..Default::default()
+        })
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(TextBundle {
+                    text: Text::with_section(
+                        String::new(),
+                        TextStyle {
+                            font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                            font_size: 30.0,
+                            color: Color::rgb(0.8, 0.8, 0.8),
+                        },
+                        TextAlignment::default(),
+                    ),
+                    ..Default::default()
+                })
+                .insert(ThemeText);
+        });
+}
+
+fn theme_text_update(themes: Res<Themes>, mut query: Query<&mut Text, With<ThemeText>>) {
+    if !themes.is_changed() {
+        return;
+    }
+
+    for mut text in query.iter_mut() {
+        text.sections[0].value = format!("Theme: {} (T to switch)", themes.current().name);
+    }
+}
+
+fn init_move_input_text(
+    mut commands: Commands,
+    asset_server: ResMut<AssetServer>,
+    mut color_materials: ResMut<Assets<ColorMaterial>>,
+) {
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Px(10.),
+                    bottom: Val::Px(50.),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            material: color_materials.add(Color::NONE.into()),
+            ..Default::default()
+        })
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(TextBundle {
+                    text: Text::with_section(
+                        String::new(),
+                        TextStyle {
+                            font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                            font_size: 30.0,
+                            color: Color::rgb(0.8, 0.8, 0.8),
+                        },
+                        TextAlignment::default(),
+                    ),
+                    ..Default::default()
+                })
+                .insert(MoveInputText);
+        });
+}
+
+fn move_input_text_update(
+    move_input: Res<MoveInput>,
+    mut query: Query<&mut Text, With<MoveInputText>>,
+) {
+    if !move_input.is_changed() {
+        return;
+    }
+
+    let value = match (&move_input.error, move_input.text.is_empty()) {
+        (Some(err), _) => format!("Move: {}_ ({})", move_input.text, err),
+        (None, true) => {
+            "Type a move (Nf3 or g1f3) or ?e4, or use the arrow keys and Enter".to_string()
+        }
+        (None, false) => format!("Move: {}_", move_input.text),
+    };
+    for mut text in query.iter_mut() {
+        text.sections[0].value = value.clone();
+    }
+}
+
+/// Shows the latest announcement, so what is spoken can also be read
+fn init_announcement_text(
+    mut commands: Commands,
+    asset_server: ResMut<AssetServer>,
+    mut color_materials: ResMut<Assets<ColorMaterial>>,
+) {
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Px(10.),
+                    bottom: Val::Px(90.),
+                    ..Default::default()
+                },
+                max_size: Size::new(Val::Px(1200.), Val::Undefined),
+                ..Default::default()
+            },
+            material: color_materials.add(Color::NONE.into()),
+            ..Default::default()
+        })
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(TextBundle {
+                    text: Text::with_section(
+                        String::new(),
+                        TextStyle {
+                            font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                            font_size: 30.0,
+                            color: Color::rgb(0.8, 0.8, 0.8),
+                        },
+                        TextAlignment::default(),
+                    ),
+                    ..Default::default()
+                })
+                .insert(AnnouncementText);
+        });
+}
+
+fn announcement_text_update(
+    mut announcements: EventReader<Announcement>,
+    mut query: Query<&mut Text, With<AnnouncementText>>,
+) {
+    if let Some(announcement) = announcements.iter().last() {
+        for mut text in query.iter_mut() {
+            text.sections[0].value = announcement.text.clone();
+        }
     }
 }
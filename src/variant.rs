@@ -0,0 +1,554 @@
+use crate::{events::color_name, notation::*, pieces::*, position::*};
+
+/// What the engine counts a king one, two or three steps from the centre as worth in King
+/// of the Hill, in centipawns
+const HILL_BONUS: [i32; 4] = [0, 150, 50, 10];
+
+/// What the engine counts having given one or two checks as worth in Three-check
+const CHECK_BONUS: [i32; 3] = [0, 150, 450];
+
+/// Rules that differ from standard chess. `Position` dispatches through the variant it is
+/// played under for the setup, which moves are allowed and when the game is over; every
+/// method defaults to the standard rules
+pub trait Variant: Sync {
+    /// Name as used in the PGN `Variant` tag
+    fn name(&self) -> &'static str;
+
+    /// Where a game of this variant starts
+    fn start(&self) -> Position {
+        Position::start()
+    }
+
+    /// Whether the king must be kept out of check. Without that it is an ordinary piece
+    fn royal_king(&self) -> bool {
+        true
+    }
+
+    /// What a pawn may promote to
+    fn promotions(&self) -> &'static [PieceType] {
+        &[
+            PieceType::Queen,
+            PieceType::Rook,
+            PieceType::Bishop,
+            PieceType::Knight,
+        ]
+    }
+
+    /// Whether a pawn of `color` standing on `rank` may move two squares
+    fn may_double_step(&self, position: &Position, rank: u8, color: PieceColor) -> bool {
+        rank == position.pawn_rank(color)
+    }
+
+    /// Narrows down the legal moves, as antichess does by making captures compulsory
+    fn filter_moves(&self, _position: &Position, moves: Vec<Move>) -> Vec<Move> {
+        moves
+    }
+
+    /// Whether captured pieces go into the capturer's pocket, to be dropped back later
+    fn has_drops(&self) -> bool {
+        false
+    }
+
+    /// Keeps count of anything the variant needs after `mover` played a move
+    fn after_move(&self, _position: &mut Position, _mover: PieceColor) {}
+
+    /// A result reached by the variant's own rules, looked at before checkmate and the
+    /// usual draws. `legal_moves` are those of the side to move
+    fn outcome(&self, _position: &Position, _legal_moves: &[Move]) -> Option<GameStatus> {
+        None
+    }
+
+    /// The engine's static evaluation in centipawns, from the side to move's point of view,
+    /// given the material and piece placement as scored in chess
+    fn evaluate(&self, _position: &Position, material: i32, placement: i32) -> i32 {
+        material + placement
+    }
+
+    /// Whether neither side can win any more
+    fn has_insufficient_material(&self, position: &Position) -> bool {
+        only_kings_and_minor_piece(position)
+    }
+
+    /// Whether a position set up by hand has the pieces the variant needs, and only where
+    /// they may stand: a king a side and no pawns on the back ranks, unless the variant
+    /// says otherwise
+    fn check_setup(&self, position: &Position) -> Result<(), String> {
+        for &color in [PieceColor::White, PieceColor::Black].iter() {
+            one_king(position, color)?;
+            pawns_within(position, color, 1, position.ranks() - 2)?;
+        }
+        Ok(())
+    }
+}
+
+/// Which rules a `Position` is played under
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum VariantKind {
+    Standard,
+    KingOfTheHill,
+    ThreeCheck,
+    Antichess,
+    Horde,
+    Crazyhouse,
+    Capablanca,
+}
+impl Default for VariantKind {
+    fn default() -> Self {
+        VariantKind::Standard
+    }
+}
+
+impl VariantKind {
+    pub const ALL: [VariantKind; 7] = [
+        VariantKind::Standard,
+        VariantKind::KingOfTheHill,
+        VariantKind::ThreeCheck,
+        VariantKind::Antichess,
+        VariantKind::Horde,
+        VariantKind::Crazyhouse,
+        VariantKind::Capablanca,
+    ];
+
+    pub fn rules(self) -> &'static dyn Variant {
+        match self {
+            VariantKind::Standard => &Standard,
+            VariantKind::KingOfTheHill => &KingOfTheHill,
+            VariantKind::ThreeCheck => &ThreeCheck,
+            VariantKind::Antichess => &Antichess,
+            VariantKind::Horde => &Horde,
+            VariantKind::Crazyhouse => &Crazyhouse,
+            VariantKind::Capablanca => &Capablanca,
+        }
+    }
+
+    /// Looks a variant up by its PGN name, ignoring case, spaces and dashes, so that
+    /// `king-of-the-hill` and `threecheck` are understood too
+    pub fn from_name(name: &str) -> Option<VariantKind> {
+        let simplify = |name: &str| -> String {
+            name.chars()
+                .filter(|c| c.is_ascii_alphanumeric())
+                .map(|c| c.to_ascii_lowercase())
+                .collect()
+        };
+        let name = simplify(name);
+        VariantKind::ALL
+            .iter()
+            .copied()
+            .find(|variant| simplify(variant.rules().name()) == name)
+    }
+}
+
+/// Standard chess
+pub struct Standard;
+impl Variant for Standard {
+    fn name(&self) -> &'static str {
+        "Standard"
+    }
+}
+
+/// Bringing the king to one of the four centre squares wins
+pub struct KingOfTheHill;
+impl Variant for KingOfTheHill {
+    fn name(&self) -> &'static str {
+        "King of the Hill"
+    }
+
+    fn outcome(&self, position: &Position, _legal_moves: &[Move]) -> Option<GameStatus> {
+        let mover = position.turn.opposite();
+        match position.king_square(mover) {
+            Some((x, y)) if (3..=4).contains(&x) && (3..=4).contains(&y) => {
+                Some(GameStatus::VariantWin {
+                    winner: mover,
+                    reason: "reaching the centre",
+                })
+            }
+            _ => None,
+        }
+    }
+
+    fn evaluate(&self, position: &Position, material: i32, placement: i32) -> i32 {
+        let hill = |color: PieceColor| {
+            position.king_square(color).map_or(0, |(x, y)| {
+                let distance = |line: u8| 3u8.saturating_sub(line).max(line.saturating_sub(4));
+                HILL_BONUS[distance(x).max(distance(y)).min(3) as usize]
+            })
+        };
+        material + placement + hill(position.turn) - hill(position.turn.opposite())
+    }
+}
+
+/// Giving check three times wins
+pub struct ThreeCheck;
+impl Variant for ThreeCheck {
+    fn name(&self) -> &'static str {
+        "Three-check"
+    }
+
+    fn after_move(&self, position: &mut Position, mover: PieceColor) {
+        if position.is_in_check(mover.opposite()) {
+            position.checks_given[color_index(mover)] += 1;
+        }
+    }
+
+    fn outcome(&self, position: &Position, _legal_moves: &[Move]) -> Option<GameStatus> {
+        let mover = position.turn.opposite();
+        if position.checks_given[color_index(mover)] >= 3 {
+            Some(GameStatus::VariantWin {
+                winner: mover,
+                reason: "giving three checks",
+            })
+        } else {
+            None
+        }
+    }
+
+    fn evaluate(&self, position: &Position, material: i32, placement: i32) -> i32 {
+        let checks = |color: PieceColor| {
+            CHECK_BONUS[position.checks_given[color_index(color)].min(2) as usize]
+        };
+        material + placement + checks(position.turn) - checks(position.turn.opposite())
+    }
+}
+
+/// Whoever loses all their pieces, or has no move left, wins. Captures are compulsory,
+/// the king is an ordinary piece and pawns may promote to kings
+pub struct Antichess;
+impl Variant for Antichess {
+    fn name(&self) -> &'static str {
+        "Antichess"
+    }
+
+    fn start(&self) -> Position {
+        let mut position = Position::start();
+        position.castling = [[None; 2]; 2];
+        position
+    }
+
+    fn royal_king(&self) -> bool {
+        false
+    }
+
+    fn promotions(&self) -> &'static [PieceType] {
+        &[
+            PieceType::Queen,
+            PieceType::Rook,
+            PieceType::Bishop,
+            PieceType::Knight,
+            PieceType::King,
+        ]
+    }
+
+    fn filter_moves(&self, position: &Position, moves: Vec<Move>) -> Vec<Move> {
+        let captures: Vec<Move> = moves
+            .iter()
+            .copied()
+            .filter(|&mv| position.captured_square(mv).is_some())
+            .collect();
+        if captures.is_empty() {
+            moves
+        } else {
+            captures
+        }
+    }
+
+    fn outcome(&self, position: &Position, legal_moves: &[Move]) -> Option<GameStatus> {
+        if legal_moves.is_empty() {
+            Some(GameStatus::VariantWin {
+                winner: position.turn,
+                reason: "running out of moves",
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Losing material is the aim, and where the pieces stand in chess says nothing here
+    fn evaluate(&self, _position: &Position, material: i32, _placement: i32) -> i32 {
+        -material
+    }
+
+    fn has_insufficient_material(&self, _position: &Position) -> bool {
+        false
+    }
+
+    fn check_setup(&self, position: &Position) -> Result<(), String> {
+        for &color in [PieceColor::White, PieceColor::Black].iter() {
+            pawns_within(position, color, 1, position.ranks() - 2)?;
+        }
+        Ok(())
+    }
+}
+
+/// White has thirty-six pawns and no king, and wins by checkmating black. Black wins by
+/// capturing every white piece. White's pawns may also move two squares from the first
+/// rank
+pub struct Horde;
+impl Variant for Horde {
+    fn name(&self) -> &'static str {
+        "Horde"
+    }
+
+    fn start(&self) -> Position {
+        parse_fen("rnbqkbnr/pppppppp/8/1PP2PP1/PPPPPPPP/PPPPPPPP/PPPPPPPP/PPPPPPPP w kq - 0 1")
+            .unwrap_or_else(|_| Position::start())
+    }
+
+    fn may_double_step(&self, position: &Position, rank: u8, color: PieceColor) -> bool {
+        rank == position.pawn_rank(color)
+            || (color == PieceColor::White && rank == position.back_rank(color))
+    }
+
+    fn outcome(&self, position: &Position, _legal_moves: &[Move]) -> Option<GameStatus> {
+        let white_left = position
+            .pieces()
+            .iter()
+            .any(|piece| piece.color == PieceColor::White);
+        if white_left {
+            None
+        } else {
+            Some(GameStatus::VariantWin {
+                winner: PieceColor::Black,
+                reason: "capturing the horde",
+            })
+        }
+    }
+
+    fn has_insufficient_material(&self, _position: &Position) -> bool {
+        false
+    }
+
+    /// The horde has no king, and its pawns may start from the first rank
+    fn check_setup(&self, position: &Position) -> Result<(), String> {
+        let highest = position.ranks() - 2;
+        one_king(position, PieceColor::Black)?;
+        pawns_within(position, PieceColor::Black, 1, highest)?;
+        pawns_within(position, PieceColor::White, 0, highest)?;
+        if position.king_square(PieceColor::White).is_some() {
+            return Err("The horde has no king".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Captured pieces change sides and may be dropped on any empty square instead of moving
+pub struct Crazyhouse;
+impl Variant for Crazyhouse {
+    fn name(&self) -> &'static str {
+        "Crazyhouse"
+    }
+
+    fn has_drops(&self) -> bool {
+        true
+    }
+
+    fn has_insufficient_material(&self, _position: &Position) -> bool {
+        false
+    }
+}
+
+/// Played on a board ten files wide, with an archbishop (bishop and knight) and a
+/// chancellor (rook and knight) added to each side. Pawns may promote to either
+pub struct Capablanca;
+impl Variant for Capablanca {
+    fn name(&self) -> &'static str {
+        "Capablanca"
+    }
+
+    fn start(&self) -> Position {
+        Position::with_back_rank(&[
+            PieceType::Rook,
+            PieceType::Knight,
+            PieceType::Archbishop,
+            PieceType::Bishop,
+            PieceType::Queen,
+            PieceType::King,
+            PieceType::Bishop,
+            PieceType::Chancellor,
+            PieceType::Knight,
+            PieceType::Rook,
+        ])
+    }
+
+    fn promotions(&self) -> &'static [PieceType] {
+        &[
+            PieceType::Queen,
+            PieceType::Chancellor,
+            PieceType::Archbishop,
+            PieceType::Rook,
+            PieceType::Bishop,
+            PieceType::Knight,
+        ]
+    }
+}
+
+/// Only kings left, plus at most a single bishop or knight
+fn only_kings_and_minor_piece(position: &Position) -> bool {
+    let others: Vec<PieceType> = position
+        .pieces()
+        .into_iter()
+        .map(|piece| piece.piece_type)
+        .filter(|&piece_type| piece_type != PieceType::King)
+        .collect();
+    matches!(
+        others.as_slice(),
+        [] | [PieceType::Bishop] | [PieceType::Knight]
+    )
+}
+
+/// Exactly one king of `color` on the board
+fn one_king(position: &Position, color: PieceColor) -> Result<(), String> {
+    let kings = position
+        .pieces()
+        .iter()
+        .filter(|piece| piece.color == color && piece.piece_type == PieceType::King)
+        .count();
+    match kings {
+        1 => Ok(()),
+        0 => Err(format!("{} has no king", color_name(color))),
+        kings => Err(format!("{} has {} kings", color_name(color), kings)),
+    }
+}
+
+/// Every pawn of `color` between the ranks `lowest` and `highest`, as counted from
+/// white's side
+fn pawns_within(
+    position: &Position,
+    color: PieceColor,
+    lowest: u8,
+    highest: u8,
+) -> Result<(), String> {
+    let misplaced = position.pieces().into_iter().find(|piece| {
+        piece.color == color
+            && piece.piece_type == PieceType::Pawn
+            && !(lowest..=highest).contains(&piece.x)
+    });
+    match misplaced {
+        Some(pawn) => Err(format!(
+            "{} pawn on {}, where pawns can't stand",
+            color_name(color),
+            square_name((pawn.x, pawn.y))
+        )),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::position::tests::assert_perft;
+
+    fn position(variant: VariantKind, fen: &str) -> Position {
+        let mut position = parse_fen(fen).unwrap();
+        position.variant = variant;
+        position
+    }
+
+    /// The variants score what they play for on top of, or instead of, chess material
+    #[test]
+    fn evaluation() {
+        let kings = "k7/8/8/8/8/2K5/8/8 w - - 0 1";
+        let antichess = position(VariantKind::Antichess, kings);
+        assert_eq!(antichess.rules().evaluate(&antichess, 300, 20), -300);
+
+        // A step from the centre against three steps away
+        let hill = position(VariantKind::KingOfTheHill, kings);
+        assert_eq!(hill.rules().evaluate(&hill, 0, 0), 140);
+
+        let mut three_check = position(VariantKind::ThreeCheck, kings);
+        three_check.checks_given = [2, 1];
+        assert_eq!(three_check.rules().evaluate(&three_check, 0, 0), 300);
+        three_check.turn = PieceColor::Black;
+        assert_eq!(three_check.rules().evaluate(&three_check, 0, 0), -300);
+
+        let standard = position(VariantKind::Standard, kings);
+        assert_eq!(standard.rules().evaluate(&standard, 300, 20), 320);
+    }
+
+    #[test]
+    fn perft_three_check() {
+        // One check left to give for either side, so checking ends the game
+        let fens = [
+            (
+                "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+                [48, 2039, 97848],
+            ),
+            ("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1", [26, 562, 13410]),
+        ];
+        for &(fen, counts) in fens.iter() {
+            let mut position = position(VariantKind::ThreeCheck, fen);
+            position.checks_given = [2, 2];
+            assert_perft(&position, &counts);
+        }
+    }
+
+    #[test]
+    fn perft_antichess() {
+        let start = Position::variant_start(VariantKind::Antichess);
+        assert_perft(&start, &[20, 400, 8067]);
+        // Pawns that race to promote, to kings as well
+        assert_perft(
+            &position(VariantKind::Antichess, "8/1p6/8/8/8/8/P7/8 w - - 0 1"),
+            &[2, 4, 4, 3, 1, 0],
+        );
+        assert_perft(
+            &position(VariantKind::Antichess, "8/2p5/8/8/8/8/P7/8 w - - 0 1"),
+            &[2, 4, 4, 4, 4, 4, 4, 4, 12, 36, 312],
+        );
+    }
+
+    #[test]
+    fn perft_horde() {
+        let start = Position::variant_start(VariantKind::Horde);
+        assert_perft(&start, &[8, 128, 1274, 23310]);
+        assert_perft(
+            &position(
+                VariantKind::Horde,
+                "4k3/pp4q1/3P2p1/8/P3PP2/PPP2r2/PPP5/PPPP4 b - - 0 1",
+            ),
+            &[30, 241, 6633],
+        );
+        // Double steps from the first rank can't be taken en passant
+        assert_perft(
+            &position(
+                VariantKind::Horde,
+                "k7/5p2/4p2P/3p2P1/2p2P2/1p2P2P/p2P2P1/2P2P2 w - - 0 1",
+            ),
+            &[13, 172, 2205],
+        );
+    }
+
+    #[test]
+    fn perft_crazyhouse() {
+        let fens = [
+            (
+                "2k5/8/8/8/8/8/8/4K3[QRBNPqrbnp] w - - 0 1",
+                &[301, 75353][..],
+            ),
+            ("2k5/8/8/8/8/8/8/4K3[Qn] w - - 0 1", &[67, 3083, 88634]),
+            (
+                "r1bqk2r/pppp1ppp/2n1p3/4P3/1b1Pn3/2NB1N2/PPP2PPP/R1BQK2R[] b KQkq - 0 1",
+                &[42, 1347],
+            ),
+            // A promoted queen goes back into the pocket as a pawn
+            ("4k3/1Q~6/8/8/4b3/8/Kpp5/8[] b - - 0 1", &[20, 360, 5445]),
+        ];
+        for &(fen, counts) in fens.iter() {
+            assert_perft(&position(VariantKind::Crazyhouse, fen), counts);
+        }
+    }
+
+    #[test]
+    fn king_of_the_hill() {
+        let mut position = position(VariantKind::KingOfTheHill, "4k3/8/8/8/8/4K3/8/8 w - - 0 1");
+        assert_eq!(position.variant_outcome(&position.legal_moves()), None);
+        let to_centre = parse_move(&position, "Kd4").unwrap();
+        position.play(to_centre);
+        assert_eq!(
+            position.variant_outcome(&position.legal_moves()),
+            Some(GameStatus::VariantWin {
+                winner: PieceColor::White,
+                reason: "reaching the centre",
+            })
+        );
+    }
+}
@@ -0,0 +1,387 @@
+use crate::{board::*, database::*, keyboard::*, notation::*, pieces::*, position::*, theme::*};
+use bevy::{
+    prelude::*,
+    render::texture::{Extent3d, TextureDimension, TextureFormat},
+};
+
+/// Which camera the board is drawn with. Both views share the same game state
+#[derive(Clone, Copy, PartialEq)]
+pub enum ViewMode {
+    ThreeD,
+    TwoD,
+}
+impl Default for ViewMode {
+    fn default() -> Self {
+        ViewMode::ThreeD
+    }
+}
+
+/// Marks everything that is only drawn in the 2D view
+struct Board2d;
+
+/// The flat stand-in for a piece, following the 3D piece entity around
+struct PieceSprite {
+    piece: Entity,
+    piece_type: PieceType,
+}
+
+/// Size of a square in the 2D view, in pixels
+const SQUARE_SIZE: f32 = 120.;
+/// Resolution of the generated piece disc texture
+const DISC_TEXTURE_SIZE: u32 = 64;
+
+struct PieceSpriteMaterials {
+    white: Handle<ColorMaterial>,
+    black: Handle<ColorMaterial>,
+    font: Handle<Font>,
+}
+impl PieceSpriteMaterials {
+    fn get(&self, color: PieceColor) -> Handle<ColorMaterial> {
+        match color {
+            PieceColor::White => self.white.clone(),
+            PieceColor::Black => self.black.clone(),
+        }
+    }
+}
+
+pub struct View2dPlugin;
+impl Plugin for View2dPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<ViewMode>()
+            .add_startup_system(create_board_2d.system())
+            .add_system(create_squares_2d.system())
+            .add_system(switch_view.system())
+            .add_system_to_stage(CoreStage::PreUpdate, pick_square_2d.system())
+            .add_system(color_square_sprites.system())
+            .add_system(color_piece_sprites.system())
+            .add_system(spawn_piece_sprites.system())
+            .add_system_to_stage(CoreStage::PostUpdate, sync_piece_sprites.system())
+            .add_system_to_stage(CoreStage::PostUpdate, show_active_view.system());
+    }
+}
+
+/// Screen position of the center of a square, given in board coordinates. The board is
+/// centered on the screen whatever its size
+fn board_to_screen(position: &Position, rank: f32, file: f32) -> Vec2 {
+    let (center_rank, center_file) = board_center(position);
+    Vec2::new(
+        (file - center_file) * SQUARE_SIZE,
+        (rank - center_rank) * SQUARE_SIZE,
+    )
+}
+
+fn board_center(position: &Position) -> (f32, f32) {
+    (
+        (position.ranks() as f32 - 1.) / 2.,
+        (position.files() as f32 - 1.) / 2.,
+    )
+}
+
+/// A white disc with a darker rim, tinted per color by its material
+fn disc_texture() -> Texture {
+    let size = DISC_TEXTURE_SIZE;
+    let radius = size as f32 / 2.;
+    let mut data = Vec::with_capacity((size * size * 4) as usize);
+    for y in 0..size {
+        for x in 0..size {
+            let dx = x as f32 + 0.5 - radius;
+            let dy = y as f32 + 0.5 - radius;
+            let distance = (dx * dx + dy * dy).sqrt() / radius;
+            // Soften the last pixel of the edge so the disc doesn't look jagged
+            let alpha = ((1. - distance) * radius).max(0.).min(1.);
+            let shade = if distance > 0.85 { 0.6 } else { 1. };
+            let shade = (shade * 255.) as u8;
+            data.extend_from_slice(&[shade, shade, shade, (alpha * 255.) as u8]);
+        }
+    }
+    Texture::new(
+        Extent3d::new(size, size, 1),
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+    )
+}
+
+fn create_board_2d(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    themes: Res<Themes>,
+    mut textures: ResMut<Assets<Texture>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    let theme = themes.current();
+
+    commands.spawn_bundle(OrthographicCameraBundle::new_2d());
+
+    let disc = textures.add(disc_texture());
+    commands.insert_resource(PieceSpriteMaterials {
+        white: materials.add(ColorMaterial::modulated_texture(
+            disc.clone(),
+            rgb(theme.white_pieces.color),
+        )),
+        black: materials.add(ColorMaterial::modulated_texture(
+            disc,
+            rgb(theme.black_pieces.color),
+        )),
+        font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+    });
+}
+
+/// Lays out the square sprites, and again whenever the board changes size, just like
+/// the 3D squares
+fn create_squares_2d(
+    mut commands: Commands,
+    position: Res<Position>,
+    themes: Res<Themes>,
+    mut board_size: Local<(u8, u8)>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    squares_query: Query<Entity, (With<Square>, With<Board2d>)>,
+) {
+    if *board_size == (position.ranks(), position.files()) {
+        return;
+    }
+    *board_size = (position.ranks(), position.files());
+    for entity in squares_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+
+    let theme = themes.current();
+    for x in 0..position.ranks() {
+        for y in 0..position.files() {
+            let square = Square { x, y };
+            commands
+                .spawn_bundle(SpriteBundle {
+                    sprite: Sprite::new(Vec2::splat(SQUARE_SIZE)),
+                    material: materials.add(theme.square_color(square.is_white()).into()),
+                    transform: Transform::from_translation(
+                        board_to_screen(&position, x as f32, y as f32).extend(0.),
+                    ),
+                    visible: Visible {
+                        is_visible: false,
+                        is_transparent: false,
+                    },
+                    ..Default::default()
+                })
+                .insert(square)
+                .insert(Board2d);
+        }
+    }
+}
+
+fn switch_view(
+    keyboard_inputs: Res<Input<KeyCode>>,
+    browser: Res<DatabaseBrowser>,
+    mut view_mode: ResMut<ViewMode>,
+) {
+    if keyboard_inputs.just_pressed(KeyCode::V) && !browser.open {
+        *view_mode = match *view_mode {
+            ViewMode::ThreeD => ViewMode::TwoD,
+            ViewMode::TwoD => ViewMode::ThreeD,
+        };
+    }
+}
+
+/// Fills in `BoardCursor` from the mouse position over the flat board
+fn pick_square_2d(
+    view_mode: Res<ViewMode>,
+    windows: Res<Windows>,
+    position: Res<Position>,
+    mut board_cursor: ResMut<BoardCursor>,
+) {
+    if *view_mode != ViewMode::TwoD {
+        return;
+    }
+
+    let window = match windows.get_primary() {
+        Some(window) => window,
+        None => return,
+    };
+    let cursor = match window.cursor_position() {
+        Some(cursor) => cursor,
+        None => {
+            *board_cursor = BoardCursor::default();
+            return;
+        }
+    };
+
+    // The 2D camera is centered on the board, while window coordinates start bottom-left
+    let world = cursor - Vec2::new(window.width(), window.height()) / 2.;
+    let (center_rank, center_file) = board_center(&position);
+    let rank = world.y / SQUARE_SIZE + center_rank;
+    let file = world.x / SQUARE_SIZE + center_file;
+
+    board_cursor.position = Some(Vec3::new(rank, 0., file));
+    let on_board = |coordinate: f32, size: u8| (-0.5..size as f32 - 0.5).contains(&coordinate);
+    board_cursor.square = if on_board(rank, position.ranks()) && on_board(file, position.files()) {
+        Some((rank.round() as u8, file.round() as u8))
+    } else {
+        None
+    };
+}
+
+fn color_square_sprites(
+    selected_square: Res<SelectedSquare>,
+    board_cursor: Res<BoardCursor>,
+    keyboard_cursor: Res<KeyboardCursor>,
+    themes: Res<Themes>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    query: Query<(&Square, &Handle<ColorMaterial>)>,
+) {
+    let theme = themes.current();
+
+    for (square, material_handle) in query.iter() {
+        let color =
+            theme.square_highlight(square, &board_cursor, &keyboard_cursor, &selected_square);
+
+        if materials
+            .get(material_handle)
+            .map(|material| material.color)
+            != Some(color)
+        {
+            if let Some(material) = materials.get_mut(material_handle) {
+                material.color = color;
+            }
+        }
+    }
+}
+
+fn color_piece_sprites(
+    themes: Res<Themes>,
+    piece_sprite_materials: Option<Res<PieceSpriteMaterials>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    if !themes.is_changed() {
+        return;
+    }
+    let piece_sprite_materials = match piece_sprite_materials {
+        Some(piece_sprite_materials) => piece_sprite_materials,
+        None => return,
+    };
+
+    for &color in [PieceColor::White, PieceColor::Black].iter() {
+        if let Some(material) = materials.get_mut(piece_sprite_materials.get(color)) {
+            material.color = rgb(themes.current().piece_style(color).color);
+        }
+    }
+}
+
+fn spawn_piece_sprites(
+    mut commands: Commands,
+    view_mode: Res<ViewMode>,
+    position: Res<Position>,
+    piece_sprite_materials: Option<Res<PieceSpriteMaterials>>,
+    pieces_query: Query<(Entity, &Piece), Added<Piece>>,
+) {
+    let piece_sprite_materials = match piece_sprite_materials {
+        Some(piece_sprite_materials) => piece_sprite_materials,
+        None => return,
+    };
+    let is_visible = *view_mode == ViewMode::TwoD;
+
+    for (entity, piece) in pieces_query.iter() {
+        // The letter is drawn in the other side's color so it reads on the disc
+        let letter_color = match piece.color {
+            PieceColor::White => Color::BLACK,
+            PieceColor::Black => Color::WHITE,
+        };
+
+        commands
+            .spawn_bundle(SpriteBundle {
+                sprite: Sprite::new(Vec2::splat(SQUARE_SIZE * 0.8)),
+                material: piece_sprite_materials.get(piece.color),
+                transform: Transform::from_translation(
+                    board_to_screen(&position, piece.x as f32, piece.y as f32).extend(1.),
+                ),
+                visible: Visible {
+                    is_visible,
+                    is_transparent: true,
+                },
+                ..Default::default()
+            })
+            .insert(PieceSprite {
+                piece: entity,
+                piece_type: piece.piece_type,
+            })
+            .insert(Board2d)
+            .with_children(|parent| {
+                parent
+                    .spawn_bundle(Text2dBundle {
+                        text: Text::with_section(
+                            piece_letter(piece.piece_type).to_string(),
+                            TextStyle {
+                                font: piece_sprite_materials.font.clone(),
+                                font_size: SQUARE_SIZE * 0.5,
+                                color: letter_color,
+                            },
+                            TextAlignment {
+                                vertical: VerticalAlign::Center,
+                                horizontal: HorizontalAlign::Center,
+                            },
+                        ),
+                        transform: Transform::from_translation(Vec3::new(0., 0., 0.1)),
+                        visible: Visible {
+                            is_visible,
+                            is_transparent: true,
+                        },
+                        ..Default::default()
+                    })
+                    .insert(Board2d);
+            });
+    }
+}
+
+/// Mirrors the 3D pieces, so moves, drags, promotions and captures show in both views
+fn sync_piece_sprites(
+    mut commands: Commands,
+    position: Res<Position>,
+    mut sprites_query: Query<(Entity, &mut PieceSprite, &mut Transform, &Children)>,
+    pieces_query: Query<(&Transform, Option<&Piece>), Without<PieceSprite>>,
+    mut letters_query: Query<&mut Text>,
+) {
+    for (sprite_entity, mut piece_sprite, mut transform, children) in sprites_query.iter_mut() {
+        let (piece_transform, piece) = match pieces_query.get(piece_sprite.piece) {
+            Ok(piece) => piece,
+            Err(_) => {
+                commands.entity(sprite_entity).despawn_recursive();
+                continue;
+            }
+        };
+
+        let translation = piece_transform.translation;
+        // Lifted pieces are drawn above the others
+        transform.translation =
+            board_to_screen(&position, translation.x, translation.z).extend(1. + translation.y);
+        transform.scale = piece_transform.scale;
+
+        if let Some(piece) = piece {
+            if piece.piece_type != piece_sprite.piece_type {
+                piece_sprite.piece_type = piece.piece_type;
+                for &child in children.iter() {
+                    if let Ok(mut text) = letters_query.get_mut(child) {
+                        text.sections[0].value = piece_letter(piece.piece_type).to_string();
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Hides whatever belongs to the inactive view
+fn show_active_view(
+    view_mode: Res<ViewMode>,
+    mut visible_3d_query: Query<&mut Visible, (With<Handle<StandardMaterial>>, Without<Board2d>)>,
+    mut visible_2d_query: Query<&mut Visible, With<Board2d>>,
+) {
+    let is_2d = *view_mode == ViewMode::TwoD;
+
+    for mut visible in visible_3d_query.iter_mut() {
+        if visible.is_visible == is_2d {
+            visible.is_visible = !is_2d;
+        }
+    }
+    for mut visible in visible_2d_query.iter_mut() {
+        if visible.is_visible != is_2d {
+            visible.is_visible = is_2d;
+        }
+    }
+}